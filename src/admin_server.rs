@@ -0,0 +1,60 @@
+use crate::logging_config::{parse_level, LoggingController};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::info;
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::error::Error;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+pub const LOG_LEVELS_PATH: &str = "/admin/log-levels";
+
+pub(crate) fn json_response(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder().status(status).header("Content-Type", "application/json").body(Body::from(body.to_string())).unwrap()
+}
+
+pub(crate) fn error_response(status: StatusCode, message: impl std::fmt::Display) -> Response<Body> {
+    json_response(status, json!({"error": message.to_string()}))
+}
+
+/// `PUT` body: `{"module": "reqwest", "level": "warn"}`, or without `module` for the root level.
+async fn set_log_level(request: Request<Body>, logging: &Mutex<LoggingController>) -> Result<Value, Box<dyn Error>> {
+    let body: Value = serde_json::from_slice(&hyper::body::to_bytes(request.into_body()).await?).map_err(|err| format!("Invalid JSON body: {}", err))?;
+    let level = parse_level(body["level"].as_str().ok_or("The body needs a \"level\"")?)?;
+    let module = body["module"].as_str();
+    let mut logging = logging.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    logging.set_level(module, level)?;
+    info!("Log level of {} set to {} through the admin endpoint", module.unwrap_or("the root logger"), level);
+    logging.levels()
+}
+
+async fn handle(request: Request<Body>, logging: Arc<Mutex<LoggingController>>) -> Result<Response<Body>, Infallible> {
+    if request.uri().path() != LOG_LEVELS_PATH {
+        return Ok(error_response(StatusCode::NOT_FOUND, "Not found"));
+    }
+    let response = match *request.method() {
+        Method::GET => match logging.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).levels() {
+            Ok(levels) => json_response(StatusCode::OK, levels),
+            Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+        },
+        Method::PUT | Method::POST => match set_log_level(request, &logging).await {
+            Ok(levels) => json_response(StatusCode::OK, levels),
+            Err(err) => error_response(StatusCode::BAD_REQUEST, err),
+        },
+        _ => error_response(StatusCode::METHOD_NOT_ALLOWED, "Use GET or PUT"),
+    };
+    Ok(response)
+}
+
+/// Binds the admin HTTP endpoint, which reads (`GET`) and changes (`PUT`) log levels at
+/// `/admin/log-levels`. Returns the bound address and the server future to spawn.
+pub fn bind(addr: SocketAddr, logging: Arc<Mutex<LoggingController>>) -> Result<(SocketAddr, impl Future<Output = Result<(), hyper::Error>>), Box<dyn Error>> {
+    let make_service = make_service_fn(move |_| {
+        let logging = logging.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| handle(request, logging.clone()))) }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    Ok((server.local_addr(), server))
+}
@@ -0,0 +1,329 @@
+use crate::admin_server::{error_response, json_response};
+use crate::data_archiver::{message_method, message_slot};
+use crate::volume_catalog::{CatalogVolume, VolumeCatalog};
+use crate::volume_extractor::local_path;
+use chrono::{DateTime, Utc};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub const VOLUMES_PATH: &str = "/volumes";
+pub const RECORDS_PATH: &str = "/records";
+pub const BLOCKS_PATH: &str = "/blocks/"; // Followed by the slot
+pub const NEXT_CURSOR_HEADER: &str = "X-Next-Cursor";
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+pub const MAX_PAGE_SIZE: usize = 1_000;
+pub const MAX_SCAN_BYTES_PER_PAGE: usize = 256 * 1024 * 1024; // Uncompressed volume data one /records page reads at most
+pub const MAX_SLOT: u64 = i64::MAX as u64; // SQLite integers are signed
+
+/// Which volumes a query looks at.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VolumeRange {
+    All,
+    /// Volumes that may hold slots `first_slot..=last_slot`; records are also filtered by slot.
+    Slots { first_slot: u64, last_slot: u64 },
+    /// Volumes whose time bucket overlaps `start..end`.
+    Time { start: DateTime<Utc>, end: DateTime<Utc> },
+}
+
+/// Record filters on top of the volume range. Program and account filters match the address as it appears
+/// in the message text, so they find JSON-encoded transactions, logs and program account updates, but not
+/// the accounts inside base64-encoded transactions.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecordFilter {
+    pub method: Option<String>,
+    pub program: Option<String>,
+    pub account: Option<String>,
+}
+
+impl RecordFilter {
+    pub fn matches(&self, message: &str) -> bool {
+        if self.method.as_deref().is_some_and(|method| message_method(message) != Some(method)) {
+            return false;
+        }
+        if let Some(program) = &self.program {
+            let owned = message.contains(&format!("\"owner\":\"{}\"", program)) || message.contains(&format!("\"programId\":\"{}\"", program));
+            if !owned && !message.contains(&format!("Program {} invoke", program)) {
+                return false;
+            }
+        }
+        self.account.as_ref().is_none_or(|account| message.contains(&format!("\"{}\"", account)))
+    }
+}
+
+/// At most `limit` results after `cursor`, the opaque value returned with the previous page. A records
+/// page also stops once it has read `max_scan_bytes` of volume data past the cursor, so a sparse filter
+/// over a large range comes back as several short (possibly empty) pages rather than one long scan.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Page {
+    pub limit: usize,
+    pub cursor: Option<String>,
+    pub max_scan_bytes: usize,
+}
+
+fn range_volumes(catalog: &VolumeCatalog, range: &VolumeRange) -> Result<Vec<CatalogVolume>, Box<dyn Error>> {
+    match range {
+        VolumeRange::All => catalog.all_volumes(),
+        VolumeRange::Slots { first_slot, last_slot } => catalog.volumes_for_slots(*first_slot, *last_slot),
+        VolumeRange::Time { start, end } => catalog.volumes_for_time_range(*start, *end),
+    }
+}
+
+/// A volume's place in archive order, as carried in cursors: `<bucket start time>,<volume number>,<file name>`,
+/// with unknown fields left empty.
+type CursorKey = (Option<String>, Option<u64>, String);
+
+fn volume_cursor(volume: &CatalogVolume) -> String {
+    let (bucket_start_time, volume_number, file_name) = volume.order_key();
+    format!("{},{},{}", bucket_start_time.unwrap_or_default(), volume_number.map(|number| number.to_string()).unwrap_or_default(), file_name)
+}
+
+fn parse_volume_cursor(cursor: &str) -> Result<CursorKey, String> {
+    let invalid = || format!("Invalid cursor: {}", cursor);
+    let mut fields = cursor.splitn(3, ',');
+    let (bucket_start_time, volume_number, file_name) = match (fields.next(), fields.next(), fields.next()) {
+        (Some(bucket_start_time), Some(volume_number), Some(file_name)) => (bucket_start_time, volume_number, file_name),
+        _ => return Err(invalid()),
+    };
+    let volume_number = if volume_number.is_empty() { None } else { Some(volume_number.parse().map_err(|_| invalid())?) };
+    Ok(((!bucket_start_time.is_empty()).then(|| bucket_start_time.to_string()), volume_number, file_name.to_string()))
+}
+
+fn is_after(volume: &CatalogVolume, key: &CursorKey) -> bool {
+    volume.order_key() > (key.0.as_deref(), key.1, key.2.as_str())
+}
+
+/// A page of cataloged volumes in the range, in archive order, and the cursor for the next page if there
+/// may be one. The cursor holds the archive order key of the last volume returned.
+pub fn query_volumes(archive_dir: &Path, range: &VolumeRange, page: &Page) -> Result<(Vec<CatalogVolume>, Option<String>), Box<dyn Error>> {
+    let after = page.cursor.as_deref().map(parse_volume_cursor).transpose()?;
+    let catalog = VolumeCatalog::open_in(archive_dir)?;
+    if after.is_none() {
+        catalog.sync_directory(archive_dir)?; // Later pages go on from the catalog, which the encoder keeps current
+    }
+    let mut volumes: Vec<CatalogVolume> = range_volumes(&catalog, range)?.into_iter().filter(|volume| after.as_ref().is_none_or(|after| is_after(volume, after))).collect();
+    let more = volumes.len() > page.limit;
+    volumes.truncate(page.limit);
+    let cursor = if more { volumes.last().map(volume_cursor) } else { None };
+    Ok((volumes, cursor))
+}
+
+fn record_cursor(cursor: &str) -> Result<(CursorKey, usize), String> {
+    let (volume, index) = cursor.rsplit_once(':').ok_or_else(|| format!("Invalid cursor: {}", cursor))?;
+    Ok((parse_volume_cursor(volume)?, index.parse().map_err(|_| format!("Invalid cursor: {}", cursor))?))
+}
+
+/// A page of archived messages in the range that pass `filter`, as compact JSON, and the cursor for the
+/// next page if there may be more. The cursor is `<volume cursor>:<index of the next message in that volume>`.
+/// Volumes are decompressed as a stream, and only as far as the page needs. Volumes evicted from local disk
+/// are skipped.
+pub fn query_records(archive_dir: &Path, range: &VolumeRange, filter: &RecordFilter, page: &Page) -> Result<(Vec<String>, Option<String>), Box<dyn Error>> {
+    let (cursor_volume, cursor_index) = match &page.cursor {
+        Some(cursor) => record_cursor(cursor).map(|(volume, index)| (Some(volume), index))?,
+        None => (None, 0),
+    };
+    let catalog = VolumeCatalog::open_in(archive_dir)?;
+    if cursor_volume.is_none() {
+        catalog.sync_directory(archive_dir)?; // Later pages go on from the catalog, which the encoder keeps current
+    }
+    let mut records = Vec::new();
+    let mut scanned = 0;
+    for volume in range_volumes(&catalog, range)? {
+        let skip = match &cursor_volume {
+            Some(cursor_volume) if volume.file_name == cursor_volume.2 => cursor_index,
+            Some(cursor_volume) if !is_after(&volume, cursor_volume) => continue,
+            _ => 0,
+        };
+        let Some(path) = local_path(archive_dir, &volume) else { continue };
+        let decoder = BufReader::new(zstd::stream::read::Decoder::new(File::open(&path)?)?);
+        let mut messages = serde_json::Deserializer::from_reader(decoder).into_iter::<Value>();
+        let mut scan_start = None; // Where the cursor left off; messages before it do not count towards the budget
+        let mut index = 0;
+        loop {
+            let offset = messages.byte_offset();
+            let Some(message) = messages.next() else {
+                scanned += offset - scan_start.unwrap_or(offset);
+                break;
+            };
+            index += 1;
+            if index <= skip {
+                continue;
+            }
+            let scan_start = *scan_start.get_or_insert(offset);
+            if records.len() == page.limit || scanned + offset - scan_start > page.max_scan_bytes {
+                return Ok((records, Some(format!("{}:{}", volume_cursor(&volume), index - 1))));
+            }
+            let text = message.map_err(|err| format!("Malformed message in {}: {}", path.display(), err))?.to_string();
+            if let VolumeRange::Slots { first_slot, last_slot } = range {
+                match message_slot(&text) {
+                    Some(slot) if slot >= *first_slot && slot <= *last_slot => {}
+                    _ => continue,
+                }
+            }
+            if filter.matches(&text) {
+                records.push(text);
+            }
+        }
+    }
+    Ok((records, None))
+}
+
+/// Calls `visit` with the slot and block of every `blockNotification` in the volume at `path`, in archive
+/// order, decoding one message at a time. Stops early when `visit` returns false.
+pub fn for_each_volume_block(path: &Path, mut visit: impl FnMut(u64, &mut Value) -> Result<bool, Box<dyn Error>>) -> Result<(), Box<dyn Error>> {
+    let decoder = BufReader::new(zstd::stream::read::Decoder::new(File::open(path)?)?);
+    for message in serde_json::Deserializer::from_reader(decoder).into_iter::<Value>() {
+        let mut message = message.map_err(|err| format!("Malformed message in {}: {}", path.display(), err))?;
+        if message["method"] != "blockNotification" {
+            continue;
+        }
+        let value = &mut message["params"]["result"]["value"];
+        let Some(slot) = value["slot"].as_u64() else { continue };
+        if value["block"].is_object() && !visit(slot, &mut value["block"])? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// The block for `slot` from the volume at `path`, if that volume captured it.
+pub fn volume_block(path: &Path, slot: u64) -> Result<Option<Value>, Box<dyn Error>> {
+    let mut found = None;
+    for_each_volume_block(path, |block_slot, block| {
+        if block_slot == slot {
+            found = Some(block.take());
+        }
+        Ok(found.is_none())
+    })?;
+    Ok(found)
+}
+
+/// The archived block for `slot`, from the first `blockNotification` that captured it.
+pub fn archived_block(archive_dir: &Path, slot: u64) -> Result<Option<Value>, Box<dyn Error>> {
+    let catalog = VolumeCatalog::open_in(archive_dir)?;
+    catalog.sync_directory(archive_dir)?;
+    for volume in catalog.volumes_for_slots(slot, slot)? {
+        let Some(path) = local_path(archive_dir, &volume) else { continue };
+        if let Some(block) = volume_block(&path, slot)? {
+            return Ok(Some(block));
+        }
+    }
+    Ok(None)
+}
+
+fn parse_param<T: std::str::FromStr>(params: &HashMap<String, String>, name: &str) -> Result<Option<T>, String> {
+    match params.get(name) {
+        Some(value) => value.parse().map(Some).map_err(|_| format!("Invalid value for {}: {}", name, value)),
+        None => Ok(None),
+    }
+}
+
+/// `start_slot`/`end_slot` (either may be left out), or `start_time` and `end_time` as RFC 3339.
+fn parse_range(params: &HashMap<String, String>) -> Result<VolumeRange, String> {
+    match (parse_param::<u64>(params, "start_slot")?, parse_param::<u64>(params, "end_slot")?) {
+        (None, None) => {}
+        (first_slot, last_slot) => {
+            let (first_slot, last_slot) = (first_slot.unwrap_or(0), last_slot.unwrap_or(MAX_SLOT).min(MAX_SLOT));
+            return if first_slot <= last_slot { Ok(VolumeRange::Slots { first_slot, last_slot }) } else { Err("start_slot must not be after end_slot".to_string()) };
+        }
+    }
+    match (parse_param::<DateTime<Utc>>(params, "start_time")?, parse_param::<DateTime<Utc>>(params, "end_time")?) {
+        (None, None) => Ok(VolumeRange::All),
+        (Some(start), Some(end)) if start < end => Ok(VolumeRange::Time { start, end }),
+        (Some(_), Some(_)) => Err("start_time must be before end_time".to_string()),
+        _ => Err("start_time and end_time must be given together".to_string()),
+    }
+}
+
+fn parse_page(params: &HashMap<String, String>) -> Result<Page, String> {
+    let limit = parse_param(params, "limit")?.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(format!("limit must be between 1 and {}", MAX_PAGE_SIZE));
+    }
+    Ok(Page { limit, cursor: params.get("cursor").cloned(), max_scan_bytes: MAX_SCAN_BYTES_PER_PAGE })
+}
+
+fn ndjson_response(lines: Vec<String>, cursor: Option<String>) -> Response<Body> {
+    let mut body = String::new();
+    for line in lines {
+        body.push_str(&line);
+        body.push('\n');
+    }
+    let mut response = Response::builder().header("Content-Type", "application/x-ndjson");
+    if let Some(cursor) = cursor {
+        response = response.header(NEXT_CURSOR_HEADER, cursor);
+    }
+    response.body(Body::from(body)).unwrap()
+}
+
+/// Runs a catalog and volume query off the async runtime, since both block on disk.
+async fn run_query<T: Send + 'static>(query: impl FnOnce() -> Result<T, Box<dyn Error>> + Send + 'static) -> Result<T, String> {
+    match tokio::task::spawn_blocking(move || query().map_err(|err| err.to_string())).await {
+        Ok(result) => result,
+        Err(err) => Err(format!("Query failed: {}", err)),
+    }
+}
+
+async fn handle(request: Request<Body>, archive_dir: Arc<PathBuf>) -> Result<Response<Body>, Infallible> {
+    if request.method() != Method::GET {
+        return Ok(error_response(StatusCode::METHOD_NOT_ALLOWED, "Use GET"));
+    }
+    let params: HashMap<String, String> = form_urlencoded::parse(request.uri().query().unwrap_or_default().as_bytes()).into_owned().collect();
+    let path = request.uri().path().to_string();
+    let response = match path.as_str() {
+        VOLUMES_PATH => match parse_range(&params).and_then(|range| Ok((range, parse_page(&params)?))).and_then(|(range, page)| {
+            page.cursor.as_deref().map(parse_volume_cursor).transpose()?;
+            Ok((range, page))
+        }) {
+            Ok((range, page)) => match run_query(move || query_volumes(&archive_dir, &range, &page)).await {
+                Ok((volumes, cursor)) => ndjson_response(volumes.iter().map(|volume| volume.to_json().to_string()).collect(), cursor),
+                Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+            },
+            Err(err) => error_response(StatusCode::BAD_REQUEST, err),
+        },
+        RECORDS_PATH => match parse_range(&params).and_then(|range| Ok((range, parse_page(&params)?))).and_then(|(range, page)| {
+            page.cursor.as_deref().map(record_cursor).transpose()?;
+            Ok((range, page))
+        }) {
+            Ok((range, page)) => {
+                let filter = RecordFilter { method: params.get("method").cloned(), program: params.get("program").cloned(), account: params.get("account").cloned() };
+                match run_query(move || query_records(&archive_dir, &range, &filter, &page)).await {
+                    Ok((records, cursor)) => ndjson_response(records, cursor),
+                    Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+                }
+            }
+            Err(err) => error_response(StatusCode::BAD_REQUEST, err),
+        },
+        _ => match path.strip_prefix(BLOCKS_PATH).map(str::parse::<u64>) {
+            Some(Ok(slot)) => match run_query(move || archived_block(&archive_dir, slot)).await {
+                Ok(Some(block)) => json_response(StatusCode::OK, block),
+                Ok(None) => error_response(StatusCode::NOT_FOUND, format!("Slot {} is not in the archive", slot)),
+                Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+            },
+            Some(Err(_)) => error_response(StatusCode::BAD_REQUEST, "The slot must be a number"),
+            None => error_response(StatusCode::NOT_FOUND, "Not found"),
+        },
+    };
+    Ok(response)
+}
+
+/// Binds the archive query API over the volumes cataloged in `archive_dir`: `GET /volumes`, `GET /records`
+/// and `GET /blocks/<slot>`. Returns the bound address and the server future to spawn.
+pub fn bind(addr: SocketAddr, archive_dir: PathBuf) -> Result<(SocketAddr, impl Future<Output = Result<(), hyper::Error>>), Box<dyn Error>> {
+    let archive_dir = Arc::new(archive_dir);
+    let make_service = make_service_fn(move |_| {
+        let archive_dir = archive_dir.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| handle(request, archive_dir.clone()))) }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    Ok((server.local_addr(), server))
+}
@@ -0,0 +1,287 @@
+use crate::archive_query_server::{for_each_volume_block, MAX_SLOT};
+use crate::data_archiver::{message_method, message_slot};
+use crate::volume_catalog::{CatalogVolume, VolumeCatalog};
+use crate::volume_extractor::local_path;
+use chrono::{DateTime, Utc};
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::Message;
+
+pub const DEFAULT_REPLAY_LISTEN_ADDR: &str = "127.0.0.1:8900"; // The port a Solana validator serves PubSub on
+const SLOT_DURATION: Duration = Duration::from_millis(400); // Nominal Solana slot time, which paces the replay
+const REPLAY_SETTLE_DELAY: Duration = Duration::from_millis(250); // Lets subscriptions sent together all see the replay from its start
+const REPLAY_QUEUE_SIZE: usize = 1_024; // Messages read ahead of the connection
+const REPLAY_WINDOW_DAYS: i64 = 365 * 100; // "Until the end of the archive" for a replay that starts at a time
+
+/// Where in the archive each connection's replay starts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplayStart {
+    Beginning,
+    /// The first message for this slot or a later one.
+    Slot(u64),
+    /// The first archived block with a block time at or after this time, or the start of the time bucket
+    /// holding it if no archived block has a block time.
+    Time(DateTime<Utc>),
+}
+
+/// How fast archived slots are replayed, relative to the 400ms slot time of the cluster they came from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplayPace {
+    RealTime,
+    /// This many times faster than real time.
+    Accelerated(f64),
+    AsFastAsPossible,
+}
+
+impl FromStr for ReplayPace {
+    type Err = String;
+
+    /// `realtime`, `max`, or a speed-up factor such as `10` or `10x`.
+    fn from_str(pace: &str) -> Result<Self, Self::Err> {
+        match pace {
+            "realtime" => Ok(Self::RealTime),
+            "max" => Ok(Self::AsFastAsPossible),
+            factor => match factor.trim_end_matches('x').parse::<f64>() {
+                Ok(factor) if factor > 0.0 && factor.is_finite() => Ok(Self::Accelerated(factor)),
+                _ => Err(format!("Invalid replay pace {}; use realtime, max or a factor such as 10x", pace)),
+            },
+        }
+    }
+}
+
+impl ReplayPace {
+    /// How long after the first replayed slot a message for `slot` is due.
+    fn offset(&self, first_slot: u64, slot: u64) -> Option<Duration> {
+        let elapsed = SLOT_DURATION * u32::try_from(slot.saturating_sub(first_slot)).unwrap_or(u32::MAX);
+        match self {
+            Self::RealTime => Some(elapsed),
+            Self::Accelerated(factor) => Some(elapsed.div_f64(*factor)),
+            Self::AsFastAsPossible => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReplayOptions {
+    pub start: ReplayStart,
+    pub pace: ReplayPace,
+}
+
+/// The notification method sent for a subscription method, e.g. `blockNotification` for `blockSubscribe`.
+pub fn notification_method(subscribe_method: &str) -> Option<String> {
+    subscribe_method.strip_suffix("Subscribe").map(|subscription| format!("{}Notification", subscription))
+}
+
+/// Volumes to replay from `start` onwards, in slot order.
+fn replay_volumes(archive_dir: &Path, start: ReplayStart) -> Result<Vec<CatalogVolume>, Box<dyn Error>> {
+    let catalog = VolumeCatalog::open_in(archive_dir)?;
+    catalog.sync_directory(archive_dir)?;
+    let mut volumes = match start {
+        ReplayStart::Beginning => catalog.all_volumes()?,
+        ReplayStart::Slot(slot) => catalog.volumes_for_slots(slot, MAX_SLOT)?,
+        ReplayStart::Time(time) => catalog.volumes_for_time_range(time, time + chrono::Duration::days(REPLAY_WINDOW_DAYS))?,
+    };
+    volumes.sort_by(|a, b| (a.first_slot, &a.file_name).cmp(&(b.first_slot, &b.file_name)));
+    Ok(volumes)
+}
+
+/// The slot a replay from `time` starts at: that of the first archived block with a block time at or
+/// after `time`. None if no block in `volumes` has one, in which case the whole first bucket is replayed.
+fn first_slot_at(archive_dir: &Path, volumes: &[CatalogVolume], time: DateTime<Utc>) -> Result<Option<u64>, Box<dyn Error>> {
+    let mut first_slot = None;
+    for volume in volumes {
+        let Some(path) = local_path(archive_dir, volume) else { continue };
+        for_each_volume_block(&path, |slot, block| {
+            if block["blockTime"].as_i64().is_some_and(|block_time| block_time >= time.timestamp()) {
+                first_slot = Some(slot);
+            }
+            Ok(first_slot.is_none())
+        })?;
+        if first_slot.is_some() {
+            break;
+        }
+    }
+    Ok(first_slot)
+}
+
+/// Sends the notifications archived in one volume, for `first_slot` onwards if given, into `tx` as they are
+/// decoded. Subscription acknowledgments, which are archived too, are left out. Returns false once the
+/// connection has gone away.
+fn send_volume_notifications(archive_dir: &Path, volume: &CatalogVolume, first_slot: Option<u64>, tx: &mpsc::Sender<String>) -> Result<bool, Box<dyn Error>> {
+    let Some(path) = local_path(archive_dir, volume) else { return Ok(true) };
+    let decoder = BufReader::new(zstd::stream::read::Decoder::new(File::open(&path)?)?);
+    for message in serde_json::Deserializer::from_reader(decoder).into_iter::<Value>() {
+        let text = message.map_err(|err| format!("Malformed message in {}: {}", path.display(), err))?.to_string();
+        if message_method(&text).is_none() {
+            continue;
+        }
+        if let Some(first_slot) = first_slot {
+            if message_slot(&text).is_none_or(|slot| slot < first_slot) {
+                continue;
+            }
+        }
+        if tx.blocking_send(text).is_err() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Reads the archive from `start` into `tx`, one message at a time, until it is exhausted or the
+/// connection goes away. The channel holds back reading while the connection is behind.
+fn replay_archive(archive_dir: &Path, start: ReplayStart, tx: &mpsc::Sender<String>) -> Result<(), Box<dyn Error>> {
+    let volumes = replay_volumes(archive_dir, start)?;
+    let first_slot = match start {
+        ReplayStart::Beginning => None,
+        ReplayStart::Slot(slot) => Some(slot),
+        ReplayStart::Time(time) => first_slot_at(archive_dir, &volumes, time)?,
+    };
+    for volume in &volumes {
+        match send_volume_notifications(archive_dir, volume, first_slot, tx) {
+            Ok(true) => {}
+            Ok(false) => break, // The connection closed
+            Err(err) => warn!("Skipping the rest of {} in the replay: {}", volume.file_name, err),
+        }
+    }
+    Ok(())
+}
+
+async fn read_archive(archive_dir: Arc<PathBuf>, start: ReplayStart, tx: mpsc::Sender<String>) {
+    tokio::time::sleep(REPLAY_SETTLE_DELAY).await;
+    // Decoding volumes blocks on disk, so the archive is read off the async runtime
+    let replayed = tokio::task::spawn_blocking(move || replay_archive(&archive_dir, start, &tx).map_err(|err| err.to_string())).await;
+    match replayed {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => warn!("Cannot replay the archive: {}", err),
+        Err(err) => warn!("Replay of the archive failed: {}", err),
+    }
+}
+
+/// Answers a subscribe or unsubscribe request. `subscriptions` maps each subscription id on the
+/// connection to the notification method it receives. Subscription parameters (filters, commitment,
+/// encoding) are accepted but not applied: every archived notification of the method is delivered.
+fn handle_request(request: &Value, subscriptions: &mut HashMap<u64, String>, next_subscription_id: &mut u64) -> Value {
+    let method = request["method"].as_str().unwrap_or_default();
+    if method.ends_with("Unsubscribe") {
+        let removed = request["params"][0].as_u64().and_then(|subscription_id| subscriptions.remove(&subscription_id)).is_some();
+        return json!({"jsonrpc": "2.0", "result": removed, "id": request["id"]});
+    }
+    match notification_method(method) {
+        Some(notification) => {
+            let subscription_id = *next_subscription_id;
+            *next_subscription_id += 1;
+            subscriptions.insert(subscription_id, notification);
+            json!({"jsonrpc": "2.0", "result": subscription_id, "id": request["id"]})
+        }
+        None => json!({"jsonrpc": "2.0", "error": {"code": -32601, "message": format!("Method not found: {}", method)}, "id": request["id"]}),
+    }
+}
+
+/// Plays the archive back to one client. The replay starts shortly after the client's first subscription and is
+/// paced by slot: a notification is sent once its slot is due, counting from the first replayed slot.
+async fn serve_connection(stream: TcpStream, archive_dir: Arc<PathBuf>, options: ReplayOptions) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (mut write, mut read) = tokio_tungstenite::accept_async(stream).await?.split();
+    let (tx, mut replay) = mpsc::channel(REPLAY_QUEUE_SIZE);
+    let mut tx = Some(tx); // Handed to the archive reader with the first subscription
+    let mut reader = None;
+    let mut subscriptions = HashMap::new();
+    let mut next_subscription_id = 0;
+    let mut origin: Option<(Instant, u64)> = None; // When the first replayed slot was sent, and that slot
+    let mut pending: Option<(Instant, String)> = None; // The next notification and when it is due
+    let mut replayed = 0;
+    loop {
+        let due = pending.as_ref().map(|(due, _)| *due);
+        tokio::select! {
+            biased; // Requests first, so a notification is never sent to a subscription that was just cancelled
+            request = read.next() => {
+                let request = match request {
+                    Some(Ok(Message::Text(request))) => request,
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Ok(_)) => continue,
+                    Some(Err(err)) => return Err(err.into()),
+                };
+                let response = match serde_json::from_str::<Value>(&request) {
+                    Ok(request) => handle_request(&request, &mut subscriptions, &mut next_subscription_id),
+                    Err(err) => json!({"jsonrpc": "2.0", "error": {"code": -32700, "message": format!("Parse error: {}", err)}, "id": null}),
+                };
+                write.send(Message::Text(response.to_string())).await?;
+                if !subscriptions.is_empty() {
+                    if let Some(tx) = tx.take() {
+                        reader = Some(tokio::spawn(read_archive(archive_dir.clone(), options.start, tx)));
+                    }
+                }
+            }
+            notification = replay.recv(), if reader.is_some() && pending.is_none() => {
+                let Some(notification) = notification else {
+                    info!("Replay finished after {} notifications", replayed);
+                    reader = None;
+                    continue;
+                };
+                let now = Instant::now();
+                let due = match message_slot(&notification) {
+                    Some(slot) => {
+                        let (started, first_slot) = *origin.get_or_insert((now, slot));
+                        options.pace.offset(first_slot, slot).map_or(now, |offset| started + offset)
+                    }
+                    None => now,
+                };
+                pending = Some((due, notification));
+            }
+            _ = tokio::time::sleep_until(due.unwrap_or_else(Instant::now)), if due.is_some() => {
+                let Some((_, notification)) = pending.take() else { continue };
+                let method = message_method(&notification).unwrap_or_default();
+                let mut message: Value = serde_json::from_str(&notification)?;
+                for (subscription_id, _) in subscriptions.iter().filter(|(_, notification_method)| *notification_method == method) {
+                    message["params"]["subscription"] = json!(subscription_id);
+                    write.send(Message::Text(message.to_string())).await?;
+                }
+                replayed += 1;
+            }
+        }
+    }
+    if let Some(reader) = reader {
+        reader.abort();
+    }
+    debug!("Replay connection closed after {} notifications", replayed);
+    Ok(())
+}
+
+/// Binds a PubSub WebSocket server that replays the volumes cataloged in `archive_dir` to each client that
+/// subscribes, speaking the protocol `SolanaConnector` consumes. Returns the bound address and the server
+/// future to spawn.
+pub async fn bind(addr: SocketAddr, archive_dir: PathBuf, options: ReplayOptions) -> Result<(SocketAddr, impl Future<Output = ()>), Box<dyn Error>> {
+    let listener = TcpListener::bind(addr).await?;
+    let local_addr = listener.local_addr()?;
+    let archive_dir = Arc::new(archive_dir);
+    let server = async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    info!("Replaying the archive to {}", peer);
+                    let archive_dir = archive_dir.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = serve_connection(stream, archive_dir, options).await {
+                            warn!("Replay connection from {} failed: {}", peer, err);
+                        }
+                    });
+                }
+                Err(err) => warn!("Error accepting a replay connection: {}", err),
+            }
+        }
+    };
+    Ok((local_addr, server))
+}
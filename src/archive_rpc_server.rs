@@ -0,0 +1,324 @@
+use crate::admin_server::{error_response, json_response};
+use crate::archive_query_server::{for_each_volume_block, volume_block, MAX_SLOT};
+use crate::archive_verifier::archived_block_signatures;
+use crate::old_faithful_car::{transaction_account_keys, transaction_signatures};
+use crate::volume_catalog::{IndexedBlock, IndexedTransaction, VolumeCatalog};
+use crate::volume_extractor::local_path;
+use base64::Engine;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{debug, warn};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::error::Error;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The read-only methods served from the archive, named as in `solana_rest_api_functions`.
+pub const RPC_METHODS: [&str; 6] = ["getBlock", "getBlockTime", "getBlocks", "getTransaction", "getSignaturesForAddress", "getSlot"];
+const MAX_BLOCKS_RANGE: u64 = 500_000; // Same limit as a Solana RPC node
+const MAX_SIGNATURES_LIMIT: usize = 1_000;
+const INDEX_INTERVAL: Duration = Duration::from_secs(10); // How often newly finalized volumes are added to the transaction index
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+const BLOCK_NOT_AVAILABLE: i64 = -32004;
+
+/// A JSON-RPC error, with the codes a Solana RPC node uses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn invalid_params(message: impl Into<String>) -> Self {
+        Self { code: INVALID_PARAMS, message: message.into() }
+    }
+}
+
+impl From<Box<dyn Error>> for RpcError {
+    fn from(err: Box<dyn Error>) -> Self {
+        Self { code: INTERNAL_ERROR, message: err.to_string() }
+    }
+}
+
+/// The bytes of a base64 or base58 encoded transaction; None for a JSON encoded one.
+fn transaction_data(transaction: &Value) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    match (transaction[0].as_str(), transaction[1].as_str()) {
+        (Some(data), Some("base64")) => Ok(Some(base64::engine::general_purpose::STANDARD.decode(data)?)),
+        (Some(data), Some("base58")) => Ok(Some(bs58::decode(data).into_vec()?)),
+        _ => Ok(None),
+    }
+}
+
+/// The first signature of a transaction in a block, which identifies it.
+fn transaction_signature(entry: &Value) -> Result<Option<String>, Box<dyn Error>> {
+    match transaction_data(&entry["transaction"])? {
+        Some(data) => Ok(transaction_signatures(&data)?.into_iter().next()),
+        None => Ok(entry["transaction"]["signatures"][0].as_str().map(String::from)),
+    }
+}
+
+/// Every account a transaction in a block references: its static account keys, and the addresses its
+/// metadata says were loaded from lookup tables.
+fn transaction_accounts(entry: &Value) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut accounts: Vec<String> = match transaction_data(&entry["transaction"])? {
+        Some(data) => transaction_account_keys(&data)?,
+        None => entry["transaction"]["message"]["accountKeys"].as_array().map(Vec::as_slice).unwrap_or_default().iter().filter_map(|key| key.as_str().or(key["pubkey"].as_str()).map(String::from)).collect(),
+    };
+    for loaded in ["writable", "readonly"] {
+        accounts.extend(entry["meta"]["loadedAddresses"][loaded].as_array().map(Vec::as_slice).unwrap_or_default().iter().filter_map(|address| address.as_str().map(String::from)));
+    }
+    Ok(accounts)
+}
+
+/// Re-encodes an archived transaction. Only the binary encodings can be produced from archived bytes, so
+/// asking for `json` or `jsonParsed` is an error unless the transaction was archived that way.
+fn encode_transaction(transaction: Value, encoding: Option<&str>) -> Result<Value, RpcError> {
+    let archived = transaction[1].as_str().unwrap_or("json");
+    let Some(encoding) = encoding.filter(|encoding| *encoding != archived) else { return Ok(transaction) };
+    match (encoding, transaction_data(&transaction)?) {
+        ("base64", Some(data)) => Ok(json!([base64::engine::general_purpose::STANDARD.encode(data), "base64"])),
+        ("base58", Some(data)) => Ok(json!([bs58::encode(data).into_string(), "base58"])),
+        _ => Err(RpcError::invalid_params(format!("Transactions are archived as {}; {} encoding is not available", archived, encoding))),
+    }
+}
+
+/// The `encoding` of a method's config object, or of the legacy form where the config is just the encoding.
+fn requested_encoding(config: &Value) -> Option<&str> {
+    config.as_str().or(config["encoding"].as_str())
+}
+
+/// Shapes an archived block as `getBlock` would return it for `config`.
+fn shape_block(mut block: Value, config: &Value) -> Result<Value, RpcError> {
+    let transaction_details = config["transactionDetails"].as_str().unwrap_or("full");
+    let signatures = match transaction_details {
+        "full" | "none" => None,
+        "signatures" => Some(archived_block_signatures(&block)?),
+        other => return Err(RpcError::invalid_params(format!("transactionDetails {} is not available from the archive", other))),
+    };
+    let Some(fields) = block.as_object_mut() else { return Ok(block) };
+    if config["rewards"] == false {
+        fields.remove("rewards");
+    }
+    if transaction_details == "full" {
+        if let Some(Value::Array(transactions)) = fields.get_mut("transactions") {
+            for entry in transactions.iter_mut() {
+                entry["transaction"] = encode_transaction(entry["transaction"].take(), requested_encoding(config))?;
+            }
+        }
+    } else {
+        fields.remove("transactions");
+        fields.remove("signatures");
+        if let Some(signatures) = signatures {
+            fields.insert("signatures".to_string(), json!(signatures));
+        }
+    }
+    Ok(block)
+}
+
+/// What the transaction index records for one archived block.
+fn index_block(slot: u64, block: &Value) -> Result<IndexedBlock, Box<dyn Error>> {
+    let mut transactions = Vec::new();
+    for entry in block["transactions"].as_array().map(Vec::as_slice).unwrap_or_default() {
+        let signature = transaction_signature(entry)?.ok_or("Archived transaction has no signature")?;
+        transactions.push(IndexedTransaction { signature, err: entry["meta"]["err"].clone(), accounts: transaction_accounts(entry)? });
+    }
+    Ok(IndexedBlock { slot, block_time: block["blockTime"].as_i64(), transactions })
+}
+
+/// Adds the blocks, transactions and accounts of every volume not yet indexed to the catalog's transaction
+/// index, one volume at a time in archive order. A volume that cannot be read is indexed as far as it could
+/// be read, so it is not retried. Returns how many volumes were indexed.
+pub fn index_archive(archive_dir: &Path) -> Result<usize, Box<dyn Error>> {
+    let catalog = VolumeCatalog::open_in(archive_dir)?;
+    catalog.sync_directory(archive_dir)?;
+    let volumes = catalog.unindexed_volumes()?;
+    for volume in &volumes {
+        let mut blocks = Vec::new();
+        if let Some(path) = local_path(archive_dir, volume) {
+            let indexed = for_each_volume_block(&path, |slot, block| {
+                blocks.push(index_block(slot, block).map_err(|err| format!("Archived block {}: {}", slot, err))?);
+                Ok(true)
+            });
+            if let Err(err) = indexed {
+                warn!("Indexed {} only up to slot {:?}: {}", volume.file_name, blocks.last().map(|block| block.slot), err);
+            }
+        }
+        catalog.record_index(&volume.file_name, &blocks)?;
+    }
+    Ok(volumes.len())
+}
+
+/// Indexes new volumes every `INDEX_INTERVAL`, for as long as the server runs.
+async fn index_periodically(archive_dir: Arc<PathBuf>) {
+    let mut interval = tokio::time::interval(INDEX_INTERVAL);
+    loop {
+        interval.tick().await;
+        let dir = archive_dir.clone();
+        match tokio::task::spawn_blocking(move || index_archive(&dir).map_err(|err| err.to_string())).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(indexed)) => debug!("Indexed {} new volumes for the JSON-RPC endpoint", indexed),
+            Ok(Err(err)) => warn!("Cannot index the archive: {}", err),
+            Err(err) => warn!("Archive indexing failed: {}", err),
+        }
+    }
+}
+
+/// The indexed block at `slot`, read from the one volume the index says holds it. None if the slot is not
+/// indexed or its volume was evicted from local disk.
+fn indexed_block(archive_dir: &Path, catalog: &VolumeCatalog, slot: u64) -> Result<Option<Value>, Box<dyn Error>> {
+    let Some((file_name, _)) = catalog.indexed_block(slot)? else { return Ok(None) };
+    let Some(path) = catalog.volume(&file_name)?.and_then(|volume| local_path(archive_dir, &volume)) else { return Ok(None) };
+    volume_block(&path, slot)
+}
+
+fn slot_param(params: &Value, index: usize) -> Result<u64, RpcError> {
+    params[index].as_u64().ok_or_else(|| RpcError::invalid_params(format!("Parameter {} must be a slot number", index)))
+}
+
+fn block_not_available(slot: u64) -> RpcError {
+    RpcError { code: BLOCK_NOT_AVAILABLE, message: format!("Block not available for slot {}", slot) }
+}
+
+fn get_block(archive_dir: &Path, params: &Value) -> Result<Value, RpcError> {
+    let slot = slot_param(params, 0)?;
+    let block = indexed_block(archive_dir, &VolumeCatalog::open_in(archive_dir)?, slot)?.ok_or_else(|| block_not_available(slot))?;
+    shape_block(block, &params[1])
+}
+
+fn get_block_time(archive_dir: &Path, params: &Value) -> Result<Value, RpcError> {
+    let slot = slot_param(params, 0)?;
+    let (_, block_time) = VolumeCatalog::open_in(archive_dir)?.indexed_block(slot)?.ok_or_else(|| block_not_available(slot))?;
+    Ok(json!(block_time))
+}
+
+/// Slots between the start and end slot (the highest archived slot if left out) that have an archived block.
+fn get_blocks(archive_dir: &Path, params: &Value) -> Result<Value, RpcError> {
+    let catalog = VolumeCatalog::open_in(archive_dir)?;
+    let first_slot = slot_param(params, 0)?;
+    let last_slot = match params[1].as_u64() {
+        Some(last_slot) => last_slot,
+        None => catalog.highest_indexed_slot()?.unwrap_or(first_slot),
+    };
+    if last_slot.saturating_sub(first_slot) > MAX_BLOCKS_RANGE {
+        return Err(RpcError::invalid_params(format!("Slot range too large; max {}", MAX_BLOCKS_RANGE)));
+    }
+    Ok(json!(catalog.indexed_slots(first_slot, last_slot.min(MAX_SLOT))?))
+}
+
+fn get_transaction(archive_dir: &Path, params: &Value) -> Result<Value, RpcError> {
+    let signature = params[0].as_str().ok_or_else(|| RpcError::invalid_params("Parameter 0 must be a transaction signature"))?;
+    let catalog = VolumeCatalog::open_in(archive_dir)?;
+    let Some(location) = catalog.indexed_transaction(signature)? else { return Ok(Value::Null) };
+    let Some(mut block) = indexed_block(archive_dir, &catalog, location.slot)? else { return Ok(Value::Null) };
+    let entry = &mut block["transactions"][location.position as usize];
+    let transaction = encode_transaction(entry["transaction"].take(), requested_encoding(&params[1]))?;
+    Ok(json!({"slot": location.slot, "blockTime": location.block_time, "transaction": transaction, "meta": entry["meta"].take(), "version": entry["version"].take()}))
+}
+
+/// Signatures of archived transactions that reference the address, newest first, like a Solana RPC node
+/// returns them, from the transaction index. A `before` or `until` signature the index does not know returns
+/// nothing, rather than the address's whole history.
+fn get_signatures_for_address(archive_dir: &Path, params: &Value) -> Result<Value, RpcError> {
+    let address = params[0].as_str().ok_or_else(|| RpcError::invalid_params("Parameter 0 must be an address"))?;
+    let config = &params[1];
+    let limit = config["limit"].as_u64().map_or(MAX_SIGNATURES_LIMIT, |limit| limit as usize);
+    if limit == 0 || limit > MAX_SIGNATURES_LIMIT {
+        return Err(RpcError::invalid_params(format!("Invalid limit; max {}", MAX_SIGNATURES_LIMIT)));
+    }
+    let catalog = VolumeCatalog::open_in(archive_dir)?;
+    let position = |signature: Option<&str>| -> Result<Option<(u64, u64)>, Box<dyn Error>> {
+        Ok(match signature {
+            Some(signature) => catalog.indexed_transaction(signature)?.map(|location| (location.slot, location.position)),
+            None => None,
+        })
+    };
+    let (before, until) = (position(config["before"].as_str())?, position(config["until"].as_str())?);
+    if (config["before"].is_string() && before.is_none()) || (config["until"].is_string() && until.is_none()) {
+        return Ok(json!([]));
+    }
+    let signatures: Vec<Value> = catalog
+        .account_transactions(address, before, until, limit)?
+        .into_iter()
+        .map(|location| json!({"signature": location.signature, "slot": location.slot, "err": location.err, "memo": null, "blockTime": location.block_time, "confirmationStatus": "finalized"}))
+        .collect();
+    Ok(json!(signatures))
+}
+
+/// Answers one JSON-RPC method call from the archive in `archive_dir`.
+pub fn call(archive_dir: &Path, method: &str, params: &Value) -> Result<Value, RpcError> {
+    match method {
+        "getBlock" => get_block(archive_dir, params),
+        "getBlockTime" => get_block_time(archive_dir, params),
+        "getBlocks" => get_blocks(archive_dir, params),
+        "getTransaction" => get_transaction(archive_dir, params),
+        "getSignaturesForAddress" => get_signatures_for_address(archive_dir, params),
+        "getSlot" => Ok(json!(VolumeCatalog::open_in(archive_dir)?.highest_indexed_slot()?.ok_or_else(|| RpcError { code: BLOCK_NOT_AVAILABLE, message: "The archive is empty".to_string() })?)),
+        _ => Err(RpcError { code: METHOD_NOT_FOUND, message: format!("Method not found: {}", method) }),
+    }
+}
+
+fn respond(archive_dir: &Path, request: &Value) -> Value {
+    let result = match request["method"].as_str() {
+        Some(method) if request["jsonrpc"] == "2.0" => call(archive_dir, method, &request["params"]),
+        _ => Err(RpcError { code: INVALID_REQUEST, message: "Invalid request".to_string() }),
+    };
+    match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "result": result, "id": request["id"]}),
+        Err(err) => json!({"jsonrpc": "2.0", "error": {"code": err.code, "message": err.message}, "id": request["id"]}),
+    }
+}
+
+/// Answers a JSON-RPC request body, which may be a single call or a batch.
+pub fn handle_body(archive_dir: &Path, body: &[u8]) -> Value {
+    match serde_json::from_slice::<Value>(body) {
+        Ok(Value::Array(requests)) => Value::Array(requests.iter().map(|request| respond(archive_dir, request)).collect()),
+        Ok(request) => respond(archive_dir, &request),
+        Err(err) => json!({"jsonrpc": "2.0", "error": {"code": PARSE_ERROR, "message": format!("Parse error: {}", err)}, "id": null}),
+    }
+}
+
+async fn handle(request: Request<Body>, archive_dir: Arc<PathBuf>) -> Result<Response<Body>, Infallible> {
+    if request.method() != Method::POST {
+        return Ok(error_response(StatusCode::METHOD_NOT_ALLOWED, "Use POST"));
+    }
+    let body = match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => body,
+        Err(err) => return Ok(error_response(StatusCode::BAD_REQUEST, err)),
+    };
+    // Reading volumes blocks on disk, so calls run off the async runtime
+    let response = match tokio::task::spawn_blocking(move || handle_body(&archive_dir, &body)).await {
+        Ok(response) => json_response(StatusCode::OK, response),
+        Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+    };
+    Ok(response)
+}
+
+/// Binds a Solana JSON-RPC endpoint that answers `RPC_METHODS` from the volumes cataloged in
+/// `archive_dir`, through the catalog's transaction index. The server future keeps the index up to date
+/// while it runs. Returns the bound address and the server future to spawn.
+pub fn bind(addr: SocketAddr, archive_dir: PathBuf) -> Result<(SocketAddr, impl Future<Output = Result<(), hyper::Error>>), Box<dyn Error>> {
+    let archive_dir = Arc::new(archive_dir);
+    let index_dir = archive_dir.clone();
+    let make_service = make_service_fn(move |_| {
+        let archive_dir = archive_dir.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| handle(request, archive_dir.clone()))) }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    let local_addr = server.local_addr();
+    let server = async move {
+        let indexer = tokio::spawn(index_periodically(index_dir));
+        let served = server.await;
+        indexer.abort();
+        served
+    };
+    Ok((local_addr, server))
+}
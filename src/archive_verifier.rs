@@ -0,0 +1,199 @@
+use crate::archive_query_server::for_each_volume_block;
+use crate::old_faithful_car::{self, transaction_signatures};
+use crate::old_faithful_converter::OLD_FAITHFUL_SOURCE;
+use crate::old_faithful_epochs;
+use crate::old_faithful_files::LocalEpochFiles;
+use crate::volume_catalog::VolumeCatalog;
+use crate::volume_extractor::local_path;
+use base64::Engine;
+use chrono::Utc;
+use log::info;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+const MAX_DETAILED_MISMATCHES: usize = 100; // Later mismatches are listed without their signature differences
+
+/// What is compared for each slot. Signatures are reduced to a digest of the sorted set, so a whole
+/// epoch's worth of summaries fits in memory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockSummary {
+    pub blockhash: Option<String>,
+    pub transaction_count: usize,
+    pub signatures_digest: String,
+}
+
+impl BlockSummary {
+    pub fn new(blockhash: Option<String>, signatures: &[String]) -> Self {
+        let mut sorted: Vec<&String> = signatures.iter().collect();
+        sorted.sort();
+        let mut hasher = Sha256::new();
+        for signature in sorted {
+            hasher.update(signature.as_bytes());
+            hasher.update(b"\n");
+        }
+        Self { blockhash, transaction_count: signatures.len(), signatures_digest: hex::encode(hasher.finalize()) }
+    }
+}
+
+/// First signature of each transaction in a `blockSubscribe` block. Handles base64 and base58 encoded
+/// transactions, JSON encoded ones, and blocks fetched with `transactionDetails: "signatures"`.
+pub fn archived_block_signatures(block: &Value) -> Result<Vec<String>, Box<dyn Error>> {
+    if let Some(signatures) = block["signatures"].as_array() {
+        return Ok(signatures.iter().filter_map(|signature| signature.as_str().map(String::from)).collect());
+    }
+    let mut signatures = Vec::new();
+    for transaction in block["transactions"].as_array().map(Vec::as_slice).unwrap_or_default() {
+        let transaction = &transaction["transaction"];
+        let signature = match (transaction[0].as_str(), transaction[1].as_str()) {
+            (Some(data), Some("base64")) => transaction_signatures(&base64::engine::general_purpose::STANDARD.decode(data)?)?.remove(0),
+            (Some(data), Some("base58")) => transaction_signatures(&bs58::decode(data).into_vec()?)?.remove(0),
+            _ => transaction["signatures"][0].as_str().ok_or("Archived transaction has no signature")?.to_string(),
+        };
+        signatures.push(signature);
+    }
+    Ok(signatures)
+}
+
+/// Finalized volumes in `archive_dir` that may hold blocks between `first_slot` and `last_slot`, from the
+/// archive catalog. Volumes converted from Old Faithful are left out, since they would be checked against
+/// themselves; volumes whose slot range is unknown are always scanned. Volumes retention moved to another
+/// directory are read from there.
+fn candidate_volumes(archive_dir: &Path, first_slot: u64, last_slot: u64) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let catalog = VolumeCatalog::open_in(archive_dir)?;
+    catalog.sync_directory(archive_dir)?;
+    Ok(catalog
+        .volumes_for_slots(first_slot, last_slot)?
+        .into_iter()
+        .filter(|volume| volume.stream != OLD_FAITHFUL_SOURCE)
+        .filter_map(|volume| local_path(archive_dir, &volume))
+        .collect())
+}
+
+/// Calls `visit` with the slot and block of every archived `blockNotification` between `first_slot` and
+/// `last_slot`, in archive order. A slot may be visited more than once if it was captured twice.
+fn for_each_archived_block(archive_dir: &Path, first_slot: u64, last_slot: u64, mut visit: impl FnMut(u64, &Value) -> Result<(), Box<dyn Error>>) -> Result<(), Box<dyn Error>> {
+    for volume in candidate_volumes(archive_dir, first_slot, last_slot)? {
+        for_each_volume_block(&volume, |slot, block| {
+            if slot >= first_slot && slot <= last_slot {
+                visit(slot, block)?;
+            }
+            Ok(true)
+        })?;
+    }
+    Ok(())
+}
+
+/// Summaries of the archived blocks between `first_slot` and `last_slot`. When a slot was captured more
+/// than once, the first capture is kept.
+pub fn archived_block_summaries(archive_dir: &Path, first_slot: u64, last_slot: u64) -> Result<BTreeMap<u64, BlockSummary>, Box<dyn Error>> {
+    let mut summaries = BTreeMap::new();
+    for_each_archived_block(archive_dir, first_slot, last_slot, |slot, block| {
+        if let std::collections::btree_map::Entry::Vacant(entry) = summaries.entry(slot) {
+            let signatures = archived_block_signatures(block).map_err(|err| format!("Archived block {}: {}", slot, err))?;
+            entry.insert(BlockSummary::new(block["blockhash"].as_str().map(String::from), &signatures));
+        }
+        Ok(())
+    })?;
+    Ok(summaries)
+}
+
+fn signature_differences(archived: &[String], old_faithful: &[String]) -> (Vec<String>, Vec<String>) {
+    let archived: BTreeSet<&String> = archived.iter().collect();
+    let old_faithful: BTreeSet<&String> = old_faithful.iter().collect();
+    let missing = old_faithful.difference(&archived).map(|signature| signature.to_string()).collect();
+    let extra = archived.difference(&old_faithful).map(|signature| signature.to_string()).collect();
+    (missing, extra)
+}
+
+/// Compares our archived blocks for an epoch with Old Faithful's CAR for the same epoch, and returns a
+/// report of the slots Old Faithful has that we do not (`missing_slots`), the slots we have that Old
+/// Faithful does not (`extra_slots`, such as blocks on an abandoned fork), and the slots whose blockhash,
+/// transaction count or signatures differ (`mismatched_slots`). Only the slots between the first and last
+/// archived block of the epoch are compared, unless `slot_range` narrows or widens them.
+pub fn verify_epoch(archive_dir: &Path, local: &LocalEpochFiles, slot_range: Option<(u64, u64)>) -> Result<Value, Box<dyn Error>> {
+    let epoch = local.files.epoch;
+    let (epoch_first_slot, epoch_last_slot) = old_faithful_epochs::epoch_slot_range(epoch);
+    let (first_slot, last_slot) = match slot_range {
+        Some((first, last)) => (first.max(epoch_first_slot), last.min(epoch_last_slot)),
+        None => (epoch_first_slot, epoch_last_slot),
+    };
+    let archived = archived_block_summaries(archive_dir, first_slot, last_slot)?;
+    let (first_slot, last_slot) = match (slot_range, archived.keys().next(), archived.keys().next_back()) {
+        (Some(_), _, _) => (first_slot, last_slot),
+        (None, Some(first), Some(last)) => (*first, *last),
+        (None, _, _) => return Err(format!("No archived blocks in epoch {} under {}", epoch, archive_dir.display()).into()),
+    };
+    info!("Verifying {} archived blocks in slots {}-{} against Old Faithful epoch {}", archived.len(), first_slot, last_slot, epoch);
+
+    let mut old_faithful_slots = BTreeSet::new();
+    let mut mismatched = BTreeMap::new();
+    let mut details_wanted = BTreeMap::new(); // Old Faithful's signatures for mismatches to be detailed
+    for block in old_faithful_car::open_epoch_car(&local.car_path)? {
+        let block = block?;
+        if block.slot < first_slot {
+            continue;
+        }
+        if block.slot > last_slot {
+            break;
+        }
+        old_faithful_slots.insert(block.slot);
+        let Some(ours) = archived.get(&block.slot) else { continue };
+        let signatures = block.transactions.iter().map(|transaction| transaction.signature()).collect::<Result<Vec<_>, _>>()?;
+        let theirs = BlockSummary::new(block.blockhash.clone(), &signatures);
+        if *ours == theirs {
+            continue;
+        }
+        let mut mismatch = json!({"slot": block.slot});
+        if ours.blockhash != theirs.blockhash {
+            mismatch["blockhash"] = json!({"archive": ours.blockhash, "old_faithful": theirs.blockhash});
+        }
+        if ours.transaction_count != theirs.transaction_count {
+            mismatch["transaction_count"] = json!({"archive": ours.transaction_count, "old_faithful": theirs.transaction_count});
+        }
+        if ours.signatures_digest != theirs.signatures_digest && details_wanted.len() < MAX_DETAILED_MISMATCHES {
+            details_wanted.insert(block.slot, signatures);
+        }
+        mismatched.insert(block.slot, mismatch);
+    }
+
+    // A second pass over the archive picks up our signatures for the mismatches worth detailing
+    let mut detailed = BTreeSet::new();
+    let (first_detailed, last_detailed) = (details_wanted.keys().next().copied(), details_wanted.keys().next_back().copied());
+    if let (Some(first_detailed), Some(last_detailed)) = (first_detailed, last_detailed) {
+        for_each_archived_block(archive_dir, first_detailed, last_detailed, |slot, block| {
+            if let Some(theirs) = details_wanted.get(&slot).filter(|_| detailed.insert(slot)) {
+                let (missing, extra) = signature_differences(&archived_block_signatures(block)?, theirs);
+                mismatched.get_mut(&slot).ok_or("Mismatch disappeared")?["signatures"] = json!({"missing": missing, "extra": extra});
+            }
+            Ok(())
+        })?;
+    }
+
+    let missing_slots: Vec<u64> = old_faithful_slots.iter().filter(|slot| !archived.contains_key(slot)).copied().collect();
+    let extra_slots: Vec<u64> = archived.keys().filter(|slot| !old_faithful_slots.contains(slot)).copied().collect();
+    let matched = archived.len() - extra_slots.len() - mismatched.len();
+    info!(
+        "Epoch {}: {} blocks matched, {} missing, {} extra and {} mismatched",
+        epoch,
+        matched,
+        missing_slots.len(),
+        extra_slots.len(),
+        mismatched.len()
+    );
+    Ok(json!({
+        "epoch": epoch,
+        "root_cid": local.files.root_cid,
+        "first_slot": first_slot,
+        "last_slot": last_slot,
+        "archived_blocks": archived.len(),
+        "old_faithful_blocks": old_faithful_slots.len(),
+        "matched_blocks": matched,
+        "missing_slots": missing_slots,
+        "extra_slots": extra_slots,
+        "mismatched_slots": mismatched.into_values().collect::<Vec<_>>(),
+        "generated_at": Utc::now().to_rfc3339(),
+    }))
+}
@@ -0,0 +1,234 @@
+use crate::event_log;
+use crate::volume_catalog::{CatalogVolume, UploadProgress, VolumeCatalog};
+use log::{error, info};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+pub const DEFAULT_CASCADE_ENDPOINT: &str = "http://127.0.0.1:8080";
+const TASK_COMPLETED_STATUS: &str = "Task Completed";
+const TASK_FAILED_STATUSES: [&str; 2] = ["Task Rejected", "Task Failed"];
+
+pub struct CascadeConfig {
+    /// Base URL of the Pastel walletnode serving the Cascade OpenAPI.
+    pub endpoint: String,
+    pub app_pastelid: String,
+    pub passphrase: String,
+    pub make_publicly_accessible: bool,
+    pub poll_interval: Duration,
+    /// How long a registration may take to be confirmed before it counts as failed and is retried.
+    pub confirmation_timeout: Duration,
+    /// Delay before retrying a failed volume, doubled on every further failure up to `max_retry_backoff`.
+    pub retry_backoff: Duration,
+    pub max_retry_backoff: Duration,
+    /// How often the archive directory is rescanned for volumes nobody announced.
+    pub rescan_interval: Duration,
+}
+
+impl Default for CascadeConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: DEFAULT_CASCADE_ENDPOINT.to_string(),
+            app_pastelid: String::new(),
+            passphrase: String::new(),
+            make_publicly_accessible: false,
+            poll_interval: Duration::from_secs(10),
+            confirmation_timeout: Duration::from_secs(2 * 60 * 60),
+            retry_backoff: Duration::from_secs(30),
+            max_retry_backoff: Duration::from_secs(60 * 60),
+            rescan_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+impl CascadeConfig {
+    /// Uploading is enabled by setting `CASCADE_ENDPOINT`; `PASTEL_ID` and `PASTEL_ID_PASSPHRASE` give the
+    /// identity registrations are made under.
+    pub fn from_env() -> Option<Self> {
+        let endpoint = std::env::var("CASCADE_ENDPOINT").ok()?;
+        Some(Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            app_pastelid: std::env::var("PASTEL_ID").unwrap_or_default(),
+            passphrase: std::env::var("PASTEL_ID_PASSPHRASE").unwrap_or_default(),
+            ..Self::default()
+        })
+    }
+}
+
+/// Uploads finalized volumes to Pastel's Cascade storage through a walletnode's OpenAPI, one at a time:
+/// `POST /openapi/cascade/upload` stores the file and returns a `file_id`, `POST
+/// /openapi/cascade/start/{file_id}` starts its registration and returns a `task_id`, and `GET
+/// /openapi/cascade/{task_id}/history` is polled until the task reaches "Task Completed". Every step and
+/// the registration ticket's txid are recorded in the volume catalog. A restart resumes from the last
+/// recorded step, so a file is never stored twice once its registration has started.
+pub struct CascadeUploader {
+    config: CascadeConfig,
+    archive_dir: PathBuf,
+    catalog: Mutex<VolumeCatalog>, // Behind a mutex only so the uploader can be moved into a spawned task
+    client: reqwest::Client,
+    next_attempt: HashMap<String, Instant>,
+}
+
+impl CascadeUploader {
+    pub fn new(config: CascadeConfig, archive_dir: &Path, catalog: VolumeCatalog) -> Self {
+        Self { config, archive_dir: archive_dir.to_path_buf(), catalog: Mutex::new(catalog), client: reqwest::Client::new(), next_attempt: HashMap::new() }
+    }
+
+    fn catalog(&self) -> MutexGuard<'_, VolumeCatalog> {
+        self.catalog.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Tries every volume whose upload is not confirmed and that is not waiting out a retry backoff,
+    /// after cataloging any volume in the archive directory the catalog missed. Returns how many were
+    /// confirmed.
+    pub async fn upload_pending(&mut self) -> Result<usize, Box<dyn Error>> {
+        let pending = {
+            let catalog = self.catalog();
+            catalog.sync_directory(&self.archive_dir)?;
+            catalog.pending_uploads()?
+        };
+        let mut confirmed = 0;
+        for volume in pending {
+            if self.next_attempt.get(&volume.file_name).is_some_and(|due| *due > Instant::now()) {
+                continue;
+            }
+            match self.upload_volume(&volume).await {
+                Ok(txid) => {
+                    info!("{} is registered on Cascade with ticket {}", volume.file_name, txid);
+                    self.next_attempt.remove(&volume.file_name);
+                    confirmed += 1;
+                }
+                Err(err) => {
+                    let attempts = volume.upload_attempts + 1;
+                    let backoff = self.config.retry_backoff.saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1) as u32)).min(self.config.max_retry_backoff);
+                    error!("Uploading {} to Cascade failed (attempt {}): {}. Retrying in {:?}", volume.file_name, attempts, err, backoff);
+                    self.next_attempt.insert(volume.file_name, Instant::now() + backoff);
+                }
+            }
+        }
+        Ok(confirmed)
+    }
+
+    /// Carries one volume through storage, registration and confirmation, starting from the step the
+    /// catalog last recorded for it. Returns the registration ticket's txid. A failure is recorded with
+    /// everything that still holds, so a retry does not store the file again.
+    pub async fn upload_volume(&mut self, volume: &CatalogVolume) -> Result<String, Box<dyn Error>> {
+        if volume.upload_state == "confirmed" {
+            return volume.cascade_txid.clone().ok_or_else(|| "Confirmed upload has no txid".into());
+        }
+        let mut progress = volume.upload_progress();
+        progress.error = None;
+        event_log::record("upload_started", json!({"volume": volume.file_name, "attempt": progress.attempts + 1, "file_id": progress.file_id, "task_id": progress.task_id}));
+        match self.advance(volume, &mut progress).await {
+            Ok(txid) => {
+                progress.txid = Some(txid.clone());
+                self.catalog().record_upload_progress(&volume.file_name, "confirmed", &progress)?;
+                event_log::record("upload_confirmed", json!({"volume": volume.file_name, "file_id": progress.file_id, "txid": txid}));
+                Ok(txid)
+            }
+            Err(err) => {
+                progress.attempts += 1;
+                progress.error = Some(err.to_string());
+                self.catalog().record_upload_progress(&volume.file_name, "failed", &progress)?;
+                event_log::record("upload_failed", json!({"volume": volume.file_name, "attempts": progress.attempts, "error": progress.error}));
+                Err(err)
+            }
+        }
+    }
+
+    async fn advance(&mut self, volume: &CatalogVolume, progress: &mut UploadProgress) -> Result<String, Box<dyn Error>> {
+        let file_id = match progress.file_id.clone() {
+            Some(file_id) => file_id,
+            None => {
+                let file_id = self.store_file(&volume.path).await?;
+                progress.file_id = Some(file_id.clone());
+                self.catalog().record_upload_progress(&volume.file_name, "uploaded", progress)?;
+                file_id
+            }
+        };
+        let task_id = match progress.task_id.clone() {
+            Some(task_id) => task_id,
+            None => {
+                let task_id = self.start_registration(&file_id).await?;
+                progress.task_id = Some(task_id.clone());
+                self.catalog().record_upload_progress(&volume.file_name, "registering", progress)?;
+                task_id
+            }
+        };
+        match self.await_confirmation(&task_id).await? {
+            Ok(txid) => Ok(txid),
+            Err(reason) => {
+                progress.task_id = None; // The task is dead, so the next attempt registers the file again
+                Err(reason.into())
+            }
+        }
+    }
+
+    async fn store_file(&self, path: &Path) -> Result<String, Box<dyn Error>> {
+        let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        let file = tokio::fs::File::open(path).await?;
+        let length = file.metadata().await?.len();
+        // Streamed from disk, so a volume is never held in memory, even across retries
+        let body = reqwest::Body::wrap_stream(tokio_util::io::ReaderStream::new(file));
+        let part = reqwest::multipart::Part::stream_with_length(body, length).file_name(file_name).mime_str("application/zstd")?;
+        let response = self.client.post(format!("{}/openapi/cascade/upload", self.config.endpoint)).multipart(reqwest::multipart::Form::new().part("file", part)).send().await?;
+        let body = checked_json(response).await?;
+        Ok(body["file_id"].as_str().ok_or("Cascade upload response has no file_id")?.to_string())
+    }
+
+    async fn start_registration(&self, file_id: &str) -> Result<String, Box<dyn Error>> {
+        let request = json!({"app_pastelid": self.config.app_pastelid, "make_publicly_accessible": self.config.make_publicly_accessible});
+        let response = self.client.post(format!("{}/openapi/cascade/start/{}", self.config.endpoint, file_id)).header("Authorization", &self.config.passphrase).json(&request).send().await?;
+        let body = checked_json(response).await?;
+        Ok(body["task_id"].as_str().ok_or("Cascade start response has no task_id")?.to_string())
+    }
+
+    /// Polls the registration task. The inner error means the task itself failed or timed out, as opposed
+    /// to the walletnode being unreachable.
+    async fn await_confirmation(&self, task_id: &str) -> Result<Result<String, String>, Box<dyn Error>> {
+        let deadline = Instant::now() + self.config.confirmation_timeout;
+        loop {
+            let response = self.client.get(format!("{}/openapi/cascade/{}/history", self.config.endpoint, task_id)).send().await?;
+            let history = checked_json(response).await?;
+            let statuses = history.as_array().map(Vec::as_slice).unwrap_or_default();
+            if let Some(failure) = statuses.iter().find(|entry| TASK_FAILED_STATUSES.iter().any(|status| entry["status"] == *status)) {
+                return Ok(Err(format!("Cascade task {} ended with {}", task_id, failure)));
+            }
+            if statuses.iter().any(|entry| entry["status"] == TASK_COMPLETED_STATUS) {
+                let txid = statuses.iter().rev().find_map(|entry| entry["txid"].as_str());
+                return Ok(txid.map(String::from).ok_or_else(|| format!("Cascade task {} completed without a registration txid", task_id)));
+            }
+            if Instant::now() >= deadline {
+                return Ok(Err(format!("Cascade task {} was not confirmed within {:?}", task_id, self.config.confirmation_timeout)));
+            }
+            tokio::time::sleep(self.config.poll_interval).await;
+        }
+    }
+
+    /// Uploads volumes as they are finalized, announced through `finalized_volumes`, and whatever else is
+    /// pending in the archive directory, retrying failures. Never returns.
+    pub async fn run(mut self, mut finalized_volumes: UnboundedReceiver<PathBuf>) {
+        loop {
+            if let Err(err) = self.upload_pending().await {
+                error!("Error uploading volumes to Cascade: {}", err);
+            }
+            tokio::select! {
+                Some(path) = finalized_volumes.recv() => info!("Queued {} for upload to Cascade", path.display()),
+                _ = tokio::time::sleep(self.config.rescan_interval) => {}
+            }
+        }
+    }
+}
+
+async fn checked_json(response: reqwest::Response) -> Result<Value, Box<dyn Error>> {
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        return Err(format!("Cascade returned {}: {}", status, body).into());
+    }
+    Ok(serde_json::from_str(&body)?)
+}
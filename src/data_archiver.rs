@@ -1,17 +1,69 @@
-use chrono::{Utc, DateTime};
+use chrono::{Utc, DateTime, TimeZone};
 use zstd::stream::write::Encoder;
 use std::fs::File;
-use std::io::{Write, BufWriter, Result as IOResult};
-use log::info;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::io::{Write, BufWriter, ErrorKind, Result as IOResult};
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicU64, Ordering};
+use log::{info, warn};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
 use num_cpus;
-use tokio::sync::mpsc::{Sender, Receiver};
+use crate::disk_monitor::DegradationLevel;
+use crate::event_log;
+use crate::fault_supervisor::{Fault, FaultSource};
+use crate::volume_catalog::VolumeCatalog;
+use tokio::sync::mpsc::{Sender, Receiver, UnboundedSender, UnboundedReceiver};
+use tokio::sync::watch;
 
 const MAX_VOLUME_SIZE: usize = 100_000_000; // 100MB
 const ZSTD_COMPRESSION_LEVEL: i32 = 21;
+const ZSTD_CONSERVING_COMPRESSION_LEVEL: i32 = 22; // Used while disk space is low, along with long-distance matching
+pub const DEFAULT_VOLUME_STATE_FILE: &str = "volume_state.json";
 
-pub fn time_bucket_complete(last_bucket_start: DateTime<Utc>, minutes_per_bucket: i64) -> bool {
-    let now = Utc::now();
-    (now - last_bucket_start).num_minutes() >= minutes_per_bucket
+/// Source of the current time for bucketing, so bucket rollover can be driven by something other than
+/// the wall clock (tests, or replaying and backfilling historical data at its original timestamps).
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time.
+#[derive(Clone)]
+pub struct SimulatedClock {
+    now: Arc<Mutex<DateTime<Utc>>>,
+}
+
+impl SimulatedClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self { now: Arc::new(Mutex::new(start)) }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, duration: chrono::Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+pub fn time_bucket_complete(last_bucket_start: DateTime<Utc>, minutes_per_bucket: i64, clock: &dyn Clock) -> bool {
+    (clock.now() - last_bucket_start).num_minutes() >= minutes_per_bucket
 }
 
 pub fn generate_file_name(bucket_start_time: DateTime<Utc>, minutes_per_bucket: i64, volume: usize, temp: bool) -> String {
@@ -21,24 +73,78 @@ pub fn generate_file_name(bucket_start_time: DateTime<Utc>, minutes_per_bucket:
     format!("solana_data_archive__from_{}_to_{}_UTC__Volume_{}.{}", from_time, to_time, volume, suffix)
 }
 
+/// The name of a volume converted from an epoch, by the slots it holds, e.g.
+/// `solana_data_archive__epoch_500_slots_216000000_216000431.zstd`.
+pub fn generate_epoch_file_name(epoch: u64, first_slot: u64, last_slot: u64) -> String {
+    format!("solana_data_archive__epoch_{}_slots_{}_{}.zstd", epoch, first_slot, last_slot)
+}
+
+/// The manifest written next to a finalized volume, e.g. `..._Volume_1.manifest.json` for `..._Volume_1.zstd`.
+pub fn manifest_path(volume_path: &Path) -> PathBuf {
+    volume_path.with_extension("manifest.json")
+}
+
+/// The slot a notification is about: the first `"slot":` field, which is the context slot (or the slot
+/// itself for slot notifications). Acknowledgments have none.
+pub fn message_slot(message: &str) -> Option<u64> {
+    let start = message.find("\"slot\":")? + 7;
+    let digits = message[start..].trim_start();
+    let end = digits.find(|c: char| !c.is_ascii_digit()).unwrap_or(digits.len());
+    digits[..end].parse().ok()
+}
+
+/// The `parentSlot` of a block notification, read without parsing the whole block.
+pub fn block_parent_slot(message: &str) -> Option<u64> {
+    let start = message.find("\"parentSlot\":")? + 13;
+    let digits: String = message[start..].trim_start().chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+/// The notification method of a message, read from its `"method":` field without parsing the whole message.
+pub fn message_method(message: &str) -> Option<&str> {
+    let start = message.find("\"method\":")? + 9;
+    let rest = message[start..].trim_start().strip_prefix('"')?;
+    rest.split('"').next()
+}
+
+/// Whether notifications with this method are dropped at `level` to save disk space. Blocks and program
+/// account updates are never dropped; slot and logs notifications can be rebuilt from blocks.
+pub fn dropped_at(level: DegradationLevel, method: &str) -> bool {
+    match method {
+        "voteNotification" | "slotsUpdatesNotification" => level >= DegradationLevel::Conserving,
+        "slotNotification" | "logsNotification" => level >= DegradationLevel::Critical,
+        _ => false,
+    }
+}
+
 pub struct MessageDispatcher {
     sender: Sender<String>,
     receiver: Receiver<String>,
     encoder_manager: EncoderManager,
+    fault_sender: UnboundedSender<Fault>,
+    last_block_slot: Option<u64>,
 }
 
 impl MessageDispatcher {
-    pub fn new(buffer_count: usize, minutes_per_bucket: i64) -> (Self, Sender<()>) {
+    /// Also returns the receiving end of the fault channel, on which the dispatcher reports encoder
+    /// failures and which other components can report to through `fault_sender`.
+    pub fn new(buffer_count: usize, minutes_per_bucket: i64, archive_dir: &Path) -> (Self, UnboundedReceiver<Fault>) {
+        Self::with_clock(buffer_count, minutes_per_bucket, archive_dir, Box::new(SystemClock))
+    }
+
+    pub fn with_clock(buffer_count: usize, minutes_per_bucket: i64, archive_dir: &Path, clock: Box<dyn Clock>) -> (Self, UnboundedReceiver<Fault>) {
         let (sender, receiver) = tokio::sync::mpsc::channel(buffer_count);
-        let (error_sender, _) = tokio::sync::mpsc::channel(1);
-        let encoder_manager = EncoderManager::new(minutes_per_bucket).unwrap();
+        let (fault_sender, fault_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let encoder_manager = EncoderManager::with_clock(minutes_per_bucket, archive_dir, clock).unwrap();
         (
             Self {
                 sender,
                 receiver,
                 encoder_manager,
+                fault_sender,
+                last_block_slot: None,
             },
-            error_sender,
+            fault_receiver,
         )
     }
 
@@ -46,54 +152,307 @@ impl MessageDispatcher {
         self.receiver.recv().await
     }
 
+    /// Archives one message. A failure is also reported on the fault channel as a recoverable encoder fault.
     pub fn process_message(&mut self, message: String) -> IOResult<()> {
-        self.encoder_manager.process_message(message)
+        self.detect_gap(&message);
+        let result = self.encoder_manager.process_message(message);
+        if let Err(err) = &result {
+            self.fault_sender.send(Fault::recoverable(FaultSource::Encoder, format!("Error processing message: {}", err))).ok();
+        }
+        result
+    }
+
+    /// Records a `gap_detected` event when a block's parent is later than the last block received, i.e.
+    /// blocks in between were produced but never arrived. Skipped slots are not gaps.
+    fn detect_gap(&mut self, message: &str) {
+        if message_method(message) != Some("blockNotification") {
+            return;
+        }
+        let Some(slot) = message_slot(message) else { return };
+        if let (Some(last_block_slot), Some(parent_slot)) = (self.last_block_slot, block_parent_slot(message)) {
+            if parent_slot > last_block_slot {
+                warn!("Blocks after slot {} through slot {} were never received", last_block_slot, parent_slot);
+                event_log::record("gap_detected", json!({"after_slot": last_block_slot, "through_slot": parent_slot, "next_block_slot": slot}));
+            }
+        }
+        self.last_block_slot = Some(self.last_block_slot.map_or(slot, |last| last.max(slot)));
     }
 
     pub fn clone_tx(&self) -> Sender<String> {
         self.sender.clone()
     }
 
+    pub fn fault_sender(&self) -> UnboundedSender<Fault> {
+        self.fault_sender.clone()
+    }
+
     pub fn finish_and_create_new(&mut self) -> IOResult<()> {
-        self.encoder_manager.finish_and_create_new()
+        self.encoder_manager.finish()
+    }
+
+    /// Stops accepting messages, archives everything already queued and finalizes the open volume with
+    /// its manifest. A message that cannot be archived is reported as a fault and skipped, so the volume is
+    /// still finalized. Returns how many queued messages were archived.
+    pub async fn drain(&mut self) -> IOResult<usize> {
+        self.receiver.close();
+        let mut drained = 0;
+        while let Some(message) = self.receiver.recv().await {
+            if self.process_message(message).is_ok() {
+                drained += 1;
+            }
+        }
+        self.encoder_manager.finish()?;
+        Ok(drained)
+    }
+
+    pub fn set_finalized_volume_sender(&mut self, sender: UnboundedSender<PathBuf>) {
+        self.encoder_manager.set_finalized_volume_sender(sender);
+    }
+
+    pub fn set_degradation(&mut self, degradation: watch::Receiver<DegradationLevel>) {
+        self.encoder_manager.set_degradation(degradation);
     }
 }
 
+/// Volume numbering state persisted in the archive directory after every finalized volume, so a restart
+/// within the same bucket continues the sequence instead of reusing (and clobbering) volume numbers.
+struct VolumeState {
+    bucket_start_time: DateTime<Utc>,
+    minutes_per_bucket: i64,
+    last_finalized_volume: usize,
+}
+
+impl VolumeState {
+    fn load(path: &Path) -> Option<Self> {
+        let contents: Value = serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()?;
+        Some(Self {
+            bucket_start_time: contents["bucket_start_time"].as_str()?.parse().ok()?,
+            minutes_per_bucket: contents["minutes_per_bucket"].as_i64()?,
+            last_finalized_volume: contents["last_finalized_volume"].as_u64()? as usize,
+        })
+    }
+
+    fn save(&self, path: &Path) -> IOResult<()> {
+        let contents = json!({
+            "bucket_start_time": self.bucket_start_time.to_rfc3339(),
+            "minutes_per_bucket": self.minutes_per_bucket,
+            "last_finalized_volume": self.last_finalized_volume,
+        });
+        let temp_path = path.with_extension("json.temp");
+        std::fs::write(&temp_path, contents.to_string())?;
+        std::fs::rename(&temp_path, path) // Rename so a crash never leaves a half-written state file
+    }
+}
+
+/// Start of the bucket containing `time`. Buckets are aligned to multiples of `minutes_per_bucket` since
+/// the Unix epoch, so every writer (and every restart) agrees on bucket boundaries and file names.
+pub fn bucket_start_for(time: DateTime<Utc>, minutes_per_bucket: i64) -> DateTime<Utc> {
+    let bucket_seconds = minutes_per_bucket.max(1) * 60;
+    let timestamp = time.timestamp();
+    Utc.timestamp_opt(timestamp - timestamp.rem_euclid(bucket_seconds), 0).unwrap()
+}
+
+/// Takes the advisory lock a writer holds on its temporary volume for as long as it is open, without
+/// waiting. Returns false if another open file, in this process or another, already holds it.
+fn try_lock(file: &File) -> IOResult<bool> {
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    if err.raw_os_error() == Some(libc::EWOULDBLOCK) {
+        Ok(false)
+    } else {
+        Err(err)
+    }
+}
+
+/// Creates `path` already locked, or returns None if it exists. The file is created and locked under a
+/// staging name and then linked into place, so no other writer can see it unlocked and orphan it.
+fn create_locked(path: &Path) -> IOResult<Option<File>> {
+    static STAGING_COUNTER: AtomicU64 = AtomicU64::new(0);
+    let staging_path = PathBuf::from(format!("{}.{}-{}.staging", path.display(), std::process::id(), STAGING_COUNTER.fetch_add(1, Ordering::Relaxed)));
+    let file = File::create(&staging_path)?;
+    try_lock(&file)?;
+    let linked = std::fs::hard_link(&staging_path, path);
+    std::fs::remove_file(&staging_path)?;
+    match linked {
+        Ok(()) => Ok(Some(file)),
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Writes messages into zstd volumes grouped by time bucket.
+///
+/// Volume numbers start at 1 in each bucket and increase by one for every volume finalized in it, with
+/// no gaps. A volume is only ever opened under a number whose finalized file does not exist yet, so an
+/// existing volume is never overwritten. Writers sharing a directory (the live ingester, backfills,
+/// conversions) lock their temporary files: a locked one makes the others move on to the next number,
+/// and an unlocked one, left by a crashed run, is moved aside as `.orphaned` rather than truncated. The
+/// encoder is opened lazily on the first message of each volume.
 pub struct EncoderManager {
     encoder: Option<Encoder<'static, BufWriter<File>>>,
+    archive_dir: PathBuf,
+    state_file_name: String,
+    clock: Box<dyn Clock>,
     bucket_start_time: DateTime<Utc>,
     minutes_per_bucket: i64,
     volume: usize,
     current_size: usize,
+    max_volume_size: usize,
+    message_count: u64,
+    slot_range: Option<(u64, u64)>,
+    manifest_details: Value,
+    finalized_volume_sender: Option<UnboundedSender<PathBuf>>,
+    catalog: Option<VolumeCatalog>,
+    degradation: Option<watch::Receiver<DegradationLevel>>,
+    dropped_messages: u64,
+    epoch_naming: Option<u64>,
 }
 
 impl EncoderManager {
-    fn create_new_encoder(&mut self, temp: bool) -> IOResult<()> {
-        let file_name = generate_file_name(self.bucket_start_time, self.minutes_per_bucket, self.volume, temp);
-        let file = File::create(&file_name)?;
+    fn volume_path(&self, volume: usize, temp: bool) -> PathBuf {
+        let file_name = generate_file_name(self.bucket_start_time, self.minutes_per_bucket, volume, temp);
+        match self.epoch_naming {
+            // Only named by its slots once finalized; until then the epoch keeps it apart from other writers' volumes
+            Some(epoch) => self.archive_dir.join(format!("solana_data_archive__epoch_{}{}", epoch, file_name.trim_start_matches("solana_data_archive"))),
+            None => self.archive_dir.join(file_name),
+        }
+    }
+
+    /// Where the open volume goes once finalized.
+    fn finalized_path(&self) -> PathBuf {
+        match (self.epoch_naming, self.slot_range) {
+            (Some(epoch), Some((first_slot, last_slot))) => self.archive_dir.join(generate_epoch_file_name(epoch, first_slot, last_slot)),
+            _ => self.volume_path(self.volume, false),
+        }
+    }
+
+    fn create_new_encoder(&mut self) -> IOResult<()> {
+        let file = loop {
+            if self.volume_path(self.volume, false).exists() {
+                warn!("{} already exists; skipping to volume {}", self.volume_path(self.volume, false).display(), self.volume + 1);
+                self.volume += 1;
+                continue;
+            }
+            let file_name = self.volume_path(self.volume, true);
+            match File::open(&file_name) {
+                Ok(existing) if !try_lock(&existing)? => {
+                    warn!("{} is being written by another writer; skipping to volume {}", file_name.display(), self.volume + 1);
+                    self.volume += 1;
+                    continue;
+                }
+                Ok(_) => {
+                    // Nobody holds its lock, so the writer that left it is gone
+                    let orphaned_file_name = PathBuf::from(format!("{}.orphaned", file_name.display()));
+                    warn!("Moving incomplete volume left by a previous run to {}", orphaned_file_name.display());
+                    match std::fs::rename(&file_name, &orphaned_file_name) {
+                        Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+                        _ => continue, // Look again, in case it was finalized in the meantime
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+            if let Some(file) = create_locked(&file_name)? {
+                break file;
+            }
+        };
         let buf_writer = BufWriter::new(file);
-        let mut encoder = Encoder::new(buf_writer, ZSTD_COMPRESSION_LEVEL)?;
+        let conserving = self.degradation_level() >= DegradationLevel::Conserving;
+        let mut encoder = Encoder::new(buf_writer, if conserving { ZSTD_CONSERVING_COMPRESSION_LEVEL } else { ZSTD_COMPRESSION_LEVEL })?;
+        if conserving {
+            encoder.long_distance_matching(true)?;
+        }
         let num_workers = num_cpus::get() as u32 / 2;
         encoder.multithread(num_workers)?;
         self.encoder = Some(encoder);
+        event_log::record("volume_opened", json!({
+            "volume": self.volume_path(self.volume, false).file_name().map(|name| name.to_string_lossy().to_string()),
+            "volume_number": self.volume,
+            "bucket_start_time": self.bucket_start_time.to_rfc3339(),
+            "source": self.manifest_details["source"],
+        }));
         Ok(())
     }
 
-    pub fn new(minutes_per_bucket: i64) -> IOResult<Self> {
-        let bucket_start_time = Utc::now();
+    pub fn new(minutes_per_bucket: i64, archive_dir: &Path) -> IOResult<Self> {
+        Self::with_clock(minutes_per_bucket, archive_dir, Box::new(SystemClock))
+    }
+
+    pub fn with_clock(minutes_per_bucket: i64, archive_dir: &Path, clock: Box<dyn Clock>) -> IOResult<Self> {
+        std::fs::create_dir_all(archive_dir)?;
         let mut manager = Self {
             encoder: None,
-            bucket_start_time,
+            archive_dir: archive_dir.to_path_buf(),
+            state_file_name: DEFAULT_VOLUME_STATE_FILE.to_string(),
+            bucket_start_time: bucket_start_for(clock.now(), minutes_per_bucket),
+            clock,
             minutes_per_bucket,
             volume: 1,
             current_size: 0,
+            max_volume_size: MAX_VOLUME_SIZE,
+            message_count: 0,
+            slot_range: None,
+            manifest_details: json!({"source": "live"}),
+            finalized_volume_sender: None,
+            catalog: None,
+            degradation: None,
+            dropped_messages: 0,
+            epoch_naming: None,
         };
-        manager.create_new_encoder(true)?;
+        manager.resume_volume_numbering();
         Ok(manager)
     }
 
+    /// Uses a separate state file, for writers sharing an archive directory with the live ingester.
+    pub fn set_state_file_name(&mut self, state_file_name: &str) {
+        self.state_file_name = state_file_name.to_string();
+        self.resume_volume_numbering();
+    }
+
+    /// Names finalized volumes by `epoch` and the slots they hold rather than by time bucket, for
+    /// conversions of whole epochs. Volumes are still rolled over at bucket boundaries and by size.
+    pub fn set_epoch_naming(&mut self, epoch: u64) {
+        self.epoch_naming = Some(epoch);
+    }
+
+    /// Fields added to every manifest this manager writes, such as the data's source ("live" by default).
+    pub fn set_manifest_details(&mut self, details: Value) {
+        self.manifest_details = details;
+    }
+
+    /// Announces the path of every volume finalized from now on, once its manifest is written.
+    pub fn set_finalized_volume_sender(&mut self, sender: UnboundedSender<PathBuf>) {
+        self.finalized_volume_sender = Some(sender);
+    }
+
+    /// Follows the disk monitor's degradation level: low-priority notifications are dropped (and counted
+    /// in the manifest) and new volumes are compressed harder while disk space is low.
+    pub fn set_degradation(&mut self, degradation: watch::Receiver<DegradationLevel>) {
+        self.degradation = Some(degradation);
+    }
+
+    fn degradation_level(&self) -> DegradationLevel {
+        self.degradation.as_ref().map_or(DegradationLevel::Normal, |degradation| *degradation.borrow())
+    }
+
+    fn resume_volume_numbering(&mut self) {
+        self.volume = match VolumeState::load(&self.archive_dir.join(&self.state_file_name)) {
+            Some(state) if state.bucket_start_time == self.bucket_start_time && state.minutes_per_bucket == self.minutes_per_bucket => state.last_finalized_volume + 1,
+            _ => 1,
+        };
+    }
+
     pub fn write_message(&mut self, message: String) -> IOResult<()> {
+        if self.encoder.is_none() {
+            self.create_new_encoder()?;
+        }
         if let Some(ref mut encoder) = self.encoder {
+            if let Some(slot) = message_slot(&message) {
+                self.slot_range = Some(self.slot_range.map_or((slot, slot), |(first, last)| (first.min(slot), last.max(slot))));
+            }
+            self.message_count += 1;
             let msg_bytes = message.into_bytes();
             encoder.write_all(&msg_bytes)?;
             self.current_size += msg_bytes.len();
@@ -102,39 +461,311 @@ impl EncoderManager {
     }
 
     pub fn nearing_max_volume(&self) -> bool {
-        self.current_size >= MAX_VOLUME_SIZE
+        self.current_size >= self.max_volume_size
+    }
+
+    /// Overrides the uncompressed size at which a volume is rolled over (100MB by default).
+    pub fn set_max_volume_size(&mut self, max_volume_size: usize) {
+        self.max_volume_size = max_volume_size;
+    }
+
+    /// Number of the open volume, or of the next volume to be opened in the current bucket.
+    pub fn volume(&self) -> usize {
+        self.volume
+    }
+
+    pub fn bucket_start_time(&self) -> DateTime<Utc> {
+        self.bucket_start_time
     }
 
     pub fn process_message(&mut self, message: String) -> IOResult<()> {
-        if self.nearing_max_volume() {
-            self.finish_and_create_new()?;
-        } else if time_bucket_complete(self.bucket_start_time, self.minutes_per_bucket) {
-            self.finish_and_create_new()?;
-            self.bucket_start_time = Utc::now();
+        let level = self.degradation_level();
+        if level > DegradationLevel::Normal && message_method(&message).is_some_and(|method| dropped_at(level, method)) {
+            self.dropped_messages += 1;
+            return Ok(());
+        }
+        if time_bucket_complete(self.bucket_start_time, self.minutes_per_bucket, self.clock.as_ref()) {
+            self.finish()?;
+            self.bucket_start_time = bucket_start_for(self.clock.now(), self.minutes_per_bucket);
             self.volume = 1;  // Reset volume to 1 for the new time bucket
+        } else if self.nearing_max_volume() {
+            self.finish()?;
         }
         self.write_message(message)?;
         Ok(())
     }
 
-    pub fn finish_and_create_new(&mut self) -> IOResult<()> {
+    /// Finalizes the open volume, if any; the next message opens the following volume in the bucket.
+    pub fn finish(&mut self) -> IOResult<()> {
         if let Some(encoder) = self.encoder.take() {
             let start_time = std::time::Instant::now();
-            encoder.finish()?;
+            let mut writer = encoder.finish()?;
+            writer.flush()?;
             let elapsed_time = start_time.elapsed();
-            let temp_file_name = generate_file_name(self.bucket_start_time, self.minutes_per_bucket, self.volume, true);
-            let final_file_name = generate_file_name(self.bucket_start_time, self.minutes_per_bucket, self.volume, false);
+            let temp_file_name = self.volume_path(self.volume, true);
+            let final_file_name = self.finalized_path();
+            if final_file_name.exists() {
+                return Err(std::io::Error::new(ErrorKind::AlreadyExists, format!("{} already exists; not overwriting it with {}", final_file_name.display(), temp_file_name.display())));
+            }
             std::fs::rename(&temp_file_name, &final_file_name)?;
+            drop(writer); // Only now release the lock, so the temporary file is never mistaken for an orphan
             let compressed_file_size = std::fs::metadata(&final_file_name)?.len() as f64 / 1_048_576.0;
             let uncompressed_file_size = self.current_size as f64 / 1_048_576.0;
             let compression_ratio = compressed_file_size / uncompressed_file_size;
-            info!("Compressed and saved data to {} in {:?} seconds! Compressed file size is {:.4}mb, compared to uncompressed file size of {:.4}mb. Compression ratio is {}.", final_file_name, elapsed_time.as_secs(), compressed_file_size, uncompressed_file_size, compression_ratio);
-            self.current_size = 0;
-            self.create_new_encoder(true)?;
-            if self.nearing_max_volume() {
-                self.volume += 1;  // Only increment volume if nearing max size
+            info!("Compressed and saved data to {} in {:?} seconds! Compressed file size is {:.4}mb, compared to uncompressed file size of {:.4}mb. Compression ratio is {}.", final_file_name.display(), elapsed_time.as_secs(), compressed_file_size, uncompressed_file_size, compression_ratio);
+            self.write_manifest(&final_file_name)?;
+            if let Some(sender) = &self.finalized_volume_sender {
+                sender.send(final_file_name.clone()).ok(); // Nobody listening is fine; uploads also rescan the directory
             }
+            let state = VolumeState { bucket_start_time: self.bucket_start_time, minutes_per_bucket: self.minutes_per_bucket, last_finalized_volume: self.volume };
+            state.save(&self.archive_dir.join(&self.state_file_name))?;
+            self.current_size = 0;
+            self.message_count = 0;
+            self.dropped_messages = 0;
+            self.slot_range = None;
+            self.volume += 1;
         }
         Ok(())
     }
-}    
\ No newline at end of file
+
+    /// Describes a finalized volume, so consumers can find data by slot without decompressing anything.
+    fn write_manifest(&mut self, volume_path: &Path) -> IOResult<()> {
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut File::open(volume_path)?, &mut hasher)?;
+        let mut manifest = json!({
+            "volume": volume_path.file_name().map(|name| name.to_string_lossy().to_string()),
+            "bucket_start_time": self.bucket_start_time.to_rfc3339(),
+            "minutes_per_bucket": self.minutes_per_bucket,
+            "volume_number": self.volume,
+            "first_slot": self.slot_range.map(|(first, _)| first),
+            "last_slot": self.slot_range.map(|(_, last)| last),
+            "message_count": self.message_count,
+            "dropped_messages": self.dropped_messages,
+            "uncompressed_bytes": self.current_size,
+            "compressed_bytes": std::fs::metadata(volume_path)?.len(),
+            "sha256": hex::encode(hasher.finalize()),
+            "finalized_at": self.clock.now().to_rfc3339(),
+        });
+        if let (Some(manifest), Value::Object(details)) = (manifest.as_object_mut(), self.manifest_details.clone()) {
+            manifest.extend(details);
+        }
+        let path = manifest_path(volume_path);
+        let temp_path = path.with_extension("json.temp");
+        std::fs::write(&temp_path, serde_json::to_string_pretty(&manifest)?)?;
+        std::fs::rename(&temp_path, &path)?;
+        let summary = ["volume", "source", "first_slot", "last_slot", "message_count", "dropped_messages", "compressed_bytes", "sha256"].iter().map(|key| (key.to_string(), manifest[key].clone()));
+        event_log::record("volume_finalized", Value::Object(summary.collect()));
+        self.catalog_volume(volume_path, &manifest);
+        Ok(())
+    }
+
+    /// Records the volume in the archive directory's catalog. A failure only costs a warning, since the
+    /// catalog picks up uncataloged volumes from their manifests on its next sync.
+    fn catalog_volume(&mut self, volume_path: &Path, manifest: &Value) {
+        if self.catalog.is_none() {
+            match VolumeCatalog::open_in(&self.archive_dir) {
+                Ok(catalog) => self.catalog = Some(catalog),
+                Err(err) => warn!("Could not open the archive catalog in {}: {}", self.archive_dir.display(), err),
+            }
+        }
+        if let Some(Err(err)) = self.catalog.as_ref().map(|catalog| catalog.record_volume(volume_path, manifest)) {
+            warn!("Could not catalog {}: {}", volume_path.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use std::io::Read;
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 9, 1, 12, 0, 0).unwrap()
+    }
+
+    fn volume_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name.starts_with("solana_data_archive__") && !name.ends_with(".manifest.json"))
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn decompress(path: &Path) -> String {
+        let mut contents = String::new();
+        zstd::stream::read::Decoder::new(File::open(path).unwrap()).unwrap().read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    fn manager_at(dir: &Path, clock: &SimulatedClock, max_volume_size: usize) -> EncoderManager {
+        let mut manager = EncoderManager::with_clock(1, dir, Box::new(clock.clone())).unwrap();
+        manager.set_max_volume_size(max_volume_size);
+        manager
+    }
+
+    #[test]
+    fn bucket_completes_exactly_at_the_boundary() {
+        let clock = SimulatedClock::new(start());
+        clock.advance(Duration::seconds(59));
+        assert!(!time_bucket_complete(start(), 1, &clock));
+        clock.advance(Duration::seconds(1));
+        assert!(time_bucket_complete(start(), 1, &clock));
+        assert!(!time_bucket_complete(start(), 5, &clock));
+        clock.set(start() + Duration::minutes(5));
+        assert!(time_bucket_complete(start(), 5, &clock));
+    }
+
+    #[test]
+    fn buckets_are_aligned_to_their_length() {
+        assert_eq!(bucket_start_for(start() + Duration::seconds(59), 1), start());
+        assert_eq!(bucket_start_for(start() + Duration::minutes(7), 5), start() + Duration::minutes(5));
+        assert_eq!(bucket_start_for(start() + Duration::minutes(5), 5), start() + Duration::minutes(5));
+    }
+
+    #[test]
+    fn crossing_a_bucket_boundary_finalizes_the_volume_and_opens_the_next_bucket() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = SimulatedClock::new(start() + Duration::seconds(10));
+        let mut manager = manager_at(dir.path(), &clock, MAX_VOLUME_SIZE);
+        manager.process_message("a".to_string()).unwrap();
+        clock.advance(Duration::seconds(49));
+        manager.process_message("b".to_string()).unwrap();
+        assert_eq!(volume_names(dir.path()), [generate_file_name(start(), 1, 1, true)]);
+
+        clock.advance(Duration::seconds(2));
+        manager.process_message("c".to_string()).unwrap();
+        let next_bucket = start() + Duration::minutes(1);
+        assert_eq!(manager.bucket_start_time(), next_bucket);
+        assert_eq!(volume_names(dir.path()), [generate_file_name(start(), 1, 1, false), generate_file_name(next_bucket, 1, 1, true)]);
+        assert_eq!(decompress(&dir.path().join(generate_file_name(start(), 1, 1, false))), "ab");
+    }
+
+    #[test]
+    fn size_rollover_increments_the_volume_within_the_same_bucket() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = SimulatedClock::new(start());
+        let mut manager = manager_at(dir.path(), &clock, 4);
+        for message in ["full", "next", "last"] {
+            manager.process_message(message.to_string()).unwrap();
+        }
+        manager.finish().unwrap();
+        assert_eq!(volume_names(dir.path()), [1, 2, 3].map(|volume| generate_file_name(start(), 1, volume, false)));
+        assert_eq!(decompress(&dir.path().join(generate_file_name(start(), 1, 1, false))), "full");
+        assert_eq!(decompress(&dir.path().join(generate_file_name(start(), 1, 3, false))), "last");
+    }
+
+    #[test]
+    fn volume_numbering_resets_for_a_new_bucket() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = SimulatedClock::new(start());
+        let mut manager = manager_at(dir.path(), &clock, 4);
+        manager.process_message("full".to_string()).unwrap();
+        manager.process_message("full".to_string()).unwrap();
+        assert_eq!(manager.volume(), 2);
+        clock.advance(Duration::minutes(1));
+        manager.process_message("later".to_string()).unwrap();
+        assert_eq!(manager.volume(), 1);
+        assert!(dir.path().join(generate_file_name(start() + Duration::minutes(1), 1, 1, true)).exists());
+        assert!(dir.path().join(generate_file_name(start(), 1, 2, false)).exists());
+    }
+
+    #[test]
+    fn time_rollover_takes_precedence_over_a_full_volume() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = SimulatedClock::new(start());
+        let mut manager = manager_at(dir.path(), &clock, 4);
+        manager.process_message("full".to_string()).unwrap();
+        clock.advance(Duration::minutes(1));
+        manager.process_message("later".to_string()).unwrap();
+        assert_eq!(manager.bucket_start_time(), start() + Duration::minutes(1));
+        assert_eq!(manager.volume(), 1);
+    }
+
+    #[test]
+    fn restart_within_a_bucket_continues_numbering() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = SimulatedClock::new(start());
+        let mut manager = manager_at(dir.path(), &clock, 4);
+        manager.process_message("full".to_string()).unwrap();
+        manager.process_message("full".to_string()).unwrap();
+        manager.finish().unwrap();
+        drop(manager);
+
+        clock.advance(Duration::seconds(30));
+        let mut restarted = manager_at(dir.path(), &clock, 4);
+        assert_eq!(restarted.volume(), 3);
+        restarted.process_message("more".to_string()).unwrap();
+        restarted.finish().unwrap();
+        assert_eq!(volume_names(dir.path()), [1, 2, 3].map(|volume| generate_file_name(start(), 1, volume, false)));
+    }
+
+    #[test]
+    fn existing_volumes_are_never_overwritten() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = SimulatedClock::new(start());
+        let existing = dir.path().join(generate_file_name(start(), 1, 1, false));
+        std::fs::write(&existing, "someone else's data").unwrap();
+        let mut manager = manager_at(dir.path(), &clock, MAX_VOLUME_SIZE);
+        manager.process_message("new".to_string()).unwrap();
+        manager.finish().unwrap();
+        assert_eq!(std::fs::read_to_string(&existing).unwrap(), "someone else's data");
+        assert_eq!(decompress(&dir.path().join(generate_file_name(start(), 1, 2, false))), "new");
+    }
+
+    #[test]
+    fn incomplete_volume_from_a_crashed_run_is_moved_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = SimulatedClock::new(start());
+        let leftover = dir.path().join(generate_file_name(start(), 1, 1, true));
+        std::fs::write(&leftover, "partial").unwrap();
+        let mut manager = manager_at(dir.path(), &clock, MAX_VOLUME_SIZE);
+        manager.process_message("new".to_string()).unwrap();
+        manager.finish().unwrap();
+        let orphaned = PathBuf::from(format!("{}.orphaned", leftover.display()));
+        assert_eq!(std::fs::read_to_string(orphaned).unwrap(), "partial");
+        assert_eq!(decompress(&dir.path().join(generate_file_name(start(), 1, 1, false))), "new");
+    }
+
+    #[test]
+    fn writers_sharing_a_directory_never_orphan_each_others_volumes() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = SimulatedClock::new(start());
+        let mut live = manager_at(dir.path(), &clock, MAX_VOLUME_SIZE);
+        live.process_message("live".to_string()).unwrap();
+        let mut backfill = manager_at(dir.path(), &clock, MAX_VOLUME_SIZE);
+        backfill.set_state_file_name("backfill_volume_state.json");
+        backfill.process_message("backfill".to_string()).unwrap();
+        assert_eq!(backfill.volume(), 2, "volume 1 is still being written");
+        backfill.finish().unwrap();
+        live.process_message(" more".to_string()).unwrap();
+        live.finish().unwrap();
+
+        assert_eq!(volume_names(dir.path()), [1, 2].map(|volume| generate_file_name(start(), 1, volume, false)));
+        assert_eq!(decompress(&dir.path().join(generate_file_name(start(), 1, 1, false))), "live more");
+        assert_eq!(decompress(&dir.path().join(generate_file_name(start(), 1, 2, false))), "backfill");
+        assert!(std::fs::read_dir(dir.path()).unwrap().all(|entry| !entry.unwrap().file_name().to_string_lossy().contains(".temp.zstd")), "nothing orphaned or left behind");
+    }
+
+    #[test]
+    fn finalized_volumes_get_a_manifest_with_their_slot_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = SimulatedClock::new(start());
+        let mut manager = manager_at(dir.path(), &clock, MAX_VOLUME_SIZE);
+        manager.set_manifest_details(json!({"source": "backfill"}));
+        for message in [r#"{"result":101,"id":1}"#, r#"{"params":{"result":{"context":{"slot": 12},"value":{"slot":12}}}}"#, r#"{"params":{"result":{"parent":10,"slot":11}}}"#] {
+            manager.process_message(message.to_string()).unwrap();
+        }
+        clock.advance(Duration::seconds(30));
+        manager.finish().unwrap();
+        let volume = dir.path().join(generate_file_name(start(), 1, 1, false));
+        let manifest: Value = serde_json::from_str(&std::fs::read_to_string(manifest_path(&volume)).unwrap()).unwrap();
+        assert_eq!((manifest["first_slot"].as_u64(), manifest["last_slot"].as_u64()), (Some(11), Some(12)));
+        assert_eq!(manifest["message_count"], 3);
+        assert_eq!(manifest["source"], "backfill");
+        assert_eq!(manifest["finalized_at"], (start() + Duration::seconds(30)).to_rfc3339(), "stamped by the manager's clock");
+        assert_eq!(manifest["compressed_bytes"].as_u64(), Some(std::fs::metadata(&volume).unwrap().len()));
+    }
+}
@@ -0,0 +1,151 @@
+use crate::fault_supervisor::{Fault, FaultSource};
+use log::{error, info, warn};
+use std::io::{Error as IOError, Result as IOResult};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::watch;
+
+pub const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const GB: u64 = 1024 * 1024 * 1024;
+
+/// How hard the ingester is trying to save disk space. Each level keeps the measures of the ones below it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DegradationLevel {
+    Normal,
+    /// Evict uploaded volumes, compress harder, pause debug decompression and drop vote and slot-update
+    /// notifications.
+    Conserving,
+    /// Also drop slot and logs notifications, which can be rebuilt from blocks.
+    Critical,
+    /// Finalize the open volume and stop ingesting.
+    Exhausted,
+}
+
+/// Free space on the archive filesystem below which each degradation level starts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DiskThresholds {
+    pub conserving_below: u64,
+    pub critical_below: u64,
+    pub exhausted_below: u64,
+}
+
+impl Default for DiskThresholds {
+    fn default() -> Self {
+        Self { conserving_below: 10 * GB, critical_below: 5 * GB, exhausted_below: GB }
+    }
+}
+
+impl DiskThresholds {
+    pub fn level_for(&self, available_bytes: u64) -> DegradationLevel {
+        if available_bytes < self.exhausted_below {
+            DegradationLevel::Exhausted
+        } else if available_bytes < self.critical_below {
+            DegradationLevel::Critical
+        } else if available_bytes < self.conserving_below {
+            DegradationLevel::Conserving
+        } else {
+            DegradationLevel::Normal
+        }
+    }
+}
+
+/// Bytes available to unprivileged writers on the filesystem holding `path`.
+pub fn available_space(path: &Path) -> IOResult<u64> {
+    let c_path = std::ffi::CString::new(path.as_os_str().to_string_lossy().as_bytes()).map_err(IOError::other)?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stats) } != 0 {
+        return Err(IOError::last_os_error());
+    }
+    Ok(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+/// Watches free space on the filesystem the archive directory lives on and publishes the resulting
+/// degradation level. While degraded, it asks the retention manager to free enough space to get back
+/// above `conserving_below`.
+pub struct DiskMonitor {
+    archive_dir: PathBuf,
+    thresholds: DiskThresholds,
+    level: watch::Sender<DegradationLevel>,
+    free_space_requests: Option<UnboundedSender<u64>>,
+    faults: Option<UnboundedSender<Fault>>,
+}
+
+impl DiskMonitor {
+    pub fn new(archive_dir: &Path, thresholds: DiskThresholds) -> Self {
+        let (level, _) = watch::channel(DegradationLevel::Normal);
+        Self { archive_dir: archive_dir.to_path_buf(), thresholds, level, free_space_requests: None, faults: None }
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<DegradationLevel> {
+        self.level.subscribe()
+    }
+
+    /// Where to send the number of bytes to free while degraded.
+    pub fn set_free_space_requests(&mut self, sender: UnboundedSender<u64>) {
+        self.free_space_requests = Some(sender);
+    }
+
+    /// Where to report running out of space (fatal) and failing to measure it (recoverable).
+    pub fn set_fault_sender(&mut self, sender: UnboundedSender<Fault>) {
+        self.faults = Some(sender);
+    }
+
+    fn report(&self, fault: Fault) {
+        if let Some(faults) = &self.faults {
+            faults.send(fault).ok();
+        }
+    }
+
+    /// Measures free space once, publishes the level and, if degraded, asks for space to be freed.
+    pub fn check(&self) -> IOResult<DegradationLevel> {
+        let available = available_space(&self.archive_dir)?;
+        let level = self.thresholds.level_for(available);
+        let previous = *self.level.borrow();
+        if level != previous {
+            let available_gb = available as f64 / GB as f64;
+            match level {
+                DegradationLevel::Normal => info!("Disk space recovered ({:.2} GB free in {}); leaving degraded mode", available_gb, self.archive_dir.display()),
+                DegradationLevel::Exhausted => {
+                    error!("Only {:.2} GB free in {}; stopping ingestion", available_gb, self.archive_dir.display());
+                    self.report(Fault::fatal(FaultSource::DiskMonitor, format!("Archive disk is full: only {:.2} GB free in {}", available_gb, self.archive_dir.display())));
+                }
+                _ => warn!("Only {:.2} GB free in {}; degrading to {:?}", available_gb, self.archive_dir.display(), level),
+            }
+            self.level.send_replace(level);
+        }
+        if level > DegradationLevel::Normal {
+            if let Some(requests) = &self.free_space_requests {
+                requests.send(self.thresholds.conserving_below - available).ok();
+            }
+        }
+        Ok(level)
+    }
+
+    /// Checks free space every `DISK_CHECK_INTERVAL`. Never returns.
+    pub async fn run(self) {
+        loop {
+            if let Err(err) = self.check() {
+                error!("Error checking free space in {}: {}", self.archive_dir.display(), err);
+                self.report(Fault::recoverable(FaultSource::DiskMonitor, format!("Error checking free space in {}: {}", self.archive_dir.display(), err)));
+            }
+            tokio::time::sleep(DISK_CHECK_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_follow_the_thresholds() {
+        let thresholds = DiskThresholds::default();
+        assert_eq!(thresholds.level_for(20 * GB), DegradationLevel::Normal);
+        assert_eq!(thresholds.level_for(10 * GB - 1), DegradationLevel::Conserving);
+        assert_eq!(thresholds.level_for(5 * GB - 1), DegradationLevel::Critical);
+        assert_eq!(thresholds.level_for(GB - 1), DegradationLevel::Exhausted);
+        assert!(available_space(Path::new(".")).unwrap() > 0);
+        assert!(available_space(Path::new("/no/such/directory")).is_err());
+    }
+}
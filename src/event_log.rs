@@ -0,0 +1,32 @@
+use chrono::Utc;
+use log::warn;
+use serde_json::{json, Value};
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Result as IOResult, Write};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+pub const DEFAULT_EVENT_LOG_FILE: &str = "pastel_solana_archival_data_events.jsonl";
+
+static EVENT_LOG: OnceLock<Mutex<LineWriter<File>>> = OnceLock::new();
+
+/// Starts appending lifecycle events to `path`, one JSON object per line. Like the text log, this is
+/// process-wide and can only be set up once; until it is, events are discarded.
+pub fn init(path: &Path) -> IOResult<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    EVENT_LOG.set(Mutex::new(LineWriter::new(file))).map_err(|_| std::io::Error::other("The event log is already initialized"))
+}
+
+/// Appends `{"timestamp": ..., "event": event, ...details}` to the event log. `details` must be an
+/// object; a failed write only costs a warning in the text log.
+pub fn record(event: &str, details: Value) {
+    let Some(event_log) = EVENT_LOG.get() else { return };
+    let mut line = json!({"timestamp": Utc::now().to_rfc3339(), "event": event});
+    if let (Some(line), Value::Object(details)) = (line.as_object_mut(), details) {
+        line.extend(details);
+    }
+    let mut writer = event_log.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Err(err) = writeln!(writer, "{}", line) {
+        warn!("Could not write {} to the event log: {}", event, err);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::old_faithful_files::{IndexKind, LocalEpochFiles};
+use log::{error, info, warn};
+use serde_json::json;
+use std::error::Error;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::watch;
+
+pub const DEFAULT_FAITHFUL_CLI_BINARY: &str = "faithful-cli";
+pub const DEFAULT_FAITHFUL_CLI_LISTEN_ADDRESS: &str = ":7999";
+
+pub struct FaithfulCliConfig {
+    pub binary_path: PathBuf,
+    /// Passed to `--listen`; ":7999" listens on every interface.
+    pub listen_address: String,
+    /// How long a freshly started server may take before its RPC endpoint answers.
+    pub startup_timeout: Duration,
+    /// Consecutive crashes tolerated before giving up. The count resets once the server has stayed up for
+    /// `stable_after`.
+    pub max_restarts: u32,
+    pub stable_after: Duration,
+    pub restart_backoff: Duration,
+    /// How long the server gets to exit after SIGTERM before it is killed.
+    pub shutdown_grace_period: Duration,
+}
+
+impl Default for FaithfulCliConfig {
+    fn default() -> Self {
+        Self {
+            binary_path: PathBuf::from(DEFAULT_FAITHFUL_CLI_BINARY),
+            listen_address: DEFAULT_FAITHFUL_CLI_LISTEN_ADDRESS.to_string(),
+            startup_timeout: Duration::from_secs(300),
+            max_restarts: 5,
+            stable_after: Duration::from_secs(300),
+            restart_backoff: Duration::from_secs(5),
+            shutdown_grace_period: Duration::from_secs(30),
+        }
+    }
+}
+
+impl FaithfulCliConfig {
+    /// The URL local clients should use to reach the server's JSON-RPC endpoint.
+    pub fn rpc_url(&self) -> String {
+        match self.listen_address.strip_prefix(':') {
+            Some(port) => format!("http://127.0.0.1:{}", port),
+            None => format!("http://{}", self.listen_address.replace("0.0.0.0", "127.0.0.1")),
+        }
+    }
+}
+
+/// Runs `faithful-cli rpc-server-car` for one epoch as a supervised child process: its output is
+/// forwarded to our log, it is restarted when it crashes, and it is stopped with SIGTERM on shutdown.
+pub struct FaithfulCliSupervisor {
+    config: FaithfulCliConfig,
+    epoch_files: LocalEpochFiles,
+    healthy: watch::Sender<bool>,
+}
+
+impl FaithfulCliSupervisor {
+    pub fn new(config: FaithfulCliConfig, epoch_files: LocalEpochFiles) -> Self {
+        let (healthy, _) = watch::channel(false);
+        Self { config, epoch_files, healthy }
+    }
+
+    pub fn rpc_url(&self) -> String {
+        self.config.rpc_url()
+    }
+
+    /// Becomes true whenever the server is up and answering RPC requests.
+    pub fn health(&self) -> watch::Receiver<bool> {
+        self.healthy.subscribe()
+    }
+
+    fn spawn(&self) -> Result<Child, Box<dyn Error>> {
+        self.epoch_files.validate()?;
+        let mut child = Command::new(&self.config.binary_path)
+            .arg("rpc-server-car")
+            .arg("--listen")
+            .arg(&self.config.listen_address)
+            .arg(&self.epoch_files.car_path)
+            .arg(self.epoch_files.index_path(IndexKind::CidToOffsetAndSize))
+            .arg(self.epoch_files.index_path(IndexKind::SlotToCid))
+            .arg(self.epoch_files.index_path(IndexKind::SigToCid))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| format!("Failed to start {}: {}", self.config.binary_path.display(), err))?;
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(forward_output(stdout, false));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(forward_output(stderr, true));
+        }
+        info!("Started faithful-cli (pid {}) for epoch {} on {}", child.id().unwrap_or(0), self.epoch_files.files.epoch, self.config.listen_address);
+        Ok(child)
+    }
+
+    async fn is_responding(&self) -> bool {
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": "getVersion"});
+        let client = reqwest::Client::builder().timeout(Duration::from_secs(5)).build().unwrap_or_default();
+        client.post(self.rpc_url()).json(&request).send().await.is_ok() // Any HTTP answer means the server is up
+    }
+
+    /// Polls the RPC endpoint until it answers; returns false if the child exits or the timeout passes first.
+    async fn wait_until_healthy(&self, child: &mut Child) -> Result<bool, Box<dyn Error>> {
+        let deadline = Instant::now() + self.config.startup_timeout;
+        while Instant::now() < deadline {
+            if let Some(status) = child.try_wait()? {
+                warn!("faithful-cli exited during startup with {}", status);
+                return Ok(false);
+            }
+            if self.is_responding().await {
+                return Ok(true);
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        warn!("faithful-cli did not become healthy within {:?}", self.config.startup_timeout);
+        Ok(false)
+    }
+
+    async fn stop(&self, child: &mut Child) -> Result<(), Box<dyn Error>> {
+        if let Some(pid) = child.id() {
+            info!("Stopping faithful-cli (pid {})...", pid);
+            unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) };
+            if tokio::time::timeout(self.config.shutdown_grace_period, child.wait()).await.is_ok() {
+                return Ok(());
+            }
+            warn!("faithful-cli did not exit within {:?}; killing it", self.config.shutdown_grace_period);
+        }
+        child.kill().await?;
+        Ok(())
+    }
+
+    /// Keeps the server running until `shutdown` becomes true, then stops it. Returns an error if the
+    /// binary cannot be started, or if it keeps crashing beyond `max_restarts`.
+    pub async fn supervise(&self, mut shutdown: watch::Receiver<bool>) -> Result<(), Box<dyn Error>> {
+        let mut consecutive_failures = 0;
+        loop {
+            if *shutdown.borrow() {
+                return Ok(());
+            }
+            let mut child = self.spawn()?;
+            let started = Instant::now();
+            let became_healthy = tokio::select! {
+                healthy = self.wait_until_healthy(&mut child) => healthy?,
+                _ = shutdown.wait_for(|stop| *stop) => {
+                    return self.stop(&mut child).await;
+                }
+            };
+            if became_healthy {
+                info!("faithful-cli is serving epoch {} at {}", self.epoch_files.files.epoch, self.rpc_url());
+                self.healthy.send_replace(true);
+                tokio::select! {
+                    status = child.wait() => {
+                        error!("faithful-cli exited unexpectedly with {}", status?);
+                    }
+                    _ = shutdown.wait_for(|stop| *stop) => {
+                        self.healthy.send_replace(false);
+                        return self.stop(&mut child).await;
+                    }
+                }
+                self.healthy.send_replace(false);
+            } else {
+                let _ = child.kill().await;
+            }
+            if started.elapsed() >= self.config.stable_after {
+                consecutive_failures = 0;
+            }
+            consecutive_failures += 1;
+            if consecutive_failures > self.config.max_restarts {
+                return Err(format!("faithful-cli failed {} times in a row; giving up", consecutive_failures).into());
+            }
+            warn!("Restarting faithful-cli in {:?} (restart {}/{})", self.config.restart_backoff, consecutive_failures, self.config.max_restarts);
+            tokio::select! {
+                _ = tokio::time::sleep(self.config.restart_backoff) => {}
+                _ = shutdown.wait_for(|stop| *stop) => return Ok(()),
+            }
+        }
+    }
+}
+
+async fn forward_output(output: impl AsyncRead + Unpin, is_stderr: bool) {
+    let mut lines = BufReader::new(output).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if is_stderr {
+            warn!("faithful-cli: {}", line);
+        } else {
+            info!("faithful-cli: {}", line);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use crate::event_log;
+use log::{error, warn};
+use serde_json::json;
+use std::fmt;
+use std::time::Duration;
+
+/// Which part of the ingester a fault came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaultSource {
+    Connector,
+    Encoder,
+    DiskMonitor,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The component can carry on once the supervisor has acted, e.g. by reconnecting.
+    Recoverable,
+    /// Ingestion cannot safely continue.
+    Fatal,
+}
+
+/// A problem reported to the supervisor through the channel returned by `MessageDispatcher::new`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fault {
+    pub source: FaultSource,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Fault {
+    pub fn recoverable(source: FaultSource, message: impl Into<String>) -> Self {
+        Self { source, severity: Severity::Recoverable, message: message.into() }
+    }
+
+    pub fn fatal(source: FaultSource, message: impl Into<String>) -> Self {
+        Self { source, severity: Severity::Fatal, message: message.into() }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} {:?} fault: {}", self.source, self.severity, self.message)
+    }
+}
+
+/// What the ingester does about a fault.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FaultAction {
+    /// Logged, nothing else to do.
+    Ignore,
+    /// Drop the PubSub connection and open a new one after waiting this long.
+    Reconnect(Duration),
+    /// Finalize the open volume so the next message starts a fresh one.
+    RotateVolume,
+    /// Stop intake, drain and exit with an error.
+    Shutdown,
+}
+
+/// Decides how to respond to each fault, giving up on the connection after too many failed reconnects.
+pub struct FaultSupervisor {
+    max_reconnect_attempts: u64,
+    reconnect_attempts: u64,
+}
+
+impl FaultSupervisor {
+    pub fn new(max_reconnect_attempts: u64) -> Self {
+        Self { max_reconnect_attempts, reconnect_attempts: 0 }
+    }
+
+    /// Call once a connection is established, so later reconnects start from a short delay again.
+    pub fn connected(&mut self) {
+        self.reconnect_attempts = 0;
+    }
+
+    pub fn decide(&mut self, fault: &Fault) -> FaultAction {
+        let action = match (fault.severity, fault.source) {
+            (Severity::Fatal, _) => FaultAction::Shutdown,
+            (Severity::Recoverable, FaultSource::Connector) if self.reconnect_attempts >= self.max_reconnect_attempts => {
+                error!("Giving up after {} failed reconnect attempts", self.reconnect_attempts);
+                FaultAction::Shutdown
+            }
+            (Severity::Recoverable, FaultSource::Connector) => {
+                self.reconnect_attempts += 1;
+                FaultAction::Reconnect(Duration::from_secs(self.reconnect_attempts)) // Linear backoff, as before
+            }
+            (Severity::Recoverable, FaultSource::Encoder) => FaultAction::RotateVolume,
+            (Severity::Recoverable, FaultSource::DiskMonitor) => FaultAction::Ignore,
+        };
+        match action {
+            FaultAction::Shutdown => error!("{}; shutting down", fault),
+            _ => warn!("{}; responding with {:?}", fault, action),
+        }
+        event_log::record("fault", json!({"source": format!("{:?}", fault.source), "severity": format!("{:?}", fault.severity), "message": fault.message, "action": format!("{:?}", action)}));
+        action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reconnects_with_backoff_until_the_limit() {
+        let mut supervisor = FaultSupervisor::new(2);
+        let dropped = Fault::recoverable(FaultSource::Connector, "connection closed");
+        assert_eq!(supervisor.decide(&dropped), FaultAction::Reconnect(Duration::from_secs(1)));
+        assert_eq!(supervisor.decide(&dropped), FaultAction::Reconnect(Duration::from_secs(2)));
+        assert_eq!(supervisor.decide(&dropped), FaultAction::Shutdown);
+        supervisor.connected();
+        assert_eq!(supervisor.decide(&dropped), FaultAction::Reconnect(Duration::from_secs(1)));
+        assert_eq!(supervisor.decide(&Fault::recoverable(FaultSource::Encoder, "write failed")), FaultAction::RotateVolume);
+        assert_eq!(supervisor.decide(&Fault::recoverable(FaultSource::DiskMonitor, "statvfs failed")), FaultAction::Ignore);
+        assert_eq!(supervisor.decide(&Fault::fatal(FaultSource::DiskMonitor, "disk full")), FaultAction::Shutdown);
+    }
+}
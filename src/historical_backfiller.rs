@@ -0,0 +1,247 @@
+use crate::data_archiver::{EncoderManager, SimulatedClock};
+use crate::event_log;
+use crate::solana_rest_api_functions::{self, extract_result};
+use chrono::{DateTime, TimeZone, Utc};
+use futures::stream::{self, StreamExt};
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const DEFAULT_BACKFILL_CONCURRENCY: usize = 8;
+pub const DEFAULT_CHECKPOINT_FILE: &str = "backfill_checkpoint.json";
+const BACKFILL_VOLUME_STATE_FILE: &str = "backfill_volume_state.json";
+const SLOTS_PER_GET_BLOCKS_REQUEST: u64 = 1_000;
+const MAX_GET_BLOCK_ATTEMPTS: u32 = 5;
+const SLOT_SEARCH_MAX_SKIPPED_SLOTS: u64 = 100;
+
+pub struct BackfillRange {
+    pub start_slot: u64,
+    pub end_slot: u64,
+}
+
+/// Progress of a backfill run, persisted so that an interrupted run can pick up where it left off.
+/// `last_completed_slot` only advances once every block up to it is in a finalized volume.
+pub struct BackfillCheckpoint {
+    pub start_slot: u64,
+    pub end_slot: u64,
+    pub last_completed_slot: Option<u64>,
+}
+
+impl BackfillCheckpoint {
+    pub fn load(path: &Path) -> Result<Option<Self>, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        let start_slot = contents["start_slot"].as_u64().ok_or("Checkpoint is missing start_slot")?;
+        let end_slot = contents["end_slot"].as_u64().ok_or("Checkpoint is missing end_slot")?;
+        Ok(Some(Self { start_slot, end_slot, last_completed_slot: contents["last_completed_slot"].as_u64() }))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let contents = json!({
+            "start_slot": self.start_slot,
+            "end_slot": self.end_slot,
+            "last_completed_slot": self.last_completed_slot,
+        });
+        let temp_path = path.with_extension("json.temp");
+        std::fs::write(&temp_path, serde_json::to_string_pretty(&contents)?)?;
+        std::fs::rename(&temp_path, path)?; // Rename so a crash never leaves a half-written checkpoint
+        Ok(())
+    }
+}
+
+/// Wraps a `getBlock` result in the same `blockNotification` envelope that `blockSubscribe` delivers,
+/// so backfilled volumes are indistinguishable from live ones for downstream consumers.
+pub fn block_notification_message(slot: u64, block: Value) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "blockNotification",
+        "params": {
+            "result": {
+                "context": {"slot": slot},
+                "value": {"slot": slot, "block": block, "err": null}
+            },
+            "subscription": 0
+        }
+    })
+    .to_string()
+}
+
+/// Fetches a block from the JSON-RPC endpoint at `rpc_url`, retrying transient failures.
+pub async fn fetch_block(rpc_url: &str, slot: u64) -> Result<Option<Value>, Box<dyn Error>> {
+    let params = json!([slot, {"encoding": "base64", "transactionDetails": "full", "rewards": true, "maxSupportedTransactionVersion": 0, "commitment": "finalized"}]);
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match solana_rest_api_functions::post_request_to(rpc_url, "getBlock", params.clone()).await.and_then(extract_result) {
+            Ok(Value::Null) => return Ok(None), // The slot was skipped or is no longer available
+            Ok(block) => return Ok(Some(block)),
+            Err(err) if attempt < MAX_GET_BLOCK_ATTEMPTS => {
+                warn!("Error fetching block {} (attempt {}/{}): {}", slot, attempt, MAX_GET_BLOCK_ATTEMPTS, err);
+                tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+            }
+            Err(err) => return Err(format!("Failed to fetch block {}: {}", slot, err).into()),
+        }
+    }
+}
+
+async fn fetch_block_slots(rpc_url: &str, start_slot: u64, end_slot: u64) -> Result<Vec<u64>, Box<dyn Error>> {
+    let result = extract_result(solana_rest_api_functions::post_request_to(rpc_url, "getBlocks", json!([start_slot, end_slot])).await?)?;
+    let slots = result.as_array().ok_or("getBlocks did not return an array")?;
+    Ok(slots.iter().filter_map(|slot| slot.as_u64()).collect())
+}
+
+async fn fetch_block_time(rpc_url: &str, slot: u64) -> Option<DateTime<Utc>> {
+    let response = solana_rest_api_functions::post_request_to(rpc_url, "getBlockTime", json!([slot])).await.ok()?;
+    let timestamp = extract_result(response).ok()?.as_i64()?;
+    Utc.timestamp_opt(timestamp, 0).single()
+}
+
+/// Binary searches the JSON-RPC endpoint at `rpc_url` for the first slot whose block time is at or after
+/// `target`. Skipped slots have no block time, so each probe walks forward to the next slot that has one.
+pub async fn find_first_slot_at_or_after(rpc_url: &str, target: DateTime<Utc>) -> Result<u64, Box<dyn Error>> {
+    let mut low = extract_result(solana_rest_api_functions::post_request_to(rpc_url, "getFirstAvailableBlock", json!({})).await?)?.as_u64().ok_or("Invalid first available block")?;
+    let mut high = extract_result(solana_rest_api_functions::post_request_to(rpc_url, "getSlot", json!({})).await?)?.as_u64().ok_or("Invalid current slot")?;
+    while low < high {
+        let mid = low + (high - low) / 2;
+        let mut probe = mid;
+        let mut probe_time = None;
+        while probe < high && probe - mid < SLOT_SEARCH_MAX_SKIPPED_SLOTS {
+            if let Some(block_time) = fetch_block_time(rpc_url, probe).await {
+                probe_time = Some(block_time);
+                break;
+            }
+            probe += 1;
+        }
+        match probe_time {
+            Some(block_time) if block_time < target => low = probe + 1,
+            _ => high = mid,
+        }
+    }
+    Ok(low)
+}
+
+/// Resolves a time range to the slots that cover it, using the JSON-RPC endpoint at `rpc_url`.
+pub async fn resolve_time_range(rpc_url: &str, start_time: DateTime<Utc>, end_time: DateTime<Utc>) -> Result<BackfillRange, Box<dyn Error>> {
+    let start_slot = find_first_slot_at_or_after(rpc_url, start_time).await?;
+    let end_slot = find_first_slot_at_or_after(rpc_url, end_time).await?.saturating_sub(1).max(start_slot);
+    info!("Resolved time range {} to {} to slots {} through {}", start_time, end_time, start_slot, end_slot);
+    Ok(BackfillRange { start_slot, end_slot })
+}
+
+/// Writes blocks, in slot order, into volumes bucketed by each block's time, which drives the encoder's
+/// simulated clock. Shared by backfills and Old Faithful conversions so both produce volumes identical to
+/// the live ingester's.
+pub struct BlockArchiver {
+    archive_dir: PathBuf,
+    minutes_per_bucket: i64,
+    state_file_name: String,
+    manifest_details: Value,
+    epoch_naming: Option<u64>,
+    clock: SimulatedClock,
+    encoder_manager: Option<EncoderManager>,
+    last_block_time: Option<DateTime<Utc>>,
+    last_written_slot: Option<u64>,
+}
+
+impl BlockArchiver {
+    pub fn new(archive_dir: &Path, minutes_per_bucket: i64, state_file_name: &str, manifest_details: Value) -> Self {
+        Self {
+            archive_dir: archive_dir.to_path_buf(),
+            minutes_per_bucket,
+            state_file_name: state_file_name.to_string(),
+            manifest_details,
+            epoch_naming: None,
+            clock: SimulatedClock::new(Utc::now()),
+            encoder_manager: None,
+            last_block_time: None,
+            last_written_slot: None,
+        }
+    }
+
+    /// Names volumes by `epoch` and the slots they hold; see `EncoderManager::set_epoch_naming`.
+    pub fn set_epoch_naming(&mut self, epoch: u64) {
+        self.epoch_naming = Some(epoch);
+    }
+
+    /// Appends a block. If that finalized the previous volume, returns the last slot it holds.
+    pub fn archive_block(&mut self, slot: u64, block: Value) -> Result<Option<u64>, Box<dyn Error>> {
+        let block_time = block["blockTime"].as_i64().and_then(|timestamp| Utc.timestamp_opt(timestamp, 0).single()).or(self.last_block_time).unwrap_or_else(Utc::now);
+        self.last_block_time = Some(block_time);
+        self.clock.set(block_time);
+        let manager = match self.encoder_manager.as_mut() {
+            Some(manager) => manager,
+            None => {
+                // Created on the first block so that its bucket is the one the block's time falls in
+                let mut manager = EncoderManager::with_clock(self.minutes_per_bucket, &self.archive_dir, Box::new(self.clock.clone()))?;
+                manager.set_state_file_name(&self.state_file_name);
+                manager.set_manifest_details(self.manifest_details.clone());
+                if let Some(epoch) = self.epoch_naming {
+                    manager.set_epoch_naming(epoch);
+                }
+                self.encoder_manager.insert(manager)
+            }
+        };
+        let volume_before = (manager.bucket_start_time(), manager.volume());
+        manager.process_message(block_notification_message(slot, block))?;
+        let finalized = (manager.bucket_start_time(), manager.volume()) != volume_before;
+        let completed_slot = if finalized { self.last_written_slot } else { None };
+        self.last_written_slot = Some(slot);
+        Ok(completed_slot)
+    }
+
+    /// Finalizes the open volume, so the next block starts a new one.
+    pub fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(manager) = self.encoder_manager.as_mut() {
+            manager.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// Archives every block in `range`, fetched from the JSON-RPC endpoint at `rpc_url`, into volumes with the
+/// same format and naming as the live ingester.
+/// Blocks are fetched `concurrency` at a time but written strictly in slot order and bucketed by their
+/// block time, which drives the encoder's simulated clock. Progress is checkpointed to `checkpoint_path`
+/// each time a volume is finalized.
+pub async fn run_backfill(rpc_url: &str, range: BackfillRange, archive_dir: &Path, minutes_per_bucket: i64, concurrency: usize, checkpoint_path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut checkpoint = match BackfillCheckpoint::load(checkpoint_path)? {
+        Some(existing) if existing.start_slot == range.start_slot && existing.end_slot == range.end_slot => existing,
+        Some(_) => return Err(format!("Checkpoint {} belongs to a different slot range; remove it or choose another checkpoint file", checkpoint_path.display()).into()),
+        None => BackfillCheckpoint { start_slot: range.start_slot, end_slot: range.end_slot, last_completed_slot: None },
+    };
+    let resume_slot = checkpoint.last_completed_slot.map_or(range.start_slot, |slot| slot + 1);
+    if resume_slot > range.end_slot {
+        info!("Backfill of slots {} through {} is already complete", range.start_slot, range.end_slot);
+        return Ok(());
+    }
+    info!("Backfilling slots {} through {} with concurrency {}...", resume_slot, range.end_slot, concurrency);
+    event_log::record("backfill_started", json!({"start_slot": range.start_slot, "end_slot": range.end_slot, "resume_slot": resume_slot, "concurrency": concurrency}));
+    let mut archiver = BlockArchiver::new(archive_dir, minutes_per_bucket, BACKFILL_VOLUME_STATE_FILE, json!({"source": "backfill"}));
+    let mut chunk_start = resume_slot;
+    while chunk_start <= range.end_slot {
+        let chunk_end = (chunk_start + SLOTS_PER_GET_BLOCKS_REQUEST - 1).min(range.end_slot);
+        let slots = fetch_block_slots(rpc_url, chunk_start, chunk_end).await?;
+        let mut blocks = stream::iter(slots.into_iter().map(|slot| async move { (slot, fetch_block(rpc_url, slot).await) })).buffered(concurrency.max(1));
+        while let Some((slot, block)) = blocks.next().await {
+            let Some(block) = block? else {
+                warn!("Block {} is not available; skipping", slot);
+                continue;
+            };
+            if let Some(completed_slot) = archiver.archive_block(slot, block)? { // The previous volume was just finalized
+                checkpoint.last_completed_slot = Some(completed_slot);
+                checkpoint.save(checkpoint_path)?;
+            }
+        }
+        chunk_start = chunk_end + 1;
+    }
+    archiver.finish()?;
+    checkpoint.last_completed_slot = Some(range.end_slot);
+    checkpoint.save(checkpoint_path)?;
+    info!("Backfill of slots {} through {} complete", range.start_slot, range.end_slot);
+    event_log::record("backfill_finished", json!({"start_slot": range.start_slot, "end_slot": range.end_slot}));
+    Ok(())
+}
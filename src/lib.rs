@@ -0,0 +1,26 @@
+pub mod admin_server;
+pub mod archive_query_server;
+pub mod archive_replay_server;
+pub mod archive_rpc_server;
+pub mod archive_verifier;
+pub mod cascade_uploader;
+pub mod data_archiver;
+pub mod disk_monitor;
+pub mod event_log;
+pub mod faithful_cli_supervisor;
+pub mod fault_supervisor;
+pub mod historical_backfiller;
+pub mod logging_config;
+pub mod old_faithful_car;
+pub mod old_faithful_converter;
+pub mod old_faithful_epochs;
+pub mod old_faithful_files;
+pub mod old_faithful_index;
+pub mod resumable_downloader;
+pub mod retention_manager;
+pub mod solana_connector;
+pub mod solana_rest_api_functions;
+#[cfg(feature = "transaction-submission")]
+pub mod solana_rest_api_write_functions;
+pub mod volume_catalog;
+pub mod volume_extractor;
@@ -0,0 +1,187 @@
+use log::LevelFilter;
+use log4rs::append::console::ConsoleAppender;
+use log4rs::append::rolling_file::{policy::compound, RollingFileAppender};
+use log4rs::config::{Appender, Config, Deserializers, Logger, RawConfig, Root};
+use log4rs::encode::json::JsonEncoder;
+use log4rs::encode::pattern::PatternEncoder;
+use log4rs::encode::Encode;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_LOG_CONFIG_FILE: &str = "log4rs.yaml"; // Used if present and LOG_CONFIG_FILE is not set
+const LOG_FILE: &str = "pastel_solana_archival_data_ingester.log";
+const LOG_BACKUP_DIRECTORY: &str = "log_file_backups";
+const LOG_BACKUP_COUNT: u32 = 10;
+const MAX_LOG_FILE_SIZE: u64 = 25 * 1024 * 1024; // 25MB
+
+/// Where logging is configured from, plus the level changes made at runtime on top of it. The whole
+/// log4rs config is rebuilt from this each time a level changes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoggingSettings {
+    /// A log4rs YAML file. Without one, logs go to the console and a rolling file, as before.
+    pub config_file: Option<PathBuf>,
+    /// Whether the built-in rolling file appender writes JSON objects instead of text lines.
+    pub json: bool,
+    /// Root level for the built-in config; a config file sets its own unless overridden.
+    pub root_level: LevelFilter,
+    pub root_level_override: Option<LevelFilter>,
+    /// Module (logger name) levels, on top of the loggers in the config file.
+    pub module_levels: BTreeMap<String, LevelFilter>,
+}
+
+impl Default for LoggingSettings {
+    fn default() -> Self {
+        Self { config_file: None, json: false, root_level: LevelFilter::Debug, root_level_override: None, module_levels: BTreeMap::new() }
+    }
+}
+
+pub fn parse_level(level: &str) -> Result<LevelFilter, Box<dyn Error>> {
+    level.parse().map_err(|_| format!("Invalid log level: {}", level).into())
+}
+
+impl LoggingSettings {
+    /// Reads `LOG_CONFIG_FILE` (defaulting to `log4rs.yaml` if it exists), `LOG_LEVEL`, `LOG_FORMAT`
+    /// ("json" or "text") and `LOG_LEVELS`, a comma-separated list of `module=level` pairs.
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let config_file = match std::env::var("LOG_CONFIG_FILE") {
+            Ok(path) => Some(PathBuf::from(path)),
+            Err(_) => Some(PathBuf::from(DEFAULT_LOG_CONFIG_FILE)).filter(|path| path.exists()),
+        };
+        let mut settings = Self { config_file, ..Self::default() };
+        if let Ok(level) = std::env::var("LOG_LEVEL") {
+            settings.root_level = parse_level(&level)?;
+            settings.root_level_override = Some(settings.root_level);
+        }
+        settings.json = match std::env::var("LOG_FORMAT").as_deref() {
+            Ok("json") => true,
+            Ok("text") | Err(_) => false,
+            Ok(other) => return Err(format!("Invalid value for LOG_FORMAT: {}", other).into()),
+        };
+        for pair in std::env::var("LOG_LEVELS").unwrap_or_default().split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (module, level) = pair.split_once('=').ok_or_else(|| format!("Invalid entry in LOG_LEVELS: {}", pair))?;
+            settings.module_levels.insert(module.trim().to_string(), parse_level(level.trim())?);
+        }
+        Ok(settings)
+    }
+
+    /// Sets the level of one module, or of the root logger when `module` is None.
+    pub fn set_level(&mut self, module: Option<&str>, level: LevelFilter) {
+        match module {
+            Some(module) => {
+                self.module_levels.insert(module.to_string(), level);
+            }
+            None => self.root_level_override = Some(level),
+        }
+    }
+
+    fn raw_config(path: &Path) -> Result<RawConfig, Box<dyn Error>> {
+        let contents = std::fs::read_to_string(path).map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+        Ok(serde_yaml::from_str(&contents).map_err(|err| format!("Invalid log config {}: {}", path.display(), err))?)
+    }
+
+    fn builtin_appenders(&self) -> Result<(Vec<Appender>, Vec<String>), Box<dyn Error>> {
+        std::fs::create_dir_all(LOG_BACKUP_DIRECTORY)?;
+        let roller = compound::roll::fixed_window::FixedWindowRoller::builder().build(&format!("{}/log.{{}}.gz", LOG_BACKUP_DIRECTORY), LOG_BACKUP_COUNT)?;
+        let policy = compound::CompoundPolicy::new(Box::new(compound::trigger::size::SizeTrigger::new(MAX_LOG_FILE_SIZE)), Box::new(roller));
+        let encoder: Box<dyn Encode> = if self.json { Box::new(JsonEncoder::new()) } else { Box::new(PatternEncoder::new("{d} - {l} - {m}\n")) };
+        let rolling_file = RollingFileAppender::builder().encoder(encoder).build(LOG_FILE, Box::new(policy))?;
+        let appenders = vec![
+            Appender::builder().build("rolling_file", Box::new(rolling_file)),
+            Appender::builder().build("console", Box::new(ConsoleAppender::builder().build())),
+        ];
+        Ok((appenders, vec!["rolling_file".to_string(), "console".to_string()]))
+    }
+
+    /// Builds the log4rs config: the config file's (or the built-in) appenders and loggers, with the
+    /// levels set here applied on top.
+    pub fn build(&self) -> Result<Config, Box<dyn Error>> {
+        let (appenders, root_appenders, root_level, mut loggers) = match &self.config_file {
+            Some(path) => {
+                let raw = Self::raw_config(path)?;
+                let (appenders, errors) = raw.appenders_lossy(&Deserializers::default());
+                if !errors.is_empty() {
+                    return Err(format!("Invalid log config {}: {}", path.display(), errors).into());
+                }
+                let root = raw.root();
+                (appenders, root.appenders().to_vec(), root.level(), raw.loggers())
+            }
+            None => {
+                let (appenders, root_appenders) = self.builtin_appenders()?;
+                (appenders, root_appenders, self.root_level, Vec::new())
+            }
+        };
+        for (module, level) in &self.module_levels {
+            let existing = loggers.iter().position(|logger| logger.name() == module).map(|index| loggers.remove(index));
+            let logger = match existing {
+                Some(existing) => Logger::builder().appenders(existing.appenders().to_vec()).additive(existing.additive()).build(module.clone(), *level),
+                None => Logger::builder().build(module.clone(), *level),
+            };
+            loggers.push(logger);
+        }
+        let root = Root::builder().appenders(root_appenders).build(self.root_level_override.unwrap_or(root_level));
+        Ok(Config::builder().appenders(appenders).loggers(loggers).build(root)?)
+    }
+}
+
+/// Owns the installed logger, so levels can be changed while running.
+pub struct LoggingController {
+    settings: LoggingSettings,
+    handle: log4rs::Handle,
+}
+
+impl LoggingController {
+    /// Installs the logger described by `settings`.
+    pub fn init(settings: LoggingSettings) -> Result<Self, Box<dyn Error>> {
+        let handle = log4rs::init_config(settings.build()?)?;
+        Ok(Self { settings, handle })
+    }
+
+    pub fn set_level(&mut self, module: Option<&str>, level: LevelFilter) -> Result<(), Box<dyn Error>> {
+        let mut settings = self.settings.clone();
+        settings.set_level(module, level);
+        self.handle.set_config(settings.build()?); // Only adopted if the config still builds
+        self.settings = settings;
+        Ok(())
+    }
+
+    /// The root level and every logger level in effect, as JSON.
+    pub fn levels(&self) -> Result<Value, Box<dyn Error>> {
+        let (root_level, mut modules) = match &self.settings.config_file {
+            Some(path) => {
+                let raw = LoggingSettings::raw_config(path)?;
+                (raw.root().level(), raw.loggers().iter().map(|logger| (logger.name().to_string(), logger.level())).collect())
+            }
+            None => (self.settings.root_level, BTreeMap::new()),
+        };
+        modules.extend(self.settings.module_levels.clone());
+        let root_level = self.settings.root_level_override.unwrap_or(root_level);
+        let modules: BTreeMap<String, String> = modules.into_iter().map(|(module, level)| (module, level.to_string().to_lowercase())).collect();
+        Ok(json!({"root": root_level.to_string().to_lowercase(), "modules": modules}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runtime_levels_apply_on_top_of_the_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log4rs.yaml");
+        std::fs::write(&path, "appenders:\n  stdout:\n    kind: console\nroot:\n  level: info\n  appenders: [stdout]\nloggers:\n  reqwest:\n    level: warn\n    appenders: [stdout]\n    additive: false\n").unwrap();
+        let mut settings = LoggingSettings { config_file: Some(path), ..LoggingSettings::default() };
+        settings.set_level(Some("reqwest"), LevelFilter::Trace);
+        settings.set_level(Some("pastel_solana_archival_data_integration_api::cascade_uploader"), LevelFilter::Error);
+
+        let config = settings.build().unwrap();
+
+        assert_eq!(config.root().level(), LevelFilter::Info);
+        let reqwest = config.loggers().iter().find(|logger| logger.name() == "reqwest").unwrap();
+        assert_eq!((reqwest.level(), reqwest.additive(), reqwest.appenders()), (LevelFilter::Trace, false, &["stdout".to_string()][..]));
+        assert_eq!(config.loggers().len(), 2);
+        settings.set_level(None, LevelFilter::Off);
+        assert_eq!(settings.build().unwrap().root().level(), LevelFilter::Off);
+    }
+}
@@ -1,111 +1,236 @@
-mod data_archiver;
-mod solana_connector;
-
-use data_archiver::MessageDispatcher;
-use log::{info, debug, error, LevelFilter};
+use pastel_solana_archival_data_integration_api::admin_server;
+use pastel_solana_archival_data_integration_api::archive_query_server;
+use pastel_solana_archival_data_integration_api::archive_replay_server::{self, ReplayOptions, ReplayPace, ReplayStart, DEFAULT_REPLAY_LISTEN_ADDR};
+use pastel_solana_archival_data_integration_api::archive_rpc_server;
+use pastel_solana_archival_data_integration_api::cascade_uploader::{CascadeConfig, CascadeUploader};
+use pastel_solana_archival_data_integration_api::data_archiver::MessageDispatcher;
+use pastel_solana_archival_data_integration_api::disk_monitor::{DegradationLevel, DiskMonitor, DiskThresholds};
+use pastel_solana_archival_data_integration_api::event_log::{self, DEFAULT_EVENT_LOG_FILE};
+use pastel_solana_archival_data_integration_api::fault_supervisor::{Fault, FaultAction, FaultSource, FaultSupervisor};
+use pastel_solana_archival_data_integration_api::historical_backfiller::{self, BackfillRange, DEFAULT_BACKFILL_CONCURRENCY, DEFAULT_CHECKPOINT_FILE};
+use pastel_solana_archival_data_integration_api::logging_config::{LoggingController, LoggingSettings};
+use pastel_solana_archival_data_integration_api::retention_manager::{RetentionManager, RetentionPolicy};
+use pastel_solana_archival_data_integration_api::solana_connector::SolanaConnector;
+use pastel_solana_archival_data_integration_api::solana_rest_api_functions;
+use pastel_solana_archival_data_integration_api::volume_catalog::VolumeCatalog;
+use pastel_solana_archival_data_integration_api::volume_extractor::{self, ExtractFormat, ExtractSelection, DEFAULT_DEBUG_EXTRACT_DIRECTORY};
+use chrono::{DateTime, Utc};
+use log::{info, debug, error};
+use serde_json::json;
 use std::time::Duration;
-use std::fs;
 use tokio::signal::unix::{signal, SignalKind};
-use sysinfo::{System, SystemExt, DiskExt, CpuExt};
-use log4rs::append::rolling_file::{RollingFileAppender, policy::compound};
-use log4rs::config::{Appender, Config, Root};
-use log4rs::append::console::ConsoleAppender;
-use std::sync::atomic::{Ordering, AtomicBool};
-use std::sync::Arc;
-use std::fs::{create_dir_all, File, read_dir};
-use std::io::{BufReader, Result as IOResult};
-use std::path::Path;
-use zstd::stream::read::Decoder;
+use sysinfo::{System, SystemExt, CpuExt};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 const USE_VERBOSE_LOGGING: bool = true;
-const DECOMPRESS_MESSAGE_BLOBS_FOR_DEBUGGING: bool = true; // Change to false to disable
 const BUFFER_MESSAGE_COUNT: usize = 100_000;
 const MINUTES_PER_BUCKET: i64 = 1;
 const MAX_RECONNECT_ATTEMPTS: u64 = 10;
+const ARCHIVE_DIRECTORY: &str = ".";
 const DISK_SPACE_THRESHOLD: u64 = 1024 * 1024 * 1024; // 1 GB
+const DEFAULT_SHUTDOWN_DEADLINE: Duration = Duration::from_secs(30); // Override with SHUTDOWN_DEADLINE_SECS
 
-pub fn decompress_zstd_files() -> IOResult<()> {
-    if !DECOMPRESS_MESSAGE_BLOBS_FOR_DEBUGGING {
-        return Ok(()); // Skip decompression if disabled
-    }
-    let output_dir = "extracted_compressed_message_blobs";
-    create_dir_all(output_dir)?;
-    for entry in read_dir(".")? {
-        let entry = entry?;
-        let path = entry.path();
-        if path.is_file() && path.extension() == Some(std::ffi::OsStr::new("zstd")) {
-            let file_stem = path.file_stem().unwrap().to_str().unwrap();
-            if file_stem.ends_with(".temp") {
-                continue; // Skip temporary files
-            }
-            let output_file_name = format!("{}/{}", output_dir, file_stem);
-            // Skip if the output file already exists
-            if Path::new(&output_file_name).exists() {
-                continue;
-            }
-            let compressed_file = File::open(&path)?;
-            let mut decompressor = Decoder::new(BufReader::new(compressed_file))?;
-            let mut output_file = File::create(&output_file_name)?;
-            std::io::copy(&mut decompressor, &mut output_file)?;
-            info!("Successfully decompressed {} into {}", path.display(), output_file_name);
-        }
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter().position(|arg| arg == flag).and_then(|index| args.get(index + 1)).map(|value| value.as_str())
+}
+
+fn parse_flag<T: std::str::FromStr>(args: &[String], flag: &str) -> Result<Option<T>, Box<dyn std::error::Error>> {
+    match flag_value(args, flag) {
+        Some(value) => value.parse().map(Some).map_err(|_| format!("Invalid value for {}: {}", flag, value).into()),
+        None => Ok(None),
+    }
+}
+
+// Usage: backfill (--start-slot N --end-slot N | --start-time RFC3339 --end-time RFC3339) [--concurrency N] [--checkpoint FILE]
+async fn run_backfill_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let rpc_url = solana_rest_api_functions::rpc_url();
+    let range = match (parse_flag::<u64>(args, "--start-slot")?, parse_flag::<u64>(args, "--end-slot")?) {
+        (Some(start_slot), Some(end_slot)) if start_slot <= end_slot => BackfillRange { start_slot, end_slot },
+        (Some(_), Some(_)) => return Err("--start-slot must not be after --end-slot".into()),
+        _ => match (parse_flag::<DateTime<Utc>>(args, "--start-time")?, parse_flag::<DateTime<Utc>>(args, "--end-time")?) {
+            (Some(start_time), Some(end_time)) if start_time < end_time => historical_backfiller::resolve_time_range(&rpc_url, start_time, end_time).await?,
+            (Some(_), Some(_)) => return Err("--start-time must be before --end-time".into()),
+            _ => return Err("backfill requires either --start-slot and --end-slot, or --start-time and --end-time".into()),
+        },
+    };
+    let concurrency = parse_flag(args, "--concurrency")?.unwrap_or(DEFAULT_BACKFILL_CONCURRENCY);
+    let checkpoint_path = Path::new(flag_value(args, "--checkpoint").unwrap_or(DEFAULT_CHECKPOINT_FILE));
+    historical_backfiller::run_backfill(&rpc_url, range, Path::new(ARCHIVE_DIRECTORY), MINUTES_PER_BUCKET, concurrency, checkpoint_path).await
+}
+
+// Usage: extract (--volume FILE | --start-slot N --end-slot N | --start-time RFC3339 --end-time RFC3339) [--format ndjson|pretty] [--output FILE]
+// Writes the selected archived messages to FILE, or to stdout.
+fn run_extract_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let selection = match (flag_value(args, "--volume"), parse_flag::<u64>(args, "--start-slot")?, parse_flag::<u64>(args, "--end-slot")?) {
+        (Some(volume), _, _) => ExtractSelection::Volume(PathBuf::from(volume)),
+        (None, Some(first_slot), Some(last_slot)) if first_slot <= last_slot => ExtractSelection::Slots { first_slot, last_slot },
+        (None, Some(_), Some(_)) => return Err("--start-slot must not be after --end-slot".into()),
+        _ => match (parse_flag::<DateTime<Utc>>(args, "--start-time")?, parse_flag::<DateTime<Utc>>(args, "--end-time")?) {
+            (Some(start), Some(end)) if start < end => ExtractSelection::Time { start, end },
+            (Some(_), Some(_)) => return Err("--start-time must be before --end-time".into()),
+            _ => return Err("extract requires --volume, --start-slot and --end-slot, or --start-time and --end-time".into()),
+        },
+    };
+    let format = match flag_value(args, "--format").unwrap_or("ndjson") {
+        "ndjson" => ExtractFormat::Ndjson,
+        "pretty" => ExtractFormat::Pretty,
+        other => return Err(format!("Unknown --format {}; use ndjson or pretty", other).into()),
+    };
+    let extracted = match flag_value(args, "--output") {
+        Some(output) => volume_extractor::extract(Path::new(ARCHIVE_DIRECTORY), &selection, format, &mut File::create(output)?)?,
+        None => volume_extractor::extract(Path::new(ARCHIVE_DIRECTORY), &selection, format, &mut std::io::stdout().lock())?,
+    };
+    info!("Extracted {} messages", extracted);
+    Ok(())
+}
+
+// Usage: replay [--listen ADDR] [--start-slot N | --start-time RFC3339] [--pace realtime|max|FACTOR]
+// Serves the archive as a PubSub WebSocket endpoint until interrupted.
+async fn run_replay_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let listen_addr = parse_flag(args, "--listen")?.unwrap_or(DEFAULT_REPLAY_LISTEN_ADDR.parse()?);
+    let start = match (parse_flag(args, "--start-slot")?, parse_flag(args, "--start-time")?) {
+        (Some(slot), None) => ReplayStart::Slot(slot),
+        (None, Some(time)) => ReplayStart::Time(time),
+        (None, None) => ReplayStart::Beginning,
+        (Some(_), Some(_)) => return Err("replay takes --start-slot or --start-time, not both".into()),
+    };
+    let pace = flag_value(args, "--pace").unwrap_or("realtime").parse::<ReplayPace>()?;
+    let (addr, server) = archive_replay_server::bind(listen_addr, PathBuf::from(ARCHIVE_DIRECTORY), ReplayOptions { start, pace }).await?;
+    info!("Replaying the archive from {:?} at {:?} pace on ws://{}", start, pace, addr);
+    tokio::select! {
+        _ = server => {}
+        _ = tokio::signal::ctrl_c() => info!("Replay server stopped"),
     }
     Ok(())
 }
 
+// Usage: locate --slot N
+// Lists the volumes that may hold data for the slot, from the archive catalog.
+fn run_locate_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let slot: u64 = parse_flag(args, "--slot")?.ok_or("locate requires --slot")?;
+    let catalog = VolumeCatalog::open_in(Path::new(ARCHIVE_DIRECTORY))?;
+    catalog.sync_directory(Path::new(ARCHIVE_DIRECTORY))?;
+    for volume in catalog.volumes_for_slots(slot, slot)? {
+        let location = match (volume.deletion_state.as_str(), &volume.cascade_txid) {
+            ("present", _) => volume.path.display().to_string(),
+            (_, Some(txid)) => format!("Cascade registration ticket {}", txid),
+            (state, None) => format!("{} locally, not on Cascade", state),
+        };
+        println!("{}\t{}\tslots {:?}-{:?}\t{}", volume.file_name, volume.stream, volume.first_slot, volume.last_slot, location);
+    }
+    Ok(())
+}
+
+/// Opens the PubSub connection, reporting a failure as a connector fault so the supervisor retries it.
+async fn connect(message_dispatcher: &MessageDispatcher, supervisor: &mut FaultSupervisor) -> Option<SolanaConnector> {
+    match SolanaConnector::new(message_dispatcher.clone_tx(), message_dispatcher.fault_sender(), USE_VERBOSE_LOGGING).await {
+        Ok(connector) => {
+            supervisor.connected();
+            Some(connector)
+        }
+        Err(err) => {
+            message_dispatcher.fault_sender().send(Fault::recoverable(FaultSource::Connector, format!("Error initializing Solana connector: {}", err))).ok();
+            None
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let window_size = 10;
-    fs::create_dir_all("log_file_backups")?;
-    let fixed_window_roller = compound::roll::fixed_window::FixedWindowRoller::builder()
-        .build("log_file_backups/log.{}.gz", window_size)?;
-    let size_trigger = compound::trigger::size::SizeTrigger::new(25 * 1024 * 1024);
-    let compound_policy = compound::CompoundPolicy::new(Box::new(size_trigger), Box::new(fixed_window_roller));
-    let rolling_file = RollingFileAppender::builder()
-        .encoder(Box::new(log4rs::encode::pattern::PatternEncoder::new("{d} - {l} - {m}\n")))
-        .build("pastel_solana_archival_data_ingester.log", Box::new(compound_policy))?;
-    let console = ConsoleAppender::builder().build();
-    let config = Config::builder()
-        .appender(Appender::builder().build("rolling_file", Box::new(rolling_file)))
-        .appender(Appender::builder().build("console", Box::new(console)))
-        .build(Root::builder().appender("rolling_file").appender("console").build(LevelFilter::Debug))?;
-    log4rs::init_config(config)?;
+    let args: Vec<String> = std::env::args().collect();
+    let logging = Arc::new(Mutex::new(LoggingController::init(LoggingSettings::from_env()?)?));
+    event_log::init(Path::new(&std::env::var("EVENT_LOG_FILE").unwrap_or_else(|_| DEFAULT_EVENT_LOG_FILE.to_string())))?;
+    if args.get(1).map(|command| command.as_str()) == Some("backfill") {
+        info!("Starting Pastel Solana historical backfill...");
+        return run_backfill_command(&args[2..]).await;
+    }
+    if args.get(1).map(|command| command.as_str()) == Some("locate") {
+        return run_locate_command(&args[2..]);
+    }
+    if args.get(1).map(|command| command.as_str()) == Some("replay") {
+        return run_replay_command(&args[2..]).await;
+    }
+    if args.get(1).map(|command| command.as_str()) == Some("extract") {
+        return run_extract_command(&args[2..]);
+    }
     info!("Starting Pastel Solana Data Ingester...");
+    if let Ok(admin_addr) = std::env::var("ADMIN_LISTEN_ADDR") {
+        let admin_addr = admin_addr.parse().map_err(|_| format!("Invalid value for ADMIN_LISTEN_ADDR: {}", admin_addr))?;
+        let (admin_addr, admin_server) = admin_server::bind(admin_addr, logging.clone())?;
+        info!("Admin endpoint listening on http://{}", admin_addr);
+        tokio::spawn(admin_server);
+    }
+    if let Ok(query_addr) = std::env::var("QUERY_LISTEN_ADDR") {
+        let query_addr = query_addr.parse().map_err(|_| format!("Invalid value for QUERY_LISTEN_ADDR: {}", query_addr))?;
+        let (query_addr, query_server) = archive_query_server::bind(query_addr, PathBuf::from(ARCHIVE_DIRECTORY))?;
+        info!("Archive query API listening on http://{}", query_addr);
+        tokio::spawn(query_server);
+    }
+    if let Ok(rpc_addr) = std::env::var("RPC_LISTEN_ADDR") {
+        let rpc_addr = rpc_addr.parse().map_err(|_| format!("Invalid value for RPC_LISTEN_ADDR: {}", rpc_addr))?;
+        let (rpc_addr, rpc_server) = archive_rpc_server::bind(rpc_addr, PathBuf::from(ARCHIVE_DIRECTORY))?;
+        info!("Archive JSON-RPC endpoint listening on http://{}", rpc_addr);
+        tokio::spawn(rpc_server);
+    }
+    event_log::record("ingester_started", json!({"archive_directory": ARCHIVE_DIRECTORY, "minutes_per_bucket": MINUTES_PER_BUCKET}));
+    let shutdown_deadline = match std::env::var("SHUTDOWN_DEADLINE_SECS") {
+        Ok(value) => Duration::from_secs(value.parse().map_err(|_| format!("Invalid value for SHUTDOWN_DEADLINE_SECS: {}", value))?),
+        Err(_) => DEFAULT_SHUTDOWN_DEADLINE,
+    };
+    let mut background_tasks = Vec::new();
     let mut sys = System::new_all();
-    let insufficient_disk_space = Arc::new(AtomicBool::new(false));
-    tokio::spawn({
-        let insufficient_disk_space = insufficient_disk_space.clone();
-        async move {
-            loop {
-                sys.refresh_all(); // Refresh the system info
-                let cpu_usage: f32 = sys.cpus().iter().map(|cpu| cpu.cpu_usage()).sum();
-                let cpu_usage = cpu_usage / sys.cpus().len() as f32;
-                // Filter disks based on mount point starting with "/dev/"
-                let disk_usage: u64 = sys.disks().iter()
-                .filter(|d| d.mount_point().to_str().map_or(false, |s| s == "/"))
-                .map(|d| d.available_space())
-                .sum();
-                let disk_usage_gb = disk_usage as f64 / 1024.0 / 1024.0 / 1024.0; // Convert bytes to gigabytes
-                debug!("CPU Usage: {}%, Disk Available Space: {} gb", cpu_usage, disk_usage_gb);
-                if disk_usage < DISK_SPACE_THRESHOLD {
-                    insufficient_disk_space.store(true, Ordering::Relaxed);
-                    error!("Insufficient disk space. Exiting...");
-                    break;
-                }
-                tokio::time::sleep(Duration::from_secs(5)).await;
-            }
+    background_tasks.push(tokio::spawn(async move {
+        loop {
+            sys.refresh_cpu();
+            let cpu_usage: f32 = sys.cpus().iter().map(|cpu| cpu.cpu_usage()).sum();
+            debug!("CPU Usage: {}%", cpu_usage / sys.cpus().len() as f32);
+            tokio::time::sleep(Duration::from_secs(5)).await;
         }
-    });
-    if DECOMPRESS_MESSAGE_BLOBS_FOR_DEBUGGING { // Spawn a task to periodically decompress Zstd files if the option is enabled
-        tokio::spawn(async {
+    }));
+    let (mut message_dispatcher, mut faults) = MessageDispatcher::new(BUFFER_MESSAGE_COUNT, MINUTES_PER_BUCKET, Path::new(ARCHIVE_DIRECTORY));
+    let mut disk_monitor = DiskMonitor::new(Path::new(ARCHIVE_DIRECTORY), DiskThresholds { exhausted_below: DISK_SPACE_THRESHOLD, ..DiskThresholds::default() });
+    let degradation = disk_monitor.subscribe();
+    let (free_space_tx, free_space_rx) = tokio::sync::mpsc::unbounded_channel();
+    disk_monitor.set_free_space_requests(free_space_tx);
+    disk_monitor.set_fault_sender(message_dispatcher.fault_sender());
+    disk_monitor.check()?;
+    background_tasks.push(tokio::spawn(disk_monitor.run()));
+    if std::env::var("DECOMPRESS_VOLUMES_FOR_DEBUGGING").is_ok_and(|value| value == "true" || value == "1") {
+        info!("Decompressing every finalized volume into {} for debugging", DEFAULT_DEBUG_EXTRACT_DIRECTORY);
+        let degradation = degradation.clone();
+        background_tasks.push(tokio::spawn(async move {
             loop {
-                if let Err(err) = decompress_zstd_files() {
+                if *degradation.borrow() >= DegradationLevel::Conserving {
+                    debug!("Skipping debug decompression while disk space is low");
+                } else if let Err(err) = volume_extractor::decompress_new_volumes(Path::new(ARCHIVE_DIRECTORY), Path::new(DEFAULT_DEBUG_EXTRACT_DIRECTORY)) {
                     error!("Error decompressing Zstd files: {}", err);
                 }
                 tokio::time::sleep(Duration::from_secs(60)).await; // Check every 60 seconds
             }
-        });
+        }));
+    }
+    let (finalized_volume_tx, finalized_volume_rx) = tokio::sync::mpsc::unbounded_channel();
+    match CascadeConfig::from_env() {
+        Some(cascade_config) => {
+            info!("Uploading finalized volumes to Cascade via {}", cascade_config.endpoint);
+            let uploader = CascadeUploader::new(cascade_config, Path::new(ARCHIVE_DIRECTORY), VolumeCatalog::open_in(Path::new(ARCHIVE_DIRECTORY))?);
+            background_tasks.push(tokio::spawn(uploader.run(finalized_volume_rx)));
+        }
+        None => info!("CASCADE_ENDPOINT is not set; volumes will not be uploaded to Cascade"),
     }
+    let retention_policy = match RetentionPolicy::from_env()? {
+        Some(policy) => {
+            info!("Evicting uploaded volumes with retention policy {:?}", policy);
+            policy
+        }
+        None => {
+            info!("No retention policy is set; uploaded volumes are only evicted when disk space runs low");
+            RetentionPolicy::default()
+        }
+    };
+    background_tasks.push(tokio::spawn(RetentionManager::new(retention_policy, Path::new(ARCHIVE_DIRECTORY), VolumeCatalog::open_in(Path::new(ARCHIVE_DIRECTORY))?).run(free_space_rx)));
     let (shutdown_tx, mut shutdown_rx) = tokio::sync::oneshot::channel();
     let mut term_signal = signal(SignalKind::terminate())?;
     let mut int_signal = signal(SignalKind::interrupt())?;
@@ -116,34 +241,74 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
         shutdown_tx.send(()).ok();
     });
-    let mut reconnect_attempts = 0;
+    message_dispatcher.set_finalized_volume_sender(finalized_volume_tx);
+    message_dispatcher.set_degradation(degradation);
+    let mut supervisor = FaultSupervisor::new(MAX_RECONNECT_ATTEMPTS);
+    let mut connector = connect(&message_dispatcher, &mut supervisor).await;
+    let mut fatal_fault = None;
+    let mut reconnect_at = None; // Set while waiting to reconnect, so signals and faults are still handled meanwhile
     loop {
-        if reconnect_attempts >= MAX_RECONNECT_ATTEMPTS || insufficient_disk_space.load(Ordering::Relaxed) {
-            break;
-        }
-        let (mut message_dispatcher, _) = MessageDispatcher::new(BUFFER_MESSAGE_COUNT, MINUTES_PER_BUCKET);
-        if solana_connector::SolanaConnector::new(message_dispatcher.clone_tx(), USE_VERBOSE_LOGGING).await.is_err() {
-            info!("Error initializing Solana connector. Reconnecting in {} seconds...", reconnect_attempts + 1);
-            reconnect_attempts += 1;
-        } else {
-            reconnect_attempts = 0;
-        }
-        loop {
-            tokio::select! {
-                msg = message_dispatcher.receive_message() => {
-                    if let Some(msg) = msg {
-                        if let Err(err) = message_dispatcher.process_message(msg) {
-                            error!("Error processing message: {}", err);
-                        }
+        tokio::select! {
+            msg = message_dispatcher.receive_message() => {
+                if let Some(msg) = msg {
+                    message_dispatcher.process_message(msg).ok(); // Failures arrive as encoder faults
+                }
+            }
+            _ = &mut shutdown_rx => {
+                info!("Gracefully shutting down...");
+                break;
+            }
+            Some(fault) = faults.recv() => match supervisor.decide(&fault) {
+                FaultAction::Ignore => {}
+                FaultAction::Reconnect(delay) => {
+                    if let Some(connector) = connector.take() {
+                        connector.shutdown().await;
+                    }
+                    info!("Reconnecting to Solana in {} seconds...", delay.as_secs());
+                    reconnect_at = Some(tokio::time::Instant::now() + delay);
+                }
+                FaultAction::RotateVolume => {
+                    if let Err(err) = message_dispatcher.finish_and_create_new() {
+                        message_dispatcher.fault_sender().send(Fault::fatal(FaultSource::Encoder, format!("Error finalizing the volume after an encoder fault: {}", err))).ok();
                     }
                 }
-                _ = &mut shutdown_rx => {
-                    info!("Gracefully shutting down...");
+                FaultAction::Shutdown => {
+                    fatal_fault = Some(fault);
                     break;
                 }
+            },
+            _ = tokio::time::sleep_until(reconnect_at.unwrap_or_else(tokio::time::Instant::now)), if reconnect_at.is_some() => {
+                reconnect_at = None;
+                connector = connect(&message_dispatcher, &mut supervisor).await;
             }
         }
-        message_dispatcher.finish_and_create_new()?;
     }
-    Ok(())
+    event_log::record("shutdown_started", json!({"fault": fatal_fault.as_ref().map(|fault| fault.to_string())}));
+    // Stop intake, then archive everything already queued and finalize the open volume, within the deadline
+    let drain = async {
+        if let Some(connector) = connector {
+            connector.shutdown().await;
+        }
+        message_dispatcher.drain().await
+    };
+    let drained = tokio::time::timeout(shutdown_deadline, drain).await;
+    for task in &background_tasks {
+        task.abort();
+    }
+    let result: Result<(), Box<dyn std::error::Error>> = match (drained, fatal_fault) {
+        (Ok(Ok(drained)), fatal_fault) => {
+            info!("Archived {} queued messages and finalized the open volume", drained);
+            match fatal_fault {
+                Some(fault) => Err(format!("Stopped after a fatal fault: {}", fault).into()),
+                None => Ok(()),
+            }
+        }
+        (Ok(Err(err)), _) => Err(format!("Error finalizing the open volume during shutdown: {}", err).into()),
+        (Err(_), _) => Err(format!("Shutdown did not finish within {:?}; the open volume was left unfinalized", shutdown_deadline).into()),
+    };
+    event_log::record("ingester_stopped", json!({"error": result.as_ref().err().map(|err| err.to_string())}));
+    if result.is_ok() {
+        info!("Shutdown complete");
+    }
+    result
 }
@@ -0,0 +1,622 @@
+use ciborium::value::Value;
+use log::warn;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+const DAG_CBOR_LINK_TAG: u64 = 42;
+const SHA2_256: u64 = 0x12;
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const MAX_SECTION_SIZE: u64 = 1 << 30; // Guards against allocating a garbage length read from a corrupt file
+
+/// A content identifier in its binary form. Displayed as base32 multibase ("bafy..."), as Old Faithful names them.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Cid(pub Vec<u8>);
+
+impl Cid {
+    /// Splits the CID off the front of `bytes`, returning it and whatever follows.
+    pub fn split_from(bytes: &[u8]) -> Result<(Cid, &[u8]), Box<dyn Error>> {
+        if bytes.len() >= 34 && bytes[0] == 0x12 && bytes[1] == 0x20 {
+            return Ok((Cid(bytes[..34].to_vec()), &bytes[34..])); // CIDv0: a bare sha2-256 multihash
+        }
+        let mut rest = bytes;
+        let version = read_varint(&mut rest)?.ok_or("Empty CID")?;
+        if version != 1 {
+            return Err(format!("Unsupported CID version {}", version).into());
+        }
+        read_varint(&mut rest)?.ok_or("Truncated CID")?; // Codec
+        read_varint(&mut rest)?.ok_or("Truncated CID")?; // Multihash code
+        let digest_length = read_varint(&mut rest)?.ok_or("Truncated CID")? as usize;
+        if rest.len() < digest_length {
+            return Err("Truncated CID".into());
+        }
+        let length = bytes.len() - rest.len() + digest_length;
+        Ok((Cid(bytes[..length].to_vec()), &bytes[length..]))
+    }
+
+    fn sha256_digest(&self) -> Option<&[u8]> {
+        if self.0.len() == 34 && self.0[0] == 0x12 {
+            return Some(&self.0[2..]);
+        }
+        let mut rest = &self.0[..];
+        let _version = read_varint(&mut rest).ok()??;
+        let _codec = read_varint(&mut rest).ok()??;
+        let code = read_varint(&mut rest).ok()??;
+        let _length = read_varint(&mut rest).ok()??;
+        (code == SHA2_256).then_some(rest)
+    }
+}
+
+impl fmt::Display for Cid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "b{}", base32_lower(&self.0))
+    }
+}
+
+impl fmt::Debug for Cid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+fn base32_lower(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+    let mut encoded = String::new();
+    let (mut buffer, mut bits) = (0u64, 0);
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u64;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        encoded.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    encoded
+}
+
+/// Reads an unsigned LEB128 varint, returning None at a clean end of input.
+pub fn read_varint(reader: &mut impl Read) -> Result<Option<u64>, Box<dyn Error>> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8];
+        match reader.read_exact(&mut byte) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof && shift == 0 => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err("Varint is too long".into())
+}
+
+/// The node kinds of Old Faithful's IPLD schema; every node is a DAG-CBOR tuple whose first field is its kind.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Transaction = 0,
+    Entry = 1,
+    Block = 2,
+    Subset = 3,
+    Epoch = 4,
+    Rewards = 5,
+    DataFrame = 6,
+}
+
+/// A chunk of a (possibly large) byte payload. Payloads that do not fit in one node continue in the
+/// frames linked from `next`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataFrame {
+    pub hash: Option<u64>,
+    pub index: Option<u64>,
+    pub total: Option<u64>,
+    pub data: Vec<u8>,
+    pub next: Vec<Cid>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransactionNode {
+    /// The bincode-serialized transaction.
+    pub data: DataFrame,
+    /// The transaction's status metadata, zstd-compressed.
+    pub metadata: DataFrame,
+    pub slot: u64,
+    pub index: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntryNode {
+    pub num_hashes: u64,
+    pub hash: Vec<u8>,
+    pub transactions: Vec<Cid>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SlotMeta {
+    pub parent_slot: u64,
+    pub blocktime: i64,
+    pub block_height: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockNode {
+    pub slot: u64,
+    /// (entry end index, shred end index) pairs; -1 marks "none".
+    pub shredding: Vec<(i64, i64)>,
+    pub entries: Vec<Cid>,
+    pub meta: SlotMeta,
+    pub rewards: Cid,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubsetNode {
+    pub first: u64,
+    pub last: u64,
+    pub blocks: Vec<Cid>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EpochNode {
+    pub epoch: u64,
+    pub subsets: Vec<Cid>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RewardsNode {
+    pub slot: u64,
+    pub data: DataFrame,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    Transaction(TransactionNode),
+    Entry(EntryNode),
+    Block(BlockNode),
+    Subset(SubsetNode),
+    Epoch(EpochNode),
+    Rewards(RewardsNode),
+    DataFrame(DataFrame),
+}
+
+impl Node {
+    pub fn kind(&self) -> Kind {
+        match self {
+            Node::Transaction(_) => Kind::Transaction,
+            Node::Entry(_) => Kind::Entry,
+            Node::Block(_) => Kind::Block,
+            Node::Subset(_) => Kind::Subset,
+            Node::Epoch(_) => Kind::Epoch,
+            Node::Rewards(_) => Kind::Rewards,
+            Node::DataFrame(_) => Kind::DataFrame,
+        }
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Node, Box<dyn Error>> {
+        let value: Value = ciborium::de::from_reader(bytes)?;
+        let fields = as_array(&value)?;
+        Ok(match as_u64(field(fields, 0)?)? {
+            0 => Node::Transaction(TransactionNode {
+                data: decode_data_frame(field(fields, 1)?)?,
+                metadata: decode_data_frame(field(fields, 2)?)?,
+                slot: as_u64(field(fields, 3)?)?,
+                index: optional(fields, 4).map(as_u64).transpose()?,
+            }),
+            1 => Node::Entry(EntryNode { num_hashes: as_u64(field(fields, 1)?)?, hash: as_bytes(field(fields, 2)?)?.to_vec(), transactions: as_links(field(fields, 3)?)? }),
+            2 => {
+                let shredding = as_array(field(fields, 2)?)?.iter().map(|shred| {
+                    let shred = as_array(shred)?;
+                    Ok((as_i64(field(shred, 0)?)?, as_i64(field(shred, 1)?)?))
+                });
+                let meta = as_array(field(fields, 4)?)?;
+                Node::Block(BlockNode {
+                    slot: as_u64(field(fields, 1)?)?,
+                    shredding: shredding.collect::<Result<_, Box<dyn Error>>>()?,
+                    entries: as_links(field(fields, 3)?)?,
+                    meta: SlotMeta { parent_slot: as_u64(field(meta, 0)?)?, blocktime: as_i64(field(meta, 1)?)?, block_height: optional(meta, 2).map(as_u64).transpose()? },
+                    rewards: as_link(field(fields, 5)?)?,
+                })
+            }
+            3 => Node::Subset(SubsetNode { first: as_u64(field(fields, 1)?)?, last: as_u64(field(fields, 2)?)?, blocks: as_links(field(fields, 3)?)? }),
+            4 => Node::Epoch(EpochNode { epoch: as_u64(field(fields, 1)?)?, subsets: as_links(field(fields, 2)?)? }),
+            5 => Node::Rewards(RewardsNode { slot: as_u64(field(fields, 1)?)?, data: decode_data_frame(field(fields, 2)?)? }),
+            6 => Node::DataFrame(decode_data_frame(&value)?),
+            kind => return Err(format!("Unknown node kind {}", kind).into()),
+        })
+    }
+}
+
+fn field(fields: &[Value], index: usize) -> Result<&Value, Box<dyn Error>> {
+    fields.get(index).ok_or_else(|| format!("Node is missing field {}", index).into())
+}
+
+/// Optional fields may be null or left off the end of the tuple.
+fn optional(fields: &[Value], index: usize) -> Option<&Value> {
+    fields.get(index).filter(|value| !value.is_null())
+}
+
+fn as_array(value: &Value) -> Result<&[Value], Box<dyn Error>> {
+    value.as_array().map(|fields| fields.as_slice()).ok_or_else(|| "Expected a CBOR array".into())
+}
+
+fn as_u64(value: &Value) -> Result<u64, Box<dyn Error>> {
+    value.as_integer().and_then(|integer| u64::try_from(integer).ok()).ok_or_else(|| "Expected an unsigned integer".into())
+}
+
+fn as_i64(value: &Value) -> Result<i64, Box<dyn Error>> {
+    value.as_integer().and_then(|integer| i64::try_from(integer).ok()).ok_or_else(|| "Expected an integer".into())
+}
+
+fn as_bytes(value: &Value) -> Result<&[u8], Box<dyn Error>> {
+    value.as_bytes().map(|bytes| bytes.as_slice()).ok_or_else(|| "Expected a byte string".into())
+}
+
+fn as_link(value: &Value) -> Result<Cid, Box<dyn Error>> {
+    match value {
+        Value::Tag(DAG_CBOR_LINK_TAG, link) => {
+            let bytes = as_bytes(link)?;
+            match bytes.split_first() {
+                Some((0, cid)) => Ok(Cid(cid.to_vec())), // Links carry a leading identity multibase byte
+                _ => Err("Malformed link".into()),
+            }
+        }
+        _ => Err("Expected a link".into()),
+    }
+}
+
+fn as_links(value: &Value) -> Result<Vec<Cid>, Box<dyn Error>> {
+    as_array(value)?.iter().map(as_link).collect()
+}
+
+fn decode_data_frame(value: &Value) -> Result<DataFrame, Box<dyn Error>> {
+    let fields = as_array(value)?;
+    if as_u64(field(fields, 0)?)? != Kind::DataFrame as u64 {
+        return Err("Expected a data frame".into());
+    }
+    Ok(DataFrame {
+        hash: optional(fields, 1).map(as_u64).transpose()?,
+        index: optional(fields, 2).map(as_u64).transpose()?,
+        total: optional(fields, 3).map(as_u64).transpose()?,
+        data: as_bytes(field(fields, 4)?)?.to_vec(),
+        next: optional(fields, 5).map(as_links).transpose()?.unwrap_or_default(),
+    })
+}
+
+/// A CAR section: a block's CID and its DAG-CBOR bytes.
+pub type Section = (Cid, Vec<u8>);
+
+/// Reads the sections of a CARv1 file: a header naming the root CIDs, then length-prefixed
+/// (CID, DAG-CBOR block) pairs.
+pub struct CarReader<R: Read> {
+    reader: R,
+    roots: Vec<Cid>,
+    offset: u64,
+    verify_hashes: bool,
+}
+
+impl<R: Read> CarReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, Box<dyn Error>> {
+        let length = read_varint(&mut reader)?.ok_or("Empty CAR file")?;
+        if length > MAX_SECTION_SIZE {
+            return Err(format!("CAR header claims {} bytes", length).into());
+        }
+        let mut header = vec![0u8; length as usize];
+        reader.read_exact(&mut header)?;
+        let header: Value = ciborium::de::from_reader(&header[..])?;
+        let entries = header.as_map().ok_or("CAR header is not a map")?;
+        let entry = |name: &str| entries.iter().find(|(key, _)| key.as_text() == Some(name)).map(|(_, value)| value);
+        let version = entry("version").map(as_u64).transpose()?;
+        if version != Some(1) {
+            return Err(format!("Unsupported CAR version {:?}", version).into());
+        }
+        let roots = as_links(entry("roots").ok_or("CAR header has no roots")?)?;
+        let offset = varint_length(length) + length;
+        Ok(Self { reader, roots, offset, verify_hashes: false })
+    }
+
+    pub fn roots(&self) -> &[Cid] {
+        &self.roots
+    }
+
+    /// Byte offset of the next section, as recorded in the `cid-to-offset-and-size` index.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Checks every block against the sha2-256 digest in its CID. Costs a hash per block, so off by default.
+    pub fn set_verify_hashes(&mut self, verify_hashes: bool) {
+        self.verify_hashes = verify_hashes;
+    }
+
+    pub fn next_section(&mut self) -> Result<Option<Section>, Box<dyn Error>> {
+        let Some(length) = read_varint(&mut self.reader)? else { return Ok(None) };
+        if length > MAX_SECTION_SIZE {
+            return Err(format!("Section at offset {} claims {} bytes", self.offset, length).into());
+        }
+        let mut section = vec![0u8; length as usize];
+        self.reader.read_exact(&mut section).map_err(|err| format!("Truncated section at offset {}: {}", self.offset, err))?;
+        let (cid, data) = Cid::split_from(&section)?;
+        if self.verify_hashes {
+            if let Some(digest) = cid.sha256_digest() {
+                if Sha256::digest(data).as_slice() != digest {
+                    return Err(format!("Block {} at offset {} does not match its CID", cid, self.offset).into());
+                }
+            }
+        }
+        self.offset += varint_length(length) + length;
+        let data = data.to_vec();
+        Ok(Some((cid, data)))
+    }
+
+    pub fn next_node(&mut self) -> Result<Option<(Cid, Node)>, Box<dyn Error>> {
+        match self.next_section()? {
+            Some((cid, data)) => {
+                let node = Node::decode(&data).map_err(|err| format!("Failed to decode node {}: {}", cid, err))?;
+                Ok(Some((cid, node)))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Reads the node whose section starts at `offset` and spans `size` bytes including its length prefix,
+/// as located by the `cid-to-offset-and-size` index.
+pub fn read_node_at(file: &File, offset: u64, size: u64) -> Result<(Cid, Node), Box<dyn Error>> {
+    if size > MAX_SECTION_SIZE {
+        return Err(format!("Section at offset {} claims {} bytes", offset, size).into());
+    }
+    let mut section = vec![0u8; size as usize];
+    file.read_exact_at(&mut section, offset).map_err(|err| format!("Failed to read section at offset {}: {}", offset, err))?;
+    let mut rest = &section[..];
+    let length = read_varint(&mut rest)?.ok_or("Empty section")?;
+    if length != rest.len() as u64 {
+        return Err(format!("Section at offset {} is {} bytes long, not {}", offset, length, rest.len()).into());
+    }
+    let (cid, data) = Cid::split_from(rest)?;
+    let node = Node::decode(data).map_err(|err| format!("Failed to decode node {}: {}", cid, err))?;
+    Ok((cid, node))
+}
+
+fn varint_length(value: u64) -> u64 {
+    (64 - value.max(1).leading_zeros() as u64).div_ceil(7)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CarTransaction {
+    pub slot: u64,
+    pub index: Option<u64>,
+    /// The bincode-serialized `VersionedTransaction`.
+    pub data: Vec<u8>,
+    /// The status metadata as stored: zstd-compressed protobuf (bincode in the earliest epochs).
+    pub metadata: Vec<u8>,
+}
+
+impl CarTransaction {
+    /// Base58 signatures of the transaction.
+    pub fn signatures(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        transaction_signatures(&self.data).map_err(|err| format!("Transaction in slot {}: {}", self.slot, err).into())
+    }
+
+    /// The first signature, which identifies the transaction.
+    pub fn signature(&self) -> Result<String, Box<dyn Error>> {
+        Ok(self.signatures()?.remove(0))
+    }
+
+    pub fn decompressed_metadata(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        decompress_if_zstd(&self.metadata)
+    }
+}
+
+/// Reads a compact-u16 (one to three bytes, seven bits each) at `offset` and moves past it.
+fn read_compact_u16(data: &[u8], offset: &mut usize) -> Result<usize, Box<dyn Error>> {
+    let mut value = 0usize;
+    for shift in 0..3 {
+        let byte = *data.get(*offset).ok_or("Transaction is too short")?;
+        value |= ((byte & 0x7f) as usize) << (7 * shift);
+        *offset += 1;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    Ok(value)
+}
+
+/// Base58 signatures of a bincode-serialized transaction, read from its front: a compact-u16 count
+/// followed by 64-byte signatures.
+pub fn transaction_signatures(data: &[u8]) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut offset = 0;
+    let count = read_compact_u16(data, &mut offset)?;
+    let end = offset + count * 64;
+    if count == 0 || data.len() < end {
+        return Err("Malformed signature list".into());
+    }
+    Ok(data[offset..end].chunks(64).map(|signature| bs58::encode(signature).into_string()).collect())
+}
+
+/// Base58 static account keys of a bincode-serialized legacy or v0 transaction. Addresses loaded from
+/// lookup tables are not in the transaction itself; they are in the `loadedAddresses` of its metadata.
+pub fn transaction_account_keys(data: &[u8]) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut offset = 0;
+    offset += read_compact_u16(data, &mut offset)? * 64;
+    if data.get(offset).is_some_and(|byte| byte & 0x80 != 0) {
+        offset += 1; // Version prefix of a versioned message
+    }
+    offset += 3; // Message header
+    let count = read_compact_u16(data, &mut offset)?;
+    let end = offset + count * 32;
+    if data.len() < end {
+        return Err("Malformed account key list".into());
+    }
+    Ok(data[offset..end].chunks(32).map(|key| bs58::encode(key).into_string()).collect())
+}
+
+fn decompress_if_zstd(bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if bytes.starts_with(&ZSTD_MAGIC) {
+        Ok(zstd::stream::decode_all(bytes)?)
+    } else {
+        Ok(bytes.to_vec())
+    }
+}
+
+/// A block with its transactions in the order they were executed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CarBlock {
+    pub slot: u64,
+    pub parent_slot: u64,
+    pub block_time: i64,
+    pub block_height: Option<u64>,
+    /// Base58 hash of the block's last entry, which is what RPC reports as its blockhash.
+    pub blockhash: Option<String>,
+    pub transactions: Vec<CarTransaction>,
+    /// Rewards as stored (zstd-compressed protobuf), if the block has any.
+    pub rewards: Option<Vec<u8>>,
+}
+
+/// Looks up a node by CID, returning None if it is not available.
+pub type FetchNode<'a> = dyn FnMut(&Cid) -> Result<Option<Node>, Box<dyn Error>> + 'a;
+
+/// Builds a block from its node, fetching the entries, transactions, rewards and data frames it links to.
+pub fn resolve_block(block: BlockNode, fetch: &mut FetchNode) -> Result<CarBlock, Box<dyn Error>> {
+    let mut transactions = Vec::new();
+    let mut blockhash = None;
+    for entry_cid in &block.entries {
+        let Some(Node::Entry(entry)) = fetch(entry_cid)? else {
+            return Err(format!("Block {} references missing entry {}", block.slot, entry_cid).into());
+        };
+        for transaction_cid in &entry.transactions {
+            let Some(Node::Transaction(transaction)) = fetch(transaction_cid)? else {
+                return Err(format!("Block {} references missing transaction {}", block.slot, transaction_cid).into());
+            };
+            transactions.push(resolve_transaction(transaction, fetch)?);
+        }
+        blockhash = Some(bs58::encode(entry.hash).into_string());
+    }
+    let rewards = match fetch(&block.rewards)? {
+        Some(Node::Rewards(rewards)) => Some(assemble(rewards.data, fetch)?).filter(|rewards| !rewards.is_empty()),
+        _ => None,
+    };
+    Ok(CarBlock { slot: block.slot, parent_slot: block.meta.parent_slot, block_time: block.meta.blocktime, block_height: block.meta.block_height, blockhash, transactions, rewards })
+}
+
+pub fn resolve_transaction(transaction: TransactionNode, fetch: &mut FetchNode) -> Result<CarTransaction, Box<dyn Error>> {
+    let data = assemble(transaction.data, fetch)?;
+    let metadata = assemble(transaction.metadata, fetch)?;
+    Ok(CarTransaction { slot: transaction.slot, index: transaction.index, data, metadata })
+}
+
+/// Concatenates a data frame with the frames it links to.
+fn assemble(frame: DataFrame, fetch: &mut FetchNode) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut data = frame.data;
+    for cid in frame.next {
+        match fetch(&cid)? {
+            Some(Node::DataFrame(next)) => data.extend(assemble(next, fetch)?),
+            _ => return Err(format!("Missing data frame {}", cid).into()),
+        }
+    }
+    Ok(data)
+}
+
+/// Streams the blocks of an epoch CAR in slot order, each with its transactions resolved. Old Faithful
+/// writes a block's transactions, entries and rewards just before the block itself, so only about one
+/// block's worth of nodes is held in memory at a time.
+pub struct EpochBlocks<R: Read> {
+    car: CarReader<R>,
+    pending: HashMap<Cid, Node>,
+    last_slot: Option<u64>,
+    done: bool,
+}
+
+impl<R: Read> EpochBlocks<R> {
+    pub fn new(car: CarReader<R>) -> Self {
+        Self { car, pending: HashMap::new(), last_slot: None, done: false }
+    }
+
+    fn next_block(&mut self) -> Result<Option<CarBlock>, Box<dyn Error>> {
+        while let Some((cid, node)) = self.car.next_node()? {
+            match node {
+                Node::Block(block) => {
+                    if let Some(last_slot) = self.last_slot.filter(|last_slot| block.slot <= *last_slot) {
+                        return Err(format!("Block {} follows block {}; the CAR is not in slot order", block.slot, last_slot).into());
+                    }
+                    self.last_slot = Some(block.slot);
+                    let pending = &mut self.pending;
+                    return resolve_block(block, &mut |cid| Ok(pending.remove(cid))).map(Some);
+                }
+                Node::Subset(_) | Node::Epoch(_) => {} // They only link blocks that have already been read
+                node => {
+                    self.pending.insert(cid, node);
+                }
+            }
+        }
+        if !self.pending.is_empty() {
+            warn!("{} nodes in the CAR were not referenced by any block", self.pending.len());
+        }
+        Ok(None)
+    }
+}
+
+impl<R: Read> Iterator for EpochBlocks<R> {
+    type Item = Result<CarBlock, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_block().transpose();
+        self.done = !matches!(result, Some(Ok(_))); // Stop after the end or the first error
+        result
+    }
+}
+
+pub fn open_epoch_car(path: &Path) -> Result<EpochBlocks<BufReader<File>>, Box<dyn Error>> {
+    let file = File::open(path).map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+    Ok(EpochBlocks::new(CarReader::new(BufReader::with_capacity(1024 * 1024, file))?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varints_round_trip_their_length() {
+        for (bytes, value) in [(&[0x01][..], 1u64), (&[0xac, 0x02][..], 300), (&[0xff, 0xff, 0x03][..], 65535)] {
+            assert_eq!(read_varint(&mut &bytes[..]).unwrap(), Some(value));
+            assert_eq!(varint_length(value), bytes.len() as u64);
+        }
+        assert_eq!(read_varint(&mut &[][..]).unwrap(), None);
+        assert!(read_varint(&mut &[0x80][..]).is_err());
+    }
+
+    #[test]
+    fn cids_display_as_base32() {
+        let mut bytes = vec![0x01, 0x71, 0x12, 0x20];
+        bytes.extend([0u8; 32]);
+        let (cid, rest) = Cid::split_from(&bytes).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(cid.to_string(), "bafyreiaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa");
+        assert_eq!(cid.sha256_digest(), Some(&[0u8; 32][..]));
+    }
+
+    #[test]
+    fn reads_signatures_and_account_keys_of_a_v0_transaction() {
+        let mut data = vec![1];
+        data.extend([7u8; 64]);
+        data.extend([0x80, 1, 0, 1, 2]); // Version 0, header, two account keys
+        data.extend([1u8; 32]);
+        data.extend([2u8; 32]);
+        data.extend([0u8; 33]); // Recent blockhash and the rest of the message
+
+        assert_eq!(transaction_signatures(&data).unwrap(), [bs58::encode([7u8; 64]).into_string()]);
+        assert_eq!(transaction_account_keys(&data).unwrap(), [bs58::encode([1u8; 32]).into_string(), bs58::encode([2u8; 32]).into_string()]);
+        assert!(transaction_account_keys(&data[..100]).is_err());
+    }
+}
@@ -0,0 +1,64 @@
+use crate::historical_backfiller::{self, BlockArchiver};
+use crate::old_faithful_epochs;
+use crate::old_faithful_files::LocalEpochFiles;
+use crate::old_faithful_index::EpochIndexes;
+use crate::volume_catalog::{CatalogVolume, VolumeCatalog};
+use futures::stream::{self, StreamExt};
+use log::info;
+use serde_json::json;
+use std::error::Error;
+use std::path::Path;
+
+pub const CONVERTED_VOLUME_STATE_FILE: &str = "old_faithful_volume_state.json";
+pub const OLD_FAITHFUL_SOURCE: &str = "old_faithful";
+const SLOTS_PER_BATCH: u64 = 1_000;
+
+/// The volumes already converted from `epoch`, according to the archive catalog.
+pub fn converted_volumes(archive_dir: &Path, epoch: u64) -> Result<Vec<CatalogVolume>, Box<dyn Error>> {
+    let catalog = VolumeCatalog::open_in(archive_dir)?;
+    catalog.sync_directory(archive_dir)?;
+    catalog.epoch_volumes(OLD_FAITHFUL_SOURCE, epoch)
+}
+
+/// Re-emits every block of an Old Faithful epoch into archive volumes, in the same `blockNotification`
+/// form and time buckets as live and backfilled data. Volumes are named by the epoch and the slots they
+/// hold, e.g. `solana_data_archive__epoch_500_slots_216000000_216000431.zstd`. Blocks are fetched from the `faithful-cli`
+/// RPC server at `rpc_url`, and the epoch's slot-to-cid index tells which slots have a block, so skipped
+/// slots are never requested. The last volume is finalized at the end of the epoch, so no volume spans
+/// two epochs. Each volume's manifest records the epoch; a conversion that was interrupted resumes after
+/// the last slot in a cataloged volume. Returns the number of blocks converted.
+pub async fn convert_epoch(local: &LocalEpochFiles, rpc_url: &str, archive_dir: &Path, minutes_per_bucket: i64, concurrency: usize) -> Result<u64, Box<dyn Error>> {
+    let epoch = local.files.epoch;
+    let (first_slot, last_slot) = old_faithful_epochs::epoch_slot_range(epoch);
+    std::fs::create_dir_all(archive_dir)?;
+    let resume_slot = converted_volumes(archive_dir, epoch)?.iter().filter_map(|volume| volume.last_slot).max().map_or(first_slot, |slot| slot + 1);
+    if resume_slot > first_slot {
+        info!("Resuming conversion of epoch {} at slot {}", epoch, resume_slot);
+    }
+    let indexes = EpochIndexes::open(local)?;
+    let details = json!({"source": OLD_FAITHFUL_SOURCE, "epoch": epoch, "root_cid": local.files.root_cid});
+    let mut archiver = BlockArchiver::new(archive_dir, minutes_per_bucket, CONVERTED_VOLUME_STATE_FILE, details);
+    archiver.set_epoch_naming(epoch);
+    let mut converted = 0;
+    let mut batch_start = resume_slot;
+    while batch_start <= last_slot {
+        let batch_end = (batch_start + SLOTS_PER_BATCH - 1).min(last_slot);
+        let mut slots = Vec::new();
+        for slot in batch_start..=batch_end {
+            if indexes.block_cid(slot)?.is_some() {
+                slots.push(slot);
+            }
+        }
+        let mut blocks = stream::iter(slots.into_iter().map(|slot| async move { (slot, historical_backfiller::fetch_block(rpc_url, slot).await) })).buffered(concurrency.max(1));
+        while let Some((slot, block)) = blocks.next().await {
+            let block = block?.ok_or_else(|| format!("faithful-cli has no block for slot {}, although epoch {}'s index lists one", slot, epoch))?;
+            archiver.archive_block(slot, block)?;
+            converted += 1;
+        }
+        batch_start = batch_end + 1;
+    }
+    archiver.finish()?;
+    info!("Converted {} blocks from epoch {} into {}", converted, epoch, archive_dir.display());
+    Ok(converted)
+}
+
@@ -0,0 +1,170 @@
+use crate::old_faithful_files::EpochFiles;
+use crate::solana_rest_api_functions::{self, extract_result};
+use chrono::Utc;
+use dotenv::dotenv;
+use log::{debug, info};
+use serde_json::{json, Map, Value};
+use std::env;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_OLD_FAITHFUL_BASE_URL: &str = "https://files.old-faithful.net";
+pub const DEFAULT_EPOCH_STATE_FILE: &str = "old_faithful_epoch_state.json";
+pub const SLOTS_PER_EPOCH: u64 = 432_000;
+const MAX_EPOCHS_TO_PROBE_FOR_LATEST: u64 = 10;
+
+/// An inclusive range of epochs, parsed from either "N" or "A-B".
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EpochRange {
+    pub first: u64,
+    pub last: u64,
+}
+
+impl std::str::FromStr for EpochRange {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parse = |epoch: &str| epoch.trim().parse::<u64>().map_err(|_| format!("Invalid epoch: {}", epoch));
+        let (first, last) = match value.split_once('-') {
+            Some((first, last)) => (parse(first)?, parse(last)?),
+            None => (parse(value)?, parse(value)?),
+        };
+        if first > last {
+            return Err(format!("Epoch range {} is reversed", value));
+        }
+        Ok(Self { first, last })
+    }
+}
+
+impl EpochRange {
+    pub fn epochs(&self) -> impl Iterator<Item = u64> {
+        self.first..=self.last
+    }
+}
+
+/// First and last slot of `epoch`.
+pub fn epoch_slot_range(epoch: u64) -> (u64, u64) {
+    (epoch * SLOTS_PER_EPOCH, (epoch + 1) * SLOTS_PER_EPOCH - 1)
+}
+
+/// Where Old Faithful files are fetched from: OLD_FAITHFUL_BASE_URL if set (e.g. a local mirror),
+/// otherwise the public Old Faithful file server.
+pub fn old_faithful_base_url() -> String {
+    dotenv().ok(); // Load the .env file
+    env::var("OLD_FAITHFUL_BASE_URL").unwrap_or_else(|_| DEFAULT_OLD_FAITHFUL_BASE_URL.to_string()).trim_end_matches('/').to_string()
+}
+
+/// Old Faithful publishes each epoch some time after it ends, so an epoch is treated as available once
+/// its files can be fetched.
+pub async fn is_epoch_available(base_url: &str, epoch: u64) -> Result<bool, Box<dyn Error>> {
+    let url = EpochFiles::remote_url(base_url, epoch, &EpochFiles::cid_file_name(epoch));
+    let response = reqwest::Client::new().head(&url).send().await?;
+    debug!("Availability check for epoch {}: {} returned {}", epoch, url, response.status());
+    Ok(response.status().is_success())
+}
+
+/// The most recent epoch Old Faithful has published, probing backwards from the cluster's current epoch
+/// (which is never available, as it has not finished yet).
+pub async fn latest_available_epoch(base_url: &str) -> Result<u64, Box<dyn Error>> {
+    let epoch_info = extract_result(solana_rest_api_functions::get_epoch_info().await?)?;
+    let current_epoch = epoch_info["epoch"].as_u64().ok_or("Epoch is missing or not a u64")?;
+    for epoch in (current_epoch.saturating_sub(MAX_EPOCHS_TO_PROBE_FOR_LATEST)..current_epoch).rev() {
+        if is_epoch_available(base_url, epoch).await? {
+            return Ok(epoch);
+        }
+    }
+    Err(format!("No Old Faithful epoch available within {} epochs of current epoch {}", MAX_EPOCHS_TO_PROBE_FOR_LATEST, current_epoch).into())
+}
+
+/// Per-epoch processing record, persisted as JSON so reruns skip epochs that are already complete.
+pub struct EpochStateFile {
+    path: PathBuf,
+    epochs: Map<String, Value>,
+}
+
+impl EpochStateFile {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let epochs = if path.exists() {
+            let contents: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+            contents["epochs"].as_object().cloned().unwrap_or_default()
+        } else {
+            Map::new()
+        };
+        Ok(Self { path: path.to_path_buf(), epochs })
+    }
+
+    pub fn is_complete(&self, epoch: u64) -> bool {
+        self.epochs.get(&epoch.to_string()).and_then(|record| record["status"].as_str()) == Some("complete")
+    }
+
+    /// Records `epoch` as complete, along with any `details` (an object) worth keeping about it.
+    pub fn mark_complete(&mut self, epoch: u64, details: Value) -> Result<(), Box<dyn Error>> {
+        let mut record = json!({"status": "complete", "completed_at": Utc::now().to_rfc3339()});
+        if let (Some(record), Value::Object(details)) = (record.as_object_mut(), details) {
+            record.extend(details);
+        }
+        self.epochs.insert(epoch.to_string(), record);
+        self.save()
+    }
+
+    pub fn is_converted(&self, epoch: u64) -> bool {
+        self.is_complete(epoch) && self.epochs[&epoch.to_string()]["converted_at"].is_string()
+    }
+
+    /// Records that a complete epoch has been converted into archive volumes.
+    pub fn mark_converted(&mut self, epoch: u64, blocks: u64) -> Result<(), Box<dyn Error>> {
+        let record = self.epochs.get_mut(&epoch.to_string()).and_then(|record| record.as_object_mut()).ok_or_else(|| format!("Epoch {} has not been processed", epoch))?;
+        record.insert("converted_at".to_string(), json!(Utc::now().to_rfc3339()));
+        record.insert("converted_blocks".to_string(), json!(blocks));
+        self.save()
+    }
+
+    pub fn record(&self, epoch: u64) -> Option<&Value> {
+        self.epochs.get(&epoch.to_string())
+    }
+
+    pub fn mark_failed(&mut self, epoch: u64, error: &str) -> Result<(), Box<dyn Error>> {
+        self.epochs.insert(epoch.to_string(), json!({"status": "failed", "error": error, "failed_at": Utc::now().to_rfc3339()}));
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        let temp_path = self.path.with_extension("json.temp");
+        std::fs::write(&temp_path, serde_json::to_string_pretty(&json!({"epochs": self.epochs}))?)?;
+        std::fs::rename(&temp_path, &self.path)?; // Rename so a crash never leaves a half-written state file
+        info!("Updated Old Faithful epoch state in {}", self.path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_epochs_and_ranges() {
+        assert_eq!("500".parse::<EpochRange>(), Ok(EpochRange { first: 500, last: 500 }));
+        assert_eq!("500-502".parse::<EpochRange>().unwrap().epochs().collect::<Vec<_>>(), [500, 501, 502]);
+        assert!("502-500".parse::<EpochRange>().is_err());
+        assert!("latest".parse::<EpochRange>().is_err());
+    }
+
+    #[test]
+    fn completion_survives_reloading_the_state_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(DEFAULT_EPOCH_STATE_FILE);
+        let mut state = EpochStateFile::load(&path).unwrap();
+        state.mark_complete(500, json!({"root_cid": "bafyreib"})).unwrap();
+        state.mark_failed(501, "download failed").unwrap();
+        let reloaded = EpochStateFile::load(&path).unwrap();
+        assert!(reloaded.is_complete(500));
+        assert_eq!(reloaded.record(500).unwrap()["root_cid"], "bafyreib");
+        assert!(!reloaded.is_complete(501));
+        assert!(!reloaded.is_complete(502));
+        assert!(!reloaded.is_converted(500));
+        let mut reloaded = reloaded;
+        reloaded.mark_converted(500, 1234).unwrap();
+        assert!(EpochStateFile::load(&path).unwrap().is_converted(500));
+        assert!(reloaded.mark_converted(502, 0).is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::resumable_downloader::{self, DownloadOptions};
+use log::{info, warn};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_OLD_FAITHFUL_DATA_DIRECTORY: &str = "old_faithful_files";
+
+/// The indexes `faithful-cli` needs alongside an epoch's CAR file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IndexKind {
+    CidToOffsetAndSize,
+    SlotToCid,
+    SigToCid,
+}
+
+impl IndexKind {
+    pub const ALL: [IndexKind; 3] = [IndexKind::CidToOffsetAndSize, IndexKind::SlotToCid, IndexKind::SigToCid];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            IndexKind::CidToOffsetAndSize => "cid-to-offset-and-size",
+            IndexKind::SlotToCid => "slot-to-cid",
+            IndexKind::SigToCid => "sig-to-cid",
+        }
+    }
+}
+
+/// Names of the files Old Faithful publishes for an epoch. The CAR is `epoch-<N>.car`, its root CID is
+/// published in `epoch-<N>.cid`, and the indexes are named after both the epoch and the root CID:
+/// `epoch-<N>-<root CID>-mainnet-<kind>.index`. All of them live under `<base URL>/<N>/`.
+#[derive(Clone, Debug)]
+pub struct EpochFiles {
+    pub epoch: u64,
+    pub root_cid: String,
+}
+
+impl EpochFiles {
+    pub fn cid_file_name(epoch: u64) -> String {
+        format!("epoch-{}.cid", epoch)
+    }
+
+    pub fn car_file_name(&self) -> String {
+        format!("epoch-{}.car", self.epoch)
+    }
+
+    pub fn index_file_name(&self, kind: IndexKind) -> String {
+        format!("epoch-{}-{}-mainnet-{}.index", self.epoch, self.root_cid, kind.name())
+    }
+
+    pub fn remote_url(base_url: &str, epoch: u64, file_name: &str) -> String {
+        format!("{}/{}/{}", base_url, epoch, file_name)
+    }
+}
+
+/// Local paths of everything needed to serve or read an epoch.
+#[derive(Clone, Debug)]
+pub struct LocalEpochFiles {
+    pub files: EpochFiles,
+    pub car_path: PathBuf,
+    pub index_paths: Vec<(IndexKind, PathBuf)>,
+}
+
+impl LocalEpochFiles {
+    /// Where an epoch's files are kept under `data_dir`; the CAR may instead be found in `car_source_dir`.
+    pub fn new(files: EpochFiles, data_dir: &Path, car_source_dir: Option<&Path>) -> Self {
+        let epoch_dir = data_dir.join(files.epoch.to_string());
+        let car_path = car_source_dir
+            .map(|dir| dir.join(files.car_file_name()))
+            .filter(|path| path.exists())
+            .unwrap_or_else(|| epoch_dir.join(files.car_file_name()));
+        let index_paths = IndexKind::ALL.iter().map(|kind| (*kind, epoch_dir.join(files.index_file_name(*kind)))).collect();
+        Self { files, car_path, index_paths }
+    }
+
+    pub fn index_path(&self, kind: IndexKind) -> &Path {
+        &self.index_paths.iter().find(|(index_kind, _)| *index_kind == kind).expect("every index kind has a path").1
+    }
+
+    /// Checks that the CAR and every index exist and are non-empty.
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let mut missing = Vec::new();
+        for path in std::iter::once(&self.car_path).chain(self.index_paths.iter().map(|(_, path)| path)) {
+            match std::fs::metadata(path) {
+                Ok(metadata) if metadata.len() > 0 => {}
+                Ok(_) => missing.push(format!("{} (empty)", path.display())),
+                Err(_) => missing.push(path.display().to_string()),
+            }
+        }
+        if !missing.is_empty() {
+            return Err(format!("Epoch {} is incomplete; missing {}", self.files.epoch, missing.join(", ")).into());
+        }
+        Ok(())
+    }
+}
+
+/// Reads the root CID Old Faithful publishes for `epoch`.
+pub async fn fetch_root_cid(base_url: &str, epoch: u64) -> Result<String, Box<dyn Error>> {
+    let url = EpochFiles::remote_url(base_url, epoch, &EpochFiles::cid_file_name(epoch));
+    let body = reqwest::get(&url).await?.error_for_status()?.text().await?;
+    let root_cid = body.trim().to_string();
+    if root_cid.is_empty() {
+        return Err(format!("{} is empty", url).into());
+    }
+    Ok(root_cid)
+}
+
+async fn download_verified(url: &str, destination: &Path, options: &DownloadOptions) -> Result<(), Box<dyn Error>> {
+    let expected_sha256 = resumable_downloader::fetch_published_sha256(url).await?;
+    if expected_sha256.is_none() {
+        warn!("No checksum is published for {}; verifying size only", url);
+    }
+    let options = DownloadOptions { expected_sha256, ..*options };
+    resumable_downloader::download_file(url, destination, &options).await?;
+    Ok(())
+}
+
+/// Makes every file for `epoch` available locally: the indexes are always downloaded into `data_dir`,
+/// while the CAR is used in place if it already exists in `car_source_dir` (e.g. shared storage) and
+/// is otherwise downloaded too. The result is validated before it is returned.
+pub async fn fetch_epoch_files(base_url: &str, epoch: u64, data_dir: &Path, car_source_dir: Option<&Path>, options: &DownloadOptions) -> Result<LocalEpochFiles, Box<dyn Error>> {
+    let files = EpochFiles { epoch, root_cid: fetch_root_cid(base_url, epoch).await? };
+    info!("Epoch {} has root CID {}", epoch, files.root_cid);
+    let local = LocalEpochFiles::new(files, data_dir, car_source_dir);
+    for (kind, path) in &local.index_paths {
+        download_verified(&EpochFiles::remote_url(base_url, epoch, &local.files.index_file_name(*kind)), path, options).await?;
+    }
+    if car_source_dir.is_some_and(|dir| local.car_path.starts_with(dir)) {
+        info!("Using existing CAR file {}", local.car_path.display());
+    } else {
+        download_verified(&EpochFiles::remote_url(base_url, epoch, &local.files.car_file_name()), &local.car_path, options).await?;
+    }
+    local.validate()?;
+    Ok(local)
+}
@@ -0,0 +1,274 @@
+use crate::old_faithful_car::{self, CarBlock, CarTransaction, Cid, Node};
+use crate::old_faithful_files::{IndexKind, LocalEpochFiles};
+use std::error::Error;
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use xxhash_rust::xxh64::xxh64;
+
+pub const COMPACT_INDEX_MAGIC: &[u8; 8] = b"compiszd";
+const COMPACT_INDEX_VERSION: u8 = 1;
+const BUCKET_HEADER_SIZE: u64 = 16;
+const OFFSET_AND_SIZE_VALUE_SIZE: u64 = 9; // 48-bit offset followed by a 24-bit size
+
+/// A `compactindexsized` file as built by Old Faithful: a hash table of fixed-size values split into
+/// buckets. The layout is
+///
+///   magic "compiszd" | header length u32 | value size u64 | bucket count u32 | version u8 | metadata
+///   bucket headers: hash domain u32 | entry count u32 | hash length u8 | padding u8 | file offset u48
+///   per bucket: entries sorted by truncated hash, each `hash length` bytes of hash then the value
+///
+/// with every integer little-endian. A key's bucket comes from its xxhash64; within the bucket, entries
+/// are keyed by the xxhash64 of the key prefixed with the bucket's hash domain.
+pub struct CompactIndex {
+    file: File,
+    path: PathBuf,
+    value_size: u64,
+    num_buckets: u32,
+    buckets_offset: u64,
+    metadata: Vec<(String, Vec<u8>)>,
+}
+
+struct BucketHeader {
+    hash_domain: u32,
+    num_entries: u32,
+    hash_len: u8,
+    file_offset: u64,
+}
+
+impl CompactIndex {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = File::open(path).map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+        let mut prefix = [0u8; 12];
+        file.read_exact_at(&mut prefix, 0)?;
+        if &prefix[..8] != COMPACT_INDEX_MAGIC {
+            return Err(format!("{} is not a compact index", path.display()).into());
+        }
+        let header_length = u32::from_le_bytes(prefix[8..12].try_into()?) as usize;
+        let mut header = vec![0u8; header_length];
+        file.read_exact_at(&mut header, 12)?;
+        if header.len() < 13 {
+            return Err(format!("{} has a truncated header", path.display()).into());
+        }
+        let value_size = u64::from_le_bytes(header[0..8].try_into()?);
+        let num_buckets = u32::from_le_bytes(header[8..12].try_into()?);
+        if header[12] != COMPACT_INDEX_VERSION {
+            return Err(format!("{} has unsupported version {}", path.display(), header[12]).into());
+        }
+        if num_buckets == 0 {
+            return Err(format!("{} has no buckets", path.display()).into());
+        }
+        let metadata = parse_metadata(&header[13..]).ok_or_else(|| format!("{} has malformed metadata", path.display()))?;
+        Ok(Self { file, path: path.to_path_buf(), value_size, num_buckets, buckets_offset: 12 + header_length as u64, metadata })
+    }
+
+    pub fn value_size(&self) -> u64 {
+        self.value_size
+    }
+
+    /// A metadata value the index was built with, such as "kind", "epoch" or "rootCid".
+    pub fn metadata(&self, key: &str) -> Option<&[u8]> {
+        self.metadata.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_slice())
+    }
+
+    fn bucket_header(&self, bucket: u32) -> Result<BucketHeader, Box<dyn Error>> {
+        let mut header = [0u8; BUCKET_HEADER_SIZE as usize];
+        self.file.read_exact_at(&mut header, self.buckets_offset + bucket as u64 * BUCKET_HEADER_SIZE)?;
+        let mut file_offset = [0u8; 8];
+        file_offset[..6].copy_from_slice(&header[10..16]);
+        Ok(BucketHeader {
+            hash_domain: u32::from_le_bytes(header[0..4].try_into()?),
+            num_entries: u32::from_le_bytes(header[4..8].try_into()?),
+            hash_len: header[8],
+            file_offset: u64::from_le_bytes(file_offset),
+        })
+    }
+
+    /// Finds the value stored for `key` by binary search within its bucket. As only a truncated hash is
+    /// stored, a key that was never indexed can occasionally match another key's value; callers check
+    /// the result where that matters.
+    pub fn lookup(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let bucket = self.bucket_header(bucket_for(key, self.num_buckets))?;
+        if bucket.hash_len == 0 || bucket.hash_len > 8 {
+            return Err(format!("{} has a bucket with hash length {}", self.path.display(), bucket.hash_len).into());
+        }
+        let hash_len = bucket.hash_len as usize;
+        let target = entry_hash(bucket.hash_domain, key) & truncation_mask(bucket.hash_len);
+        let stride = hash_len as u64 + self.value_size;
+        let mut entry = vec![0u8; stride as usize];
+        let (mut low, mut high) = (0u64, bucket.num_entries as u64);
+        while low < high {
+            let middle = (low + high) / 2;
+            self.file.read_exact_at(&mut entry, bucket.file_offset + middle * stride)?;
+            let mut hash = [0u8; 8];
+            hash[..hash_len].copy_from_slice(&entry[..hash_len]);
+            let hash = u64::from_le_bytes(hash);
+            match hash.cmp(&target) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return Ok(Some(entry[hash_len..].to_vec())),
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn parse_metadata(bytes: &[u8]) -> Option<Vec<(String, Vec<u8>)>> {
+    let Some((&count, mut rest)) = bytes.split_first() else { return Some(Vec::new()) };
+    let mut metadata = Vec::new();
+    for _ in 0..count {
+        let mut take = || {
+            let (&length, tail) = rest.split_first()?;
+            let (value, tail) = (tail.get(..length as usize)?, tail.get(length as usize..)?);
+            rest = tail;
+            Some(value.to_vec())
+        };
+        let key = take()?;
+        let value = take()?;
+        metadata.push((String::from_utf8_lossy(&key).to_string(), value));
+    }
+    Some(metadata)
+}
+
+/// Picks the bucket for a key, rehashing values that would bias the modulo towards low buckets.
+pub fn bucket_for(key: &[u8], num_buckets: u32) -> u32 {
+    let buckets = num_buckets as u64;
+    let threshold = buckets.wrapping_neg() % buckets;
+    let mut hash = xxh64(key, 0);
+    while hash < threshold {
+        hash = mix(hash);
+    }
+    (hash % buckets) as u32
+}
+
+fn mix(mut value: u64) -> u64 {
+    value ^= value >> 33;
+    value = value.wrapping_mul(0xff51afd7ed558ccd);
+    value ^= value >> 33;
+    value = value.wrapping_mul(0xc4ceb9fe1a85ec53);
+    value ^ (value >> 33)
+}
+
+/// The full (untruncated) hash an entry is stored under within a bucket.
+pub fn entry_hash(hash_domain: u32, key: &[u8]) -> u64 {
+    let mut input = vec![0u8; 32];
+    input[..4].copy_from_slice(&hash_domain.to_le_bytes());
+    input.extend_from_slice(key);
+    xxh64(&input, 0)
+}
+
+pub fn truncation_mask(hash_len: u8) -> u64 {
+    if hash_len >= 8 {
+        u64::MAX
+    } else {
+        (1u64 << (8 * hash_len as u32)) - 1
+    }
+}
+
+/// The three indexes Old Faithful publishes for an epoch.
+pub struct EpochIndexes {
+    cid_to_offset_and_size: CompactIndex,
+    slot_to_cid: CompactIndex,
+    sig_to_cid: CompactIndex,
+}
+
+impl EpochIndexes {
+    pub fn open(local: &LocalEpochFiles) -> Result<Self, Box<dyn Error>> {
+        let open = |kind: IndexKind| -> Result<CompactIndex, Box<dyn Error>> {
+            let index = CompactIndex::open(local.index_path(kind))?;
+            match index.metadata("kind") {
+                Some(recorded) if recorded != kind.name().as_bytes() => {
+                    Err(format!("{} is a {} index, not {}", local.index_path(kind).display(), String::from_utf8_lossy(recorded), kind.name()).into())
+                }
+                _ => Ok(index),
+            }
+        };
+        let cid_to_offset_and_size = open(IndexKind::CidToOffsetAndSize)?;
+        if cid_to_offset_and_size.value_size() != OFFSET_AND_SIZE_VALUE_SIZE {
+            return Err(format!("{} stores {}-byte values, expected {}", local.index_path(IndexKind::CidToOffsetAndSize).display(), cid_to_offset_and_size.value_size(), OFFSET_AND_SIZE_VALUE_SIZE).into());
+        }
+        Ok(Self { cid_to_offset_and_size, slot_to_cid: open(IndexKind::SlotToCid)?, sig_to_cid: open(IndexKind::SigToCid)? })
+    }
+
+    /// Byte offset and size of the CAR section holding `cid`.
+    pub fn offset_and_size(&self, cid: &Cid) -> Result<Option<(u64, u64)>, Box<dyn Error>> {
+        Ok(self.cid_to_offset_and_size.lookup(&cid.0)?.map(|value| {
+            let mut offset = [0u8; 8];
+            offset[..6].copy_from_slice(&value[..6]);
+            let mut size = [0u8; 8];
+            size[..3].copy_from_slice(&value[6..9]);
+            (u64::from_le_bytes(offset), u64::from_le_bytes(size))
+        }))
+    }
+
+    pub fn block_cid(&self, slot: u64) -> Result<Option<Cid>, Box<dyn Error>> {
+        Ok(self.slot_to_cid.lookup(&slot.to_le_bytes())?.map(Cid))
+    }
+
+    pub fn transaction_cid(&self, signature: &str) -> Result<Option<Cid>, Box<dyn Error>> {
+        let signature = bs58::decode(signature).into_vec().map_err(|err| format!("Invalid signature {}: {}", signature, err))?;
+        Ok(self.sig_to_cid.lookup(&signature)?.map(Cid))
+    }
+}
+
+/// Random access to an epoch's blocks and transactions through its indexes, without scanning the CAR.
+pub struct IndexedEpoch {
+    car: File,
+    indexes: EpochIndexes,
+}
+
+impl IndexedEpoch {
+    pub fn open(local: &LocalEpochFiles) -> Result<Self, Box<dyn Error>> {
+        let car = File::open(&local.car_path).map_err(|err| format!("Failed to open {}: {}", local.car_path.display(), err))?;
+        Ok(Self { car, indexes: EpochIndexes::open(local)? })
+    }
+
+    pub fn node(&self, cid: &Cid) -> Result<Option<Node>, Box<dyn Error>> {
+        let Some((offset, size)) = self.indexes.offset_and_size(cid)? else { return Ok(None) };
+        let (found, node) = old_faithful_car::read_node_at(&self.car, offset, size)?;
+        if found != *cid {
+            return Ok(None); // A truncated-hash collision pointed at some other node
+        }
+        Ok(Some(node))
+    }
+
+    /// The block produced in `slot`, or None if the slot was skipped.
+    pub fn block(&self, slot: u64) -> Result<Option<CarBlock>, Box<dyn Error>> {
+        let Some(cid) = self.indexes.block_cid(slot)? else { return Ok(None) };
+        match self.node(&cid)? {
+            Some(Node::Block(block)) if block.slot == slot => Ok(Some(old_faithful_car::resolve_block(block, &mut |cid| self.node(cid))?)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn transaction(&self, signature: &str) -> Result<Option<CarTransaction>, Box<dyn Error>> {
+        let Some(cid) = self.indexes.transaction_cid(signature)? else { return Ok(None) };
+        let Some(Node::Transaction(transaction)) = self.node(&cid)? else { return Ok(None) };
+        let transaction = old_faithful_car::resolve_transaction(transaction, &mut |cid| self.node(cid))?;
+        Ok(transaction.signatures()?.iter().any(|found| found == signature).then_some(transaction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_stay_in_range_and_hashes_truncate() {
+        for key in [&b""[..], b"a", &1296000u64.to_le_bytes()] {
+            for buckets in [1, 3, 1000] {
+                assert!(bucket_for(key, buckets) < buckets);
+            }
+        }
+        assert_eq!(truncation_mask(3), 0xff_ffff);
+        assert_eq!(truncation_mask(8), u64::MAX);
+        assert_ne!(entry_hash(0, b"key"), entry_hash(1, b"key"), "the hash domain changes the hash");
+    }
+
+    #[test]
+    fn parses_metadata_pairs() {
+        let metadata = parse_metadata(&[2, 4, b'k', b'i', b'n', b'd', 2, b'a', b'b', 5, b'e', b'p', b'o', b'c', b'h', 1, 3]).unwrap();
+        assert_eq!(metadata, [("kind".to_string(), b"ab".to_vec()), ("epoch".to_string(), vec![3])]);
+        assert!(parse_metadata(&[1, 4, b'k']).is_none());
+    }
+}
@@ -1,66 +1,185 @@
-mod solana_rest_api_functions;
-use reqwest;
-use std::process::Command;
+use pastel_solana_archival_data_integration_api::archive_verifier;
+use pastel_solana_archival_data_integration_api::faithful_cli_supervisor::{FaithfulCliConfig, FaithfulCliSupervisor};
+use pastel_solana_archival_data_integration_api::historical_backfiller::DEFAULT_BACKFILL_CONCURRENCY;
+use pastel_solana_archival_data_integration_api::old_faithful_converter;
+use pastel_solana_archival_data_integration_api::old_faithful_epochs::{self, EpochRange, EpochStateFile, DEFAULT_EPOCH_STATE_FILE};
+use pastel_solana_archival_data_integration_api::old_faithful_files::{self, EpochFiles, LocalEpochFiles, DEFAULT_OLD_FAITHFUL_DATA_DIRECTORY};
+use pastel_solana_archival_data_integration_api::resumable_downloader::DownloadOptions;
+use serde_json::json;
+use log::{info, warn, LevelFilter};
+use log4rs::append::console::ConsoleAppender;
+use log4rs::config::{Appender, Config, Root};
 use std::error::Error;
-use std::fs;
-use tokio;
+use std::path::{Path, PathBuf};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
 
-pub struct OldFaithfulSolanaConnector;
+const DEFAULT_ARCHIVE_DIRECTORY: &str = ".";
+const DEFAULT_REPORT_DIRECTORY: &str = ".";
+const ARCHIVE_MINUTES_PER_BUCKET: i64 = 1; // Must match the live ingester's MINUTES_PER_BUCKET so buckets line up
 
-impl OldFaithfulSolanaConnector {
-    pub async fn new() -> Result<(), Box<dyn Error>> {
-        // Get current epoch
-        let epoch_info = solana_rest_api_functions::get_epoch_info().await?;
-        let current_epoch: u64 = epoch_info["result"]["epoch"]
-        .as_u64()
-        .ok_or("Epoch is missing or not a u64")?;
-    
-        // Create directory if it doesn't exist
-        let dir_path = "old_faithful_index_files";
-        if !fs::metadata(&dir_path).is_ok() {
-            fs::create_dir(&dir_path)?;
-        }
+pub struct OldFaithfulSolanaConnector {
+    base_url: String,
+    data_dir: PathBuf,
+    car_source_dir: Option<PathBuf>,
+    download_options: DownloadOptions,
+}
 
-        // Download indices
-        Self::download_index(current_epoch, "cid-to-offset", dir_path).await?;
-        Self::download_index(current_epoch, "slot-to-cid", dir_path).await?;
-        Self::download_index(current_epoch, "sig-to-cid", dir_path).await?;
+impl OldFaithfulSolanaConnector {
+    pub fn new(base_url: String, data_dir: PathBuf, car_source_dir: Option<PathBuf>, download_options: DownloadOptions) -> Self {
+        Self { base_url, data_dir, car_source_dir, download_options }
+    }
 
-        // Run RPC server
-        Self::run_rpc_server(current_epoch, dir_path);
+    /// Processes each available epoch in `epochs` in order, skipping those the state file already records
+    /// as complete. Returns the files of the epochs that are complete once the run finishes.
+    pub async fn run(&self, epochs: EpochRange, state_file: &Path) -> Result<Vec<LocalEpochFiles>, Box<dyn Error>> {
+        let mut state = EpochStateFile::load(state_file)?;
+        let mut completed = Vec::new();
+        for epoch in epochs.epochs() {
+            if let Some(root_cid) = state.record(epoch).filter(|_| state.is_complete(epoch)).and_then(|record| record["root_cid"].as_str()) {
+                info!("Epoch {} is already complete; skipping", epoch);
+                completed.push(LocalEpochFiles::new(EpochFiles { epoch, root_cid: root_cid.to_string() }, &self.data_dir, self.car_source_dir.as_deref()));
+                continue;
+            }
+            if !old_faithful_epochs::is_epoch_available(&self.base_url, epoch).await? {
+                warn!("Epoch {} is not available from Old Faithful yet; skipping", epoch);
+                continue;
+            }
+            match self.process_epoch(epoch).await {
+                Ok(local) => {
+                    state.mark_complete(epoch, json!({"root_cid": local.files.root_cid, "car_path": local.car_path}))?;
+                    info!("Epoch {} complete", epoch);
+                    completed.push(local);
+                }
+                Err(err) => {
+                    state.mark_failed(epoch, &err.to_string())?;
+                    return Err(format!("Failed to process epoch {}: {}", epoch, err).into());
+                }
+            }
+        }
+        Ok(completed)
+    }
 
-        Ok(())
+    async fn process_epoch(&self, epoch: u64) -> Result<LocalEpochFiles, Box<dyn Error>> {
+        info!("Processing epoch {}...", epoch);
+        old_faithful_files::fetch_epoch_files(&self.base_url, epoch, &self.data_dir, self.car_source_dir.as_deref(), &self.download_options).await
     }
+}
 
-    async fn download_index(epoch: u64, index_type: &str, dir_path: &str) -> Result<(), Box<dyn Error>> {
-        let url = format!("https://files.old-faithful.net/{}/epoch-{}.car.{}.index", epoch, epoch, index_type);
-        let resp = reqwest::get(&url).await?;
-        let bytes = resp.bytes().await?;
-        let file_path = format!("{}/epoch-{}.car.{}.index", dir_path, epoch, index_type);
-        tokio::fs::write(&file_path, bytes).await?;
-        Ok(())
+fn faithful_cli_config(args: &[String]) -> FaithfulCliConfig {
+    let mut config = FaithfulCliConfig::default();
+    if let Some(binary_path) = flag_value(args, "--faithful-cli").map(String::from).or_else(|| std::env::var("FAITHFUL_CLI_PATH").ok()) {
+        config.binary_path = PathBuf::from(binary_path);
+    }
+    if let Some(listen_address) = flag_value(args, "--listen") {
+        config.listen_address = listen_address.to_string();
     }
+    config
+}
 
-    fn run_rpc_server(epoch: u64, dir_path: &str) {
-        let output = Command::new("faithful-cli")
-            .arg("rpc-server-car")
-            .arg("--listen")
-            .arg(":7999")
-            .arg(format!("{}/epoch-{}.car", dir_path, epoch))
-            .arg(format!("{}/epoch-{}.car.*.cid-to-offset.index", dir_path, epoch))
-            .arg(format!("{}/epoch-{}.car.*.slot-to-cid.index", dir_path, epoch))
-            .arg(format!("{}/epoch-{}.car.*.sig-to-cid.index", dir_path, epoch))
-            .output()
-            .expect("Failed to execute command");
+/// Converts `local` into archive volumes, reading its blocks from a supervised faithful-cli that is
+/// stopped again afterwards.
+async fn convert(local: &LocalEpochFiles, config: FaithfulCliConfig, archive_dir: &Path) -> Result<u64, Box<dyn Error>> {
+    let supervisor = FaithfulCliSupervisor::new(config, local.clone());
+    let rpc_url = supervisor.rpc_url();
+    let mut health = supervisor.health();
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let supervising = supervisor.supervise(shutdown_rx);
+    tokio::pin!(supervising);
+    let conversion = async {
+        health.wait_for(|healthy| *healthy).await.map_err(|_| "faithful-cli health channel closed")?;
+        old_faithful_converter::convert_epoch(local, &rpc_url, archive_dir, ARCHIVE_MINUTES_PER_BUCKET, DEFAULT_BACKFILL_CONCURRENCY).await
+    };
+    let result = tokio::select! {
+        result = conversion => result,
+        result = &mut supervising => {
+            return Err(result.err().unwrap_or_else(|| "faithful-cli stopped before the conversion finished".into()));
+        }
+    };
+    shutdown_tx.send_replace(true);
+    supervising.await?;
+    result
+}
 
-        if !output.status.success() {
-            eprintln!("Failed to run RPC server: {:?}", output);
+/// Serves `local` with a supervised faithful-cli until SIGTERM or Ctrl-C.
+async fn serve(local: LocalEpochFiles, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let config = faithful_cli_config(args);
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        let mut sigterm = signal(SignalKind::terminate()).expect("Failed to install SIGTERM handler");
+        tokio::select! {
+            _ = sigterm.recv() => info!("Received SIGTERM"),
+            _ = tokio::signal::ctrl_c() => info!("Received Ctrl-C"),
         }
-    }
+        let _ = shutdown_tx.send(true);
+    });
+    FaithfulCliSupervisor::new(config, local).supervise(shutdown_rx).await
+}
+
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter().position(|arg| arg == flag).and_then(|index| args.get(index + 1)).map(|value| value.as_str())
 }
 
+// Usage: old_faithful_solana_connector [--epoch N | --epochs A-B] [--state-file FILE] [--base-url URL] [--parallel-chunks N]
+//                                     [--data-dir DIR] [--car-source-dir DIR] [--convert] [--archive-dir DIR] [--serve]
+//                                     [--verify] [--report-dir DIR] [--faithful-cli PATH] [--listen ADDR]
+// Without an epoch, the latest epoch Old Faithful has published is processed. With --serve, the RPC server
+// is started for the last completed epoch once processing finishes. --base-url (or OLD_FAITHFUL_BASE_URL)
+// points downloads at a mirror. CAR files already present in --car-source-dir are used instead of being downloaded.
+// With --convert, each completed epoch not yet converted is re-emitted into archive volumes in --archive-dir.
+// With --verify, the live archive in --archive-dir is checked against each completed epoch, and a report is
+// written to verification_report_epoch_<N>.json in --report-dir.
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    OldFaithfulSolanaConnector::new().await?;
+    let config = Config::builder()
+        .appender(Appender::builder().build("console", Box::new(ConsoleAppender::builder().build())))
+        .build(Root::builder().appender("console").build(LevelFilter::Info))?;
+    log4rs::init_config(config)?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let base_url = flag_value(&args, "--base-url").map(|url| url.trim_end_matches('/').to_string()).unwrap_or_else(old_faithful_epochs::old_faithful_base_url);
+    let mut download_options = DownloadOptions::default();
+    if let Some(parallel_chunks) = flag_value(&args, "--parallel-chunks") {
+        download_options.parallel_chunks = parallel_chunks.parse()?;
+    }
+    let data_dir = PathBuf::from(flag_value(&args, "--data-dir").unwrap_or(DEFAULT_OLD_FAITHFUL_DATA_DIRECTORY));
+    let car_source_dir = flag_value(&args, "--car-source-dir").map(PathBuf::from);
+    let connector = OldFaithfulSolanaConnector::new(base_url, data_dir, car_source_dir, download_options);
+    let epochs = match flag_value(&args, "--epochs").or_else(|| flag_value(&args, "--epoch")) {
+        Some(value) => value.parse::<EpochRange>()?,
+        None => {
+            let latest = old_faithful_epochs::latest_available_epoch(&connector.base_url).await?;
+            info!("No epoch specified; using the latest available epoch {}", latest);
+            EpochRange { first: latest, last: latest }
+        }
+    };
+    let state_file = Path::new(flag_value(&args, "--state-file").unwrap_or(DEFAULT_EPOCH_STATE_FILE));
+    let completed = connector.run(epochs, state_file).await?;
+    if args.iter().any(|arg| arg == "--convert") {
+        let archive_dir = Path::new(flag_value(&args, "--archive-dir").unwrap_or(DEFAULT_ARCHIVE_DIRECTORY));
+        let mut state = EpochStateFile::load(state_file)?;
+        for local in &completed {
+            if state.is_converted(local.files.epoch) {
+                continue;
+            }
+            let blocks = convert(local, faithful_cli_config(&args), archive_dir).await?;
+            state.mark_converted(local.files.epoch, blocks)?;
+        }
+    }
+    if args.iter().any(|arg| arg == "--verify") {
+        let archive_dir = Path::new(flag_value(&args, "--archive-dir").unwrap_or(DEFAULT_ARCHIVE_DIRECTORY));
+        let report_dir = Path::new(flag_value(&args, "--report-dir").unwrap_or(DEFAULT_REPORT_DIRECTORY));
+        for local in &completed {
+            let report = archive_verifier::verify_epoch(archive_dir, local, None)?;
+            let report_path = report_dir.join(format!("verification_report_epoch_{}.json", local.files.epoch));
+            std::fs::write(&report_path, serde_json::to_string_pretty(&report)?)?;
+            info!("Wrote verification report for epoch {} to {}", local.files.epoch, report_path.display());
+        }
+    }
+    if args.iter().any(|arg| arg == "--serve") {
+        match completed.last() {
+            Some(local) => serve(local.clone(), &args).await?,
+            None => warn!("No completed epoch to serve"),
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,264 @@
+use futures::stream::{self, StreamExt, TryStreamExt};
+use log::{info, warn};
+use reqwest::header::{ACCEPT_RANGES, CONTENT_LENGTH, RANGE};
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+const PROGRESS_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+const MAX_CHUNK_ATTEMPTS: u32 = 5;
+
+pub struct DownloadOptions {
+    /// Number of byte ranges fetched at once when the server supports range requests.
+    pub parallel_chunks: usize,
+    pub chunk_size: u64,
+    /// Hex-encoded SHA-256 the finished file must match, if one is published.
+    pub expected_sha256: Option<String>,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self { parallel_chunks: 8, chunk_size: 256 * 1024 * 1024, expected_sha256: None }
+    }
+}
+
+struct ProgressReporter {
+    label: String,
+    total: Option<u64>,
+    done: AtomicU64,
+    last_report: Mutex<Instant>,
+    started: Instant,
+}
+
+impl ProgressReporter {
+    fn new(label: &str, total: Option<u64>, already_done: u64) -> Self {
+        Self { label: label.to_string(), total, done: AtomicU64::new(already_done), last_report: Mutex::new(Instant::now()), started: Instant::now() }
+    }
+
+    fn add(&self, bytes: u64) {
+        let done = self.done.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        let mut last_report = self.last_report.lock().unwrap();
+        if last_report.elapsed() >= PROGRESS_REPORT_INTERVAL {
+            *last_report = Instant::now();
+            let rate = done as f64 / 1_048_576.0 / self.started.elapsed().as_secs_f64().max(0.001);
+            match self.total {
+                Some(total) => info!("Downloading {}: {:.1}% ({} of {} bytes, {:.1} MB/s)", self.label, done as f64 * 100.0 / total.max(1) as f64, done, total, rate),
+                None => info!("Downloading {}: {} bytes ({:.1} MB/s)", self.label, done, rate),
+            }
+        }
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    PathBuf::from(format!("{}{}", path.display(), suffix))
+}
+
+/// Size and range support as advertised by the server.
+async fn probe(client: &Client, url: &str) -> Result<(Option<u64>, bool), Box<dyn Error>> {
+    let response = client.head(url).send().await?.error_for_status()?;
+    let length = response.headers().get(CONTENT_LENGTH).and_then(|value| value.to_str().ok()).and_then(|value| value.parse().ok());
+    let ranges = response.headers().get(ACCEPT_RANGES).and_then(|value| value.to_str().ok()) == Some("bytes");
+    Ok((length, ranges))
+}
+
+/// Fetches the checksum published next to `url` as `<url>.sha256`, if there is one. Any client error
+/// counts as none being published, since hosts answer a missing object with 403 as often as with 404.
+pub async fn fetch_published_sha256(url: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let response = Client::new().get(format!("{}.sha256", url)).send().await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if response.status().is_client_error() {
+        warn!("No checksum for {} ({}); the download will only be checked against its size", url, response.status());
+        return Ok(None);
+    }
+    let body = response.error_for_status()?.text().await?;
+    Ok(body.split_whitespace().next().map(|checksum| checksum.to_lowercase()))
+}
+
+/// Downloads `url` to `destination`, streaming to a `.part` file that is renamed into place only after
+/// its size (and checksum, if given) has been verified. An interrupted download resumes from where it
+/// stopped: single-stream downloads continue with a range request from the end of the `.part` file, and
+/// chunked downloads record finished chunks in a `.part.progress` file. Returns the file size.
+pub async fn download_file(url: &str, destination: &Path, options: &DownloadOptions) -> Result<u64, Box<dyn Error>> {
+    let client = Client::new();
+    let (length, ranges) = probe(&client, url).await?;
+    if let (Ok(metadata), Some(length)) = (tokio::fs::metadata(destination).await, length) {
+        if metadata.len() == length {
+            info!("{} is already downloaded", destination.display());
+            return Ok(length);
+        }
+    }
+    if let Some(parent) = destination.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let part_path = with_suffix(destination, ".part");
+    let label = destination.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_else(|| url.to_string());
+    let downloaded = match length {
+        Some(length) if ranges && options.parallel_chunks > 1 && length > options.chunk_size => {
+            download_chunked(&client, url, &part_path, length, options, &label).await?
+        }
+        _ => download_single_stream(&client, url, &part_path, ranges, length, &label).await?,
+    };
+    if let Some(length) = length {
+        if downloaded != length {
+            return Err(format!("Downloaded {} bytes of {} but the server reported {} bytes", downloaded, url, length).into());
+        }
+    }
+    if let Some(expected) = &options.expected_sha256 {
+        let actual = sha256_file(&part_path).await?;
+        if !actual.eq_ignore_ascii_case(expected) {
+            tokio::fs::remove_file(&part_path).await?; // A corrupt download cannot be resumed
+            return Err(format!("Checksum mismatch for {}: expected {}, got {}", url, expected, actual).into());
+        }
+        info!("Verified SHA-256 of {}", label);
+    }
+    tokio::fs::rename(&part_path, destination).await?;
+    let _ = tokio::fs::remove_file(with_suffix(destination, ".part.progress")).await;
+    info!("Downloaded {} to {} ({} bytes)", url, destination.display(), downloaded);
+    Ok(downloaded)
+}
+
+async fn download_single_stream(client: &Client, url: &str, part_path: &Path, ranges: bool, length: Option<u64>, label: &str) -> Result<u64, Box<dyn Error>> {
+    let mut offset = match tokio::fs::metadata(part_path).await {
+        Ok(metadata) if ranges => metadata.len(),
+        _ => 0,
+    };
+    if length.is_some_and(|length| offset > length) {
+        offset = 0; // The remote file changed; start over
+    }
+    if offset > 0 && length == Some(offset) {
+        info!("{} was fully downloaded before it was interrupted", label);
+        return Ok(offset); // A range request from the end would be refused with 416
+    }
+    let mut request = client.get(url);
+    if offset > 0 {
+        info!("Resuming download of {} at byte {}", label, offset);
+        request = request.header(RANGE, format!("bytes={}-", offset));
+    }
+    let response = request.send().await?.error_for_status()?;
+    if offset > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+        warn!("Server ignored the range request for {}; restarting the download", label);
+        offset = 0;
+    }
+    let mut file = OpenOptions::new().create(true).write(true).truncate(offset == 0).open(part_path).await?;
+    file.seek(SeekFrom::Start(offset)).await?;
+    let progress = ProgressReporter::new(label, length, offset);
+    let mut body = response.bytes_stream();
+    let received = loop {
+        match body.try_next().await {
+            Ok(Some(bytes)) => {
+                file.write_all(&bytes).await?;
+                progress.add(bytes.len() as u64);
+            }
+            Ok(None) => break Ok(()),
+            Err(err) => break Err(err),
+        }
+    };
+    file.flush().await?; // Even when interrupted, so a retry resumes after every byte already received
+    received?;
+    Ok(file.metadata().await?.len())
+}
+
+fn load_completed_chunks(progress_path: &Path, length: u64, chunk_size: u64) -> BTreeSet<u64> {
+    let Ok(contents) = std::fs::read_to_string(progress_path) else { return BTreeSet::new() };
+    let Ok(progress) = serde_json::from_str::<Value>(&contents) else { return BTreeSet::new() };
+    if progress["length"].as_u64() != Some(length) || progress["chunk_size"].as_u64() != Some(chunk_size) {
+        return BTreeSet::new(); // Recorded for a different file or chunking; start over
+    }
+    progress["completed_chunks"].as_array().map(|chunks| chunks.iter().filter_map(|chunk| chunk.as_u64()).collect()).unwrap_or_default()
+}
+
+async fn download_chunked(client: &Client, url: &str, part_path: &Path, length: u64, options: &DownloadOptions, label: &str) -> Result<u64, Box<dyn Error>> {
+    let progress_path = with_suffix(part_path, ".progress");
+    let chunk_count = length.div_ceil(options.chunk_size);
+    let mut completed = load_completed_chunks(&progress_path, length, options.chunk_size);
+    if !part_path.exists() {
+        completed.clear();
+    }
+    let file = OpenOptions::new().create(true).write(true).truncate(completed.is_empty()).open(part_path).await?;
+    file.set_len(length).await?;
+    let already_done: u64 = completed.iter().map(|chunk| chunk_bounds(*chunk, options.chunk_size, length)).map(|(start, end)| end - start + 1).sum();
+    if already_done > 0 {
+        info!("Resuming download of {} with {} of {} chunks complete", label, completed.len(), chunk_count);
+    }
+    let progress = ProgressReporter::new(label, Some(length), already_done);
+    let pending: Vec<u64> = (0..chunk_count).filter(|chunk| !completed.contains(chunk)).collect();
+    let mut results = stream::iter(pending)
+        .map(|chunk| {
+            let progress = &progress;
+            async move { (chunk, download_chunk_with_retries(client, url, part_path, chunk_bounds(chunk, options.chunk_size, length), progress).await) }
+        })
+        .buffer_unordered(options.parallel_chunks);
+    while let Some((chunk, result)) = results.next().await {
+        result?;
+        completed.insert(chunk);
+        let record = json!({"length": length, "chunk_size": options.chunk_size, "completed_chunks": completed});
+        tokio::fs::write(&progress_path, record.to_string()).await?;
+    }
+    Ok(length)
+}
+
+fn chunk_bounds(chunk: u64, chunk_size: u64, length: u64) -> (u64, u64) {
+    let start = chunk * chunk_size;
+    (start, (start + chunk_size).min(length) - 1)
+}
+
+async fn download_chunk_with_retries(client: &Client, url: &str, part_path: &Path, (start, end): (u64, u64), progress: &ProgressReporter) -> Result<(), Box<dyn Error>> {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match download_chunk(client, url, part_path, start, end, progress).await {
+            Ok(()) => return Ok(()),
+            Err(err) if attempt < MAX_CHUNK_ATTEMPTS => {
+                warn!("Error downloading bytes {}-{} of {} (attempt {}/{}): {}", start, end, url, attempt, MAX_CHUNK_ATTEMPTS, err);
+                tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+            }
+            Err(err) => return Err(format!("Failed to download bytes {}-{} of {}: {}", start, end, url, err).into()),
+        }
+    }
+}
+
+async fn download_chunk(client: &Client, url: &str, part_path: &Path, start: u64, end: u64, progress: &ProgressReporter) -> Result<(), Box<dyn Error>> {
+    let response = client.get(url).header(RANGE, format!("bytes={}-{}", start, end)).send().await?.error_for_status()?;
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        return Err(format!("Expected a partial response for bytes {}-{}, got {}", start, end, response.status()).into());
+    }
+    let mut file = OpenOptions::new().write(true).open(part_path).await?;
+    file.seek(SeekFrom::Start(start)).await?;
+    let mut written = 0;
+    let mut body = response.bytes_stream();
+    while let Some(bytes) = body.try_next().await? {
+        file.write_all(&bytes).await?;
+        written += bytes.len() as u64;
+        progress.add(bytes.len() as u64);
+    }
+    file.flush().await?;
+    if written != end - start + 1 {
+        return Err(format!("Received {} bytes for range {}-{}", written, start, end).into());
+    }
+    Ok(())
+}
+
+pub async fn sha256_file(path: &Path) -> Result<String, Box<dyn Error>> {
+    let mut file = File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
@@ -0,0 +1,189 @@
+use crate::data_archiver::manifest_path;
+use crate::volume_catalog::{CatalogVolume, VolumeCatalog};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use log::{error, info, warn};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+pub const RETENTION_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What happens to a volume once it may leave the archive directory.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EvictionAction {
+    Delete,
+    /// Moves the volume and its manifest into this directory, e.g. on a larger, slower disk.
+    MoveTo(PathBuf),
+}
+
+/// Which volumes are kept in the archive directory. Only volumes whose Cascade upload is confirmed, and
+/// whose file still matches the checksum in its manifest, are ever evicted; with no policy set, that
+/// only happens when the disk monitor asks for space.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetentionPolicy {
+    /// Volumes finalized within this long are kept, unless `max_local_bytes` needs the room.
+    pub keep_recent: Option<ChronoDuration>,
+    /// Once the archive's volumes take more than this, the oldest evictable volumes go first, however recent.
+    pub max_local_bytes: Option<u64>,
+    /// Streams ("live", "backfill", "old_faithful") whose volumes are kept forever.
+    pub keep_forever_streams: Vec<String>,
+    pub action: EvictionAction,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self { keep_recent: None, max_local_bytes: None, keep_forever_streams: Vec::new(), action: EvictionAction::Delete }
+    }
+}
+
+impl RetentionPolicy {
+    /// Reads `RETENTION_KEEP_HOURS`, `RETENTION_MAX_GB`, `RETENTION_KEEP_STREAMS` (comma separated) and
+    /// `RETENTION_MOVE_TO`. Returns None, meaning keep everything, when neither limit is set.
+    pub fn from_env() -> Result<Option<Self>, Box<dyn Error>> {
+        let number = |name: &str| -> Result<Option<f64>, Box<dyn Error>> {
+            match std::env::var(name) {
+                Ok(value) => Ok(Some(value.parse().map_err(|_| format!("Invalid value for {}: {}", name, value))?)),
+                Err(_) => Ok(None),
+            }
+        };
+        let keep_recent = number("RETENTION_KEEP_HOURS")?.map(|hours| ChronoDuration::seconds((hours * 3600.0) as i64));
+        let max_local_bytes = number("RETENTION_MAX_GB")?.map(|gigabytes| (gigabytes * 1024.0 * 1024.0 * 1024.0) as u64);
+        if keep_recent.is_none() && max_local_bytes.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            keep_recent,
+            max_local_bytes,
+            keep_forever_streams: std::env::var("RETENTION_KEEP_STREAMS").unwrap_or_default().split(',').map(str::trim).filter(|stream| !stream.is_empty()).map(String::from).collect(),
+            action: std::env::var("RETENTION_MOVE_TO").map(|directory| EvictionAction::MoveTo(PathBuf::from(directory))).unwrap_or(EvictionAction::Delete),
+        }))
+    }
+}
+
+/// Outcome of one `enforce` pass.
+#[derive(Debug, Default, PartialEq)]
+pub struct RetentionReport {
+    pub evicted: Vec<String>,
+    pub evicted_bytes: u64,
+    /// Local bytes of the volumes still present afterwards.
+    pub remaining_bytes: u64,
+    /// Volumes that were due for eviction but failed their checksum check.
+    pub checksum_mismatches: Vec<String>,
+}
+
+pub struct RetentionManager {
+    policy: RetentionPolicy,
+    archive_dir: PathBuf,
+    catalog: VolumeCatalog,
+}
+
+impl RetentionManager {
+    pub fn new(policy: RetentionPolicy, archive_dir: &Path, catalog: VolumeCatalog) -> Self {
+        Self { policy, archive_dir: archive_dir.to_path_buf(), catalog }
+    }
+
+    fn volume_bytes(&self, volume: &CatalogVolume) -> u64 {
+        std::fs::metadata(self.archive_dir.join(&volume.file_name)).map(|metadata| metadata.len()).or_else(|_| volume.compressed_bytes.ok_or(())).unwrap_or(0)
+    }
+
+    fn evictable(&self, volume: &CatalogVolume) -> bool {
+        volume.upload_state == "confirmed" && volume.cascade_txid.is_some() && !self.policy.keep_forever_streams.contains(&volume.stream)
+    }
+
+    /// Evicts what the policy allows, oldest volumes first: every evictable volume older than
+    /// `keep_recent`, then more evictable volumes while the archive is over `max_local_bytes`.
+    pub fn enforce(&self, now: DateTime<Utc>) -> Result<RetentionReport, Box<dyn Error>> {
+        self.evict_until(now, 0)
+    }
+
+    /// Enforces the policy, then keeps evicting the oldest evictable volumes until at least
+    /// `bytes_needed` have been freed, however recent they are. Used when the disk is running out.
+    pub fn free_space(&self, bytes_needed: u64, now: DateTime<Utc>) -> Result<RetentionReport, Box<dyn Error>> {
+        self.evict_until(now, bytes_needed)
+    }
+
+    fn evict_until(&self, now: DateTime<Utc>, bytes_needed: u64) -> Result<RetentionReport, Box<dyn Error>> {
+        self.catalog.sync_directory(&self.archive_dir)?;
+        let mut volumes = self.catalog.present_volumes()?;
+        volumes.sort_by(|a, b| (&a.finalized_at, &a.file_name).cmp(&(&b.finalized_at, &b.file_name)));
+        let mut report = RetentionReport { remaining_bytes: volumes.iter().map(|volume| self.volume_bytes(volume)).sum(), ..RetentionReport::default() };
+        for volume in volumes.iter().filter(|volume| self.evictable(volume)) {
+            let finalized_at = volume.finalized_at.as_deref().and_then(|time| time.parse::<DateTime<Utc>>().ok());
+            let expired = matches!((self.policy.keep_recent, finalized_at), (Some(keep_recent), Some(finalized_at)) if now - finalized_at > keep_recent);
+            let over_budget = self.policy.max_local_bytes.is_some_and(|max_local_bytes| report.remaining_bytes > max_local_bytes);
+            let needs_room = report.evicted_bytes < bytes_needed;
+            if !expired && !over_budget && !needs_room {
+                continue;
+            }
+            if !self.checksum_matches(volume)? {
+                warn!("Keeping {}: its contents no longer match the checksum in its manifest", volume.file_name);
+                report.checksum_mismatches.push(volume.file_name.clone());
+                continue;
+            }
+            let bytes = self.volume_bytes(volume);
+            self.evict_volume(volume)?;
+            report.evicted.push(volume.file_name.clone());
+            report.evicted_bytes += bytes;
+            report.remaining_bytes -= bytes;
+        }
+        if !report.evicted.is_empty() {
+            info!("Retention evicted {} volumes ({:.1} MB); {:.1} MB of volumes remain", report.evicted.len(), report.evicted_bytes as f64 / 1_048_576.0, report.remaining_bytes as f64 / 1_048_576.0);
+        }
+        if self.policy.max_local_bytes.is_some_and(|max_local_bytes| report.remaining_bytes > max_local_bytes) {
+            warn!("Archive holds {:.1} MB of volumes, over the retention limit, but nothing else may be evicted yet", report.remaining_bytes as f64 / 1_048_576.0);
+        }
+        Ok(report)
+    }
+
+    fn checksum_matches(&self, volume: &CatalogVolume) -> Result<bool, Box<dyn Error>> {
+        let Some(expected) = &volume.sha256 else { return Ok(false) }; // Nothing to check against
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut File::open(self.archive_dir.join(&volume.file_name))?, &mut hasher)?;
+        Ok(hex::encode(hasher.finalize()) == *expected)
+    }
+
+    fn evict_volume(&self, volume: &CatalogVolume) -> Result<(), Box<dyn Error>> {
+        let path = self.archive_dir.join(&volume.file_name);
+        match &self.policy.action {
+            EvictionAction::Delete => {
+                std::fs::remove_file(&path)?;
+                std::fs::remove_file(manifest_path(&path)).ok();
+                self.catalog.record_deletion(&volume.file_name, None)
+            }
+            EvictionAction::MoveTo(directory) => {
+                std::fs::create_dir_all(directory)?;
+                let destination = directory.join(&volume.file_name);
+                move_file(&manifest_path(&path), &manifest_path(&destination)).ok();
+                move_file(&path, &destination)?;
+                self.catalog.record_deletion(&volume.file_name, Some(&destination))
+            }
+        }
+    }
+
+    /// Enforces the policy every `RETENTION_CHECK_INTERVAL`, and frees space whenever the disk monitor
+    /// asks for it through `free_space_requests`. Never returns.
+    pub async fn run(self, mut free_space_requests: UnboundedReceiver<u64>) {
+        let mut bytes_needed = 0;
+        loop {
+            if let Err(err) = self.free_space(bytes_needed, Utc::now()) {
+                error!("Error enforcing the retention policy: {}", err);
+            }
+            tokio::select! {
+                Some(request) = free_space_requests.recv() => bytes_needed = request,
+                _ = tokio::time::sleep(RETENTION_CHECK_INTERVAL) => bytes_needed = 0,
+            }
+        }
+    }
+}
+
+/// Renames `from` to `to`, copying when they are on different filesystems.
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    std::fs::copy(from, to)?;
+    std::fs::remove_file(from)
+}
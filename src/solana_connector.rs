@@ -1,19 +1,45 @@
+use std::env;
+use dotenv::dotenv;
 use tokio_tungstenite::tungstenite::Message;
 use futures_util::StreamExt;
 use futures_util::SinkExt;
 use std::error::Error;
 use serde_json::json;
 use tokio_tungstenite::connect_async;
-use tokio::sync::mpsc::Sender;
-use log::{info, debug, error};
+use tokio::sync::mpsc::{Sender, UnboundedSender};
+use crate::event_log;
+use crate::fault_supervisor::{Fault, FaultSource};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use log::{info, debug, error, warn};
 
-pub struct SolanaConnector;
+/// A live PubSub connection. Dropping it leaves the connection running; `shutdown` unsubscribes and closes it.
+pub struct SolanaConnector {
+    stop: Arc<Notify>,
+    reader: JoinHandle<()>,
+}
+
+const DEFAULT_PUBSUB_URL: &str = "wss://solana-mainnet.rpc.extrnode.com";
+const UNSUBSCRIBE_TIMEOUT: Duration = Duration::from_secs(5); // How long to wait for unsubscribe acknowledgments
+const UNSUBSCRIBE_ID_OFFSET: u64 = 1000; // Unsubscribe request ids are the subscription request id plus this
 
 impl SolanaConnector {
-    pub async fn new(tx: Sender<String>, verbose_logging: bool) -> Result<SolanaConnector, Box<dyn Error>> {
-        let free_service_url = "wss://solana-mainnet.rpc.extrnode.com";
+    /// Connects to SOLANA_WS_URL if set, otherwise to the default free PubSub endpoint.
+    pub async fn new(tx: Sender<String>, faults: UnboundedSender<Fault>, verbose_logging: bool) -> Result<SolanaConnector, Box<dyn Error>> {
+        dotenv().ok(); // Load the .env file
+        let url = env::var("SOLANA_WS_URL").unwrap_or_else(|_| DEFAULT_PUBSUB_URL.to_string());
+        Self::connect(&url, tx, faults, verbose_logging).await
+    }
+
+    /// Subscribes and forwards every message to `tx`. If the connection fails or the server closes it,
+    /// a recoverable connector fault is reported on `faults`.
+    pub async fn connect(url: &str, tx: Sender<String>, faults: UnboundedSender<Fault>, verbose_logging: bool) -> Result<SolanaConnector, Box<dyn Error>> {
         info!("Initializing Solana connector...");
-        let (ws_stream, _) = connect_async(free_service_url).await?;
+        let (ws_stream, _) = connect_async(url).await?;
+        event_log::record("connected", json!({"url": url}));
         let (write, mut read) = ws_stream.split();
         let subscriptions = vec![
             json!({"jsonrpc": "2.0", "id": 1, "method": "slotSubscribe"}),
@@ -24,13 +50,14 @@ impl SolanaConnector {
             json!({"jsonrpc": "2.0", "id": 6, "method": "voteSubscribe"})
         ];
         let mut write_handle = write.sink_map_err(|e| format!("WebSocket write error: {}", e));
+        let mut subscription_ids = HashMap::new(); // Subscription request id to the subscription id the server assigned
         for subscription in &subscriptions {
             write_handle.send(Message::Text(subscription.to_string())).await?;
             if verbose_logging {
                 let ack = read.next().await;
                 match ack {
                     Some(Ok(response)) => {
-                        let json_response: Result<serde_json::Value, _> = serde_json::from_str(&response.to_text()?);
+                        let json_response: Result<serde_json::Value, _> = serde_json::from_str(response.to_text()?);
                         if let Ok(json_response) = json_response {
                             let id = json_response.get("id").and_then(|v| v.as_u64()).unwrap_or(0);
                             if id == subscription["id"].as_u64().unwrap_or(0) {
@@ -38,32 +65,31 @@ impl SolanaConnector {
                             } else {
                                 debug!("Unexpected acknowledgment for id {}", id);
                             }
+                            record_subscription_id(&json_response, &subscriptions, &mut subscription_ids);
                         }
                     }
                     _ => debug!("Failed to receive acknowledgment for subscription")
                 }
             }
         }
-        tokio::spawn(async move {
-            while let Some(msg) = read.next().await {
+        let stop = Arc::new(Notify::new());
+        let stop_requested = stop.clone();
+        let reader = tokio::spawn(async move {
+            loop {
+                let msg = tokio::select! {
+                    msg = read.next() => msg,
+                    _ = stop_requested.notified() => break,
+                };
+                let Some(msg) = msg else {
+                    event_log::record("disconnected", json!({"reason": "closed by the server"}));
+                    faults.send(Fault::recoverable(FaultSource::Connector, "PubSub connection closed by the server")).ok();
+                    return;
+                };
                 match msg {
                     Ok(msg) => {
                         if msg.is_text() {
                             match msg.to_text() {
-                                Ok(message_content) => {
-                                    let json_msg: Result<serde_json::Value, _> = serde_json::from_str(&message_content);
-                                    if let Ok(json_msg) = json_msg {
-                                        if verbose_logging {
-                                            let method = json_msg.get("method").and_then(|v| v.as_str()).unwrap_or("");
-                                            debug!("Received message of type {}, length: {}", method, message_content.len());
-                                        }
-                                        if tx.send(message_content.to_string()).await.is_err() {
-                                            error!("Failed to send message to receiver.");
-                                        }
-                                    } else {
-                                        error!("Failed to parse JSON message: {}", message_content);
-                                    }
-                                }
+                                Ok(message_content) => forward_message(message_content, &tx, verbose_logging, &subscriptions, &mut subscription_ids).await,
                                 Err(err) => {
                                     error!("Error converting message to text: {}", err);
                                 }
@@ -72,11 +98,78 @@ impl SolanaConnector {
                     }
                     Err(err) => {
                         error!("Error reading message: {}", err);
-                        break;
+                        event_log::record("disconnected", json!({"reason": err.to_string()}));
+                        faults.send(Fault::recoverable(FaultSource::Connector, format!("Error reading message: {}", err))).ok();
+                        return;
                     }
                 }
             }
+            // Stop intake: unsubscribe, keep forwarding whatever was already in flight until every
+            // unsubscribe is acknowledged, then close the connection.
+            let mut pending_acks = 0;
+            for (request_id, subscription_id) in &subscription_ids {
+                let subscribe_method = subscriptions.iter().find(|subscription| subscription["id"] == *request_id).and_then(|subscription| subscription["method"].as_str()).unwrap_or_default();
+                let unsubscribe = json!({"jsonrpc": "2.0", "id": request_id + UNSUBSCRIBE_ID_OFFSET, "method": subscribe_method.replace("Subscribe", "Unsubscribe"), "params": [subscription_id]});
+                if write_handle.send(Message::Text(unsubscribe.to_string())).await.is_ok() {
+                    pending_acks += 1;
+                }
+            }
+            let _ = tokio::time::timeout(UNSUBSCRIBE_TIMEOUT, async {
+                while pending_acks > 0 {
+                    match read.next().await {
+                        Some(Ok(msg)) if msg.is_text() => {
+                            let message_content = msg.to_text().unwrap_or_default();
+                            let is_unsubscribe_ack = serde_json::from_str::<serde_json::Value>(message_content).ok().and_then(|response| response["id"].as_u64()).is_some_and(|id| id > UNSUBSCRIBE_ID_OFFSET);
+                            if is_unsubscribe_ack {
+                                pending_acks -= 1;
+                            } else {
+                                forward_message(message_content, &tx, verbose_logging, &subscriptions, &mut subscription_ids).await;
+                            }
+                        }
+                        Some(Ok(_)) => {}
+                        _ => break,
+                    }
+                }
+            })
+            .await;
+            if pending_acks > 0 {
+                warn!("{} unsubscribe requests were not acknowledged", pending_acks);
+            }
+            write_handle.send(Message::Close(None)).await.ok();
+            event_log::record("disconnected", json!({"reason": "shutdown", "unacknowledged_unsubscribes": pending_acks}));
+            info!("Solana connector stopped");
         });
-        Ok(SolanaConnector)
+        Ok(SolanaConnector { stop, reader })
+    }
+
+    /// Stops intake: unsubscribes from every acknowledged subscription, forwards the notifications that
+    /// were already in flight, and closes the connection. Returns once nothing more will be sent on `tx`.
+    pub async fn shutdown(self) {
+        self.stop.notify_one();
+        self.reader.await.ok();
+    }
+}
+
+fn record_subscription_id(response: &serde_json::Value, subscriptions: &[serde_json::Value], subscription_ids: &mut HashMap<u64, u64>) {
+    if let (Some(request_id), Some(subscription_id)) = (response["id"].as_u64(), response["result"].as_u64()) {
+        let method = subscriptions.iter().find(|subscription| subscription["id"] == request_id).map(|subscription| subscription["method"].clone());
+        event_log::record("subscription_acknowledged", json!({"request_id": request_id, "method": method, "subscription_id": subscription_id}));
+        subscription_ids.insert(request_id, subscription_id);
+    }
+}
+
+async fn forward_message(message_content: &str, tx: &Sender<String>, verbose_logging: bool, subscriptions: &[serde_json::Value], subscription_ids: &mut HashMap<u64, u64>) {
+    let json_msg: Result<serde_json::Value, _> = serde_json::from_str(message_content);
+    if let Ok(json_msg) = json_msg {
+        if verbose_logging {
+            let method = json_msg.get("method").and_then(|v| v.as_str()).unwrap_or("");
+            debug!("Received message of type {}, length: {}", method, message_content.len());
+        }
+        record_subscription_id(&json_msg, subscriptions, subscription_ids);
+        if tx.send(message_content.to_string()).await.is_err() {
+            error!("Failed to send message to receiver.");
+        }
+    } else {
+        error!("Failed to parse JSON message: {}", message_content);
     }
 }
@@ -1,3 +1,6 @@
+// Read-only Solana JSON-RPC methods used by the archival service. Methods that submit or simulate
+// transactions live in `solana_rest_api_write_functions`, behind the `transaction-submission` feature.
+
 use reqwest::Client;
 use reqwest::Response;
 use serde_json::{json, Value};
@@ -140,10 +143,6 @@ pub async fn get_transaction(params: Value) -> Result<Value, Box<dyn Error>> {
     post_request("getTransaction", params).await
 }
 
-pub async fn send_transaction(params: Value) -> Result<Value, Box<dyn Error>> {
-    post_request("sendTransaction", params).await
-}
-
 pub async fn get_signature_statuses(params: Value) -> Result<Value, Box<dyn Error>> {
     post_request("getSignatureStatuses", params).await
 }
@@ -152,10 +151,6 @@ pub async fn get_signatures_for_address(params: Value) -> Result<Value, Box<dyn
     post_request("getSignaturesForAddress", params).await
 }
 
-pub async fn simulate_transaction(params: Value) -> Result<Value, Box<dyn Error>> {
-    post_request("simulateTransaction", params).await
-}
-
 // Slot Information
 
 pub async fn minimum_ledger_slot() -> Result<Value, Box<dyn Error>> {
@@ -196,8 +191,29 @@ pub async fn get_token_supply(params: Value) -> Result<Value, Box<dyn Error>> {
     post_request("getTokenSupply", params).await
 }
 
+// Methods that can change chain state; refused unless the `transaction-submission` feature is enabled
+const WRITE_METHODS: [&str; 3] = ["sendTransaction", "simulateTransaction", "requestAirdrop"];
+
+// Resolves the JSON-RPC endpoint: SOLANA_RPC_URL if set (e.g. a local mirror or test server), otherwise Alchemy
+pub fn rpc_url() -> String {
+    dotenv().ok(); // Load the .env file
+    if let Ok(url) = env::var("SOLANA_RPC_URL") {
+        return url;
+    }
+    let alchemy_api_key = env::var("ALCHEMY_API_KEY").expect("ALCHEMY_API_KEY must be set"); // Retrieve the Alchemy API key from the environment variable
+    format!("https://solana-mainnet.g.alchemy.com/v2/{}", alchemy_api_key)
+}
+
 // Helper function to make a POST request
 pub async fn post_request(method: &str, params: Value) -> Result<Value, Box<dyn Error>> {
+    post_request_to(&rpc_url(), method, params).await
+}
+
+// Helper function to make a POST request to a specific endpoint, such as a local faithful-cli
+pub async fn post_request_to(url: &str, method: &str, params: Value) -> Result<Value, Box<dyn Error>> {
+    if !cfg!(feature = "transaction-submission") && WRITE_METHODS.contains(&method) {
+        return Err(format!("{} is disabled; rebuild with the transaction-submission feature to enable it", method).into());
+    }
     let client = Client::new();
     let payload = json!({
         "id": 1,
@@ -206,12 +222,8 @@ pub async fn post_request(method: &str, params: Value) -> Result<Value, Box<dyn
         "params": params
     });
 
-    dotenv().ok(); // Load the .env file
-    let alchemy_api_key = env::var("ALCHEMY_API_KEY").expect("ALCHEMY_API_KEY must be set"); // Retrieve the Alchemy API key from the environment variable
-    let alchemy_url = format!("https://solana-mainnet.g.alchemy.com/v2/{}", alchemy_api_key);
-
     let response: Response = client
-        .post(alchemy_url)
+        .post(url)
         .header("Content-Type", "application/json")
         .body(payload.to_string())
         .send()
@@ -219,4 +231,26 @@ pub async fn post_request(method: &str, params: Value) -> Result<Value, Box<dyn
 
     let data: Value = response.json().await?;
     Ok(data)
-}
\ No newline at end of file
+}
+
+// Helper function to unwrap the "result" of a JSON-RPC response, turning an RPC "error" object into an Err
+pub fn extract_result(response: Value) -> Result<Value, Box<dyn Error>> {
+    if let Some(error) = response.get("error") {
+        return Err(format!("RPC error: {}", error).into());
+    }
+    response.get("result").cloned().ok_or_else(|| "RPC response is missing a result".into())
+}
+
+#[cfg(all(test, not(feature = "transaction-submission")))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn write_methods_are_refused_without_the_feature() {
+        for method in WRITE_METHODS {
+            // Nothing listens on the discard port, so only the guard can produce this error
+            let err = post_request_to("http://127.0.0.1:9", method, json!([])).await.unwrap_err();
+            assert!(err.to_string().contains("transaction-submission feature"), "{}: {}", method, err);
+        }
+    }
+}
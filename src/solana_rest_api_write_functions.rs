@@ -0,0 +1,18 @@
+// Write-capable Solana JSON-RPC methods. These can submit transactions with the configured API key, so
+// they are only compiled when the `transaction-submission` cargo feature is explicitly enabled.
+
+use crate::solana_rest_api_functions::post_request;
+use serde_json::Value;
+use std::error::Error;
+
+pub async fn send_transaction(params: Value) -> Result<Value, Box<dyn Error>> {
+    post_request("sendTransaction", params).await
+}
+
+pub async fn simulate_transaction(params: Value) -> Result<Value, Box<dyn Error>> {
+    post_request("simulateTransaction", params).await
+}
+
+pub async fn request_airdrop(params: Value) -> Result<Value, Box<dyn Error>> {
+    post_request("requestAirdrop", params).await
+}
@@ -0,0 +1,483 @@
+use crate::data_archiver::manifest_path;
+use chrono::{DateTime, Utc};
+use log::warn;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const DEFAULT_CATALOG_FILE: &str = "archive_catalog.sqlite3";
+pub const UNKNOWN_STREAM: &str = "unknown"; // Volumes finalized before manifests existed
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30); // The ingester, uploader and query tools share the file
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS volumes (
+    file_name TEXT PRIMARY KEY,
+    path TEXT NOT NULL,
+    stream TEXT NOT NULL,
+    epoch INTEGER,
+    bucket_start_time TEXT,
+    minutes_per_bucket INTEGER,
+    volume_number INTEGER,
+    first_slot INTEGER,
+    last_slot INTEGER,
+    message_count INTEGER,
+    uncompressed_bytes INTEGER,
+    compressed_bytes INTEGER,
+    sha256 TEXT,
+    finalized_at TEXT,
+    upload_state TEXT NOT NULL DEFAULT 'pending',
+    upload_attempts INTEGER NOT NULL DEFAULT 0,
+    upload_error TEXT,
+    cascade_file_id TEXT,
+    cascade_task_id TEXT,
+    cascade_txid TEXT,
+    uploaded_at TEXT,
+    deletion_state TEXT NOT NULL DEFAULT 'present',
+    deleted_at TEXT
+);
+CREATE INDEX IF NOT EXISTS volumes_by_slot ON volumes (first_slot, last_slot);
+CREATE INDEX IF NOT EXISTS volumes_by_upload_state ON volumes (upload_state);
+CREATE TABLE IF NOT EXISTS indexed_volumes (
+    file_name TEXT PRIMARY KEY
+);
+CREATE TABLE IF NOT EXISTS blocks (
+    slot INTEGER PRIMARY KEY,
+    file_name TEXT NOT NULL,
+    block_time INTEGER
+);
+CREATE TABLE IF NOT EXISTS transactions (
+    signature TEXT PRIMARY KEY,
+    slot INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    err TEXT
+);
+CREATE INDEX IF NOT EXISTS transactions_by_position ON transactions (slot, position);
+CREATE TABLE IF NOT EXISTS transaction_accounts (
+    account TEXT NOT NULL,
+    slot INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    PRIMARY KEY (account, slot, position)
+) WITHOUT ROWID;
+";
+
+/// One row of the catalog. `upload_state` is "pending", "uploaded", "registering", "confirmed" or
+/// "failed"; `deletion_state` is "present" until retention removes the local file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CatalogVolume {
+    pub file_name: String,
+    pub path: PathBuf,
+    pub stream: String,
+    pub epoch: Option<u64>,
+    pub bucket_start_time: Option<String>,
+    pub volume_number: Option<u64>,
+    pub first_slot: Option<u64>,
+    pub last_slot: Option<u64>,
+    pub message_count: Option<u64>,
+    pub compressed_bytes: Option<u64>,
+    pub sha256: Option<String>,
+    pub finalized_at: Option<String>,
+    pub upload_state: String,
+    pub upload_attempts: u64,
+    pub upload_error: Option<String>,
+    pub cascade_file_id: Option<String>,
+    pub cascade_task_id: Option<String>,
+    pub cascade_txid: Option<String>,
+    pub deletion_state: String,
+}
+
+const COLUMNS: &str = "file_name, path, stream, epoch, bucket_start_time, first_slot, last_slot, message_count, compressed_bytes, sha256, finalized_at, upload_state, upload_attempts, upload_error, cascade_file_id, cascade_task_id, cascade_txid, deletion_state, volume_number";
+
+impl CatalogVolume {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            file_name: row.get(0)?,
+            path: PathBuf::from(row.get::<_, String>(1)?),
+            stream: row.get(2)?,
+            epoch: row.get(3)?,
+            bucket_start_time: row.get(4)?,
+            first_slot: row.get(5)?,
+            last_slot: row.get(6)?,
+            message_count: row.get(7)?,
+            compressed_bytes: row.get(8)?,
+            sha256: row.get(9)?,
+            finalized_at: row.get(10)?,
+            upload_state: row.get(11)?,
+            upload_attempts: row.get(12)?,
+            upload_error: row.get(13)?,
+            cascade_file_id: row.get(14)?,
+            cascade_task_id: row.get(15)?,
+            cascade_txid: row.get(16)?,
+            deletion_state: row.get(17)?,
+            volume_number: row.get(18)?,
+        })
+    }
+
+    /// The row as a JSON object, with the same field names.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "file_name": self.file_name,
+            "path": self.path.display().to_string(),
+            "stream": self.stream,
+            "epoch": self.epoch,
+            "bucket_start_time": self.bucket_start_time,
+            "volume_number": self.volume_number,
+            "first_slot": self.first_slot,
+            "last_slot": self.last_slot,
+            "message_count": self.message_count,
+            "compressed_bytes": self.compressed_bytes,
+            "sha256": self.sha256,
+            "finalized_at": self.finalized_at,
+            "upload_state": self.upload_state,
+            "upload_attempts": self.upload_attempts,
+            "upload_error": self.upload_error,
+            "cascade_file_id": self.cascade_file_id,
+            "cascade_task_id": self.cascade_task_id,
+            "cascade_txid": self.cascade_txid,
+            "deletion_state": self.deletion_state,
+        })
+    }
+
+    /// Where the volume comes in archive order: by time bucket, then by volume number within the bucket.
+    /// Volumes without a manifest come first. The catalog lists volumes in this order.
+    pub fn order_key(&self) -> (Option<&str>, Option<u64>, &str) {
+        (self.bucket_start_time.as_deref(), self.volume_number, &self.file_name)
+    }
+
+    pub fn upload_progress(&self) -> UploadProgress {
+        UploadProgress { file_id: self.cascade_file_id.clone(), task_id: self.cascade_task_id.clone(), txid: self.cascade_txid.clone(), attempts: self.upload_attempts, error: self.upload_error.clone() }
+    }
+}
+
+/// Where the upload to Cascade of one volume stands, as recorded by `record_upload_progress`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UploadProgress {
+    pub file_id: Option<String>,
+    pub task_id: Option<String>,
+    pub txid: Option<String>,
+    pub attempts: u64,
+    pub error: Option<String>,
+}
+
+/// One archived block as the transaction index records it: where it is, and for each of its transactions
+/// (by position in the block) the signature, error and every account it references.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IndexedBlock {
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub transactions: Vec<IndexedTransaction>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IndexedTransaction {
+    pub signature: String,
+    pub err: Value,
+    pub accounts: Vec<String>,
+}
+
+/// The volume an indexed block is in, and the block's time.
+pub type BlockLocation = (String, Option<i64>);
+
+/// A transaction found in the index: its block's slot and time, and its position in the block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransactionLocation {
+    pub signature: String,
+    pub slot: u64,
+    pub position: u64,
+    pub block_time: Option<i64>,
+    pub err: Value,
+    pub file_name: String,
+}
+
+impl TransactionLocation {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            signature: row.get(0)?,
+            slot: row.get(1)?,
+            position: row.get(2)?,
+            block_time: row.get(3)?,
+            err: row.get::<_, Option<String>>(4)?.and_then(|err| serde_json::from_str(&err).ok()).unwrap_or(Value::Null),
+            file_name: row.get(5)?,
+        })
+    }
+}
+
+const TRANSACTION_COLUMNS: &str = "transactions.signature, transactions.slot, transactions.position, blocks.block_time, transactions.err, blocks.file_name";
+
+/// SQLite catalog of every finalized volume in an archive directory: where it is, which slots and
+/// bucket it covers, its checksum and size, and how far its upload and local retention have got.
+pub struct VolumeCatalog {
+    connection: Connection,
+}
+
+impl VolumeCatalog {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let connection = Connection::open(path)?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+
+    /// The catalog kept in `archive_dir` itself.
+    pub fn open_in(archive_dir: &Path) -> Result<Self, Box<dyn Error>> {
+        Self::open(&archive_dir.join(DEFAULT_CATALOG_FILE))
+    }
+
+    /// Adds a finalized volume from its manifest, or refreshes its manifest fields if it is already known.
+    /// Upload and deletion state are left alone.
+    pub fn record_volume(&self, volume_path: &Path, manifest: &Value) -> Result<(), Box<dyn Error>> {
+        let file_name = volume_path.file_name().ok_or("Volume path has no file name")?.to_string_lossy().to_string();
+        self.connection.execute(
+            "INSERT INTO volumes (file_name, path, stream, epoch, bucket_start_time, minutes_per_bucket, volume_number, first_slot, last_slot, message_count, uncompressed_bytes, compressed_bytes, sha256, finalized_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+             ON CONFLICT (file_name) DO UPDATE SET path = ?2, stream = ?3, epoch = ?4, bucket_start_time = ?5, minutes_per_bucket = ?6, volume_number = ?7, first_slot = ?8, last_slot = ?9,
+                 message_count = ?10, uncompressed_bytes = ?11, compressed_bytes = ?12, sha256 = ?13, finalized_at = ?14",
+            params![
+                file_name,
+                volume_path.to_string_lossy(),
+                manifest["source"].as_str().unwrap_or(UNKNOWN_STREAM),
+                manifest["epoch"].as_u64(),
+                manifest["bucket_start_time"].as_str(),
+                manifest["minutes_per_bucket"].as_i64(),
+                manifest["volume_number"].as_u64(),
+                manifest["first_slot"].as_u64(),
+                manifest["last_slot"].as_u64(),
+                manifest["message_count"].as_u64(),
+                manifest["uncompressed_bytes"].as_u64(),
+                manifest["compressed_bytes"].as_u64(),
+                manifest["sha256"].as_str(),
+                manifest["finalized_at"].as_str(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Catalogs every finalized volume in `archive_dir` the catalog does not know yet, such as volumes
+    /// written before it existed. Volumes without a manifest are added with an unknown slot range.
+    /// Returns how many were added.
+    pub fn sync_directory(&self, archive_dir: &Path) -> Result<usize, Box<dyn Error>> {
+        let mut added = 0;
+        for entry in std::fs::read_dir(archive_dir)? {
+            let path = entry?.path();
+            let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            if !file_name.ends_with(".zstd") || file_name.ends_with(".temp.zstd") || self.volume(&file_name)?.is_some() {
+                continue;
+            }
+            let manifest = match std::fs::read_to_string(manifest_path(&path)) {
+                Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|err| {
+                    warn!("Ignoring unreadable manifest for {}: {}", file_name, err);
+                    Value::Null
+                }),
+                Err(_) => Value::Null,
+            };
+            self.record_volume(&path, &manifest)?;
+            added += 1;
+        }
+        Ok(added)
+    }
+
+    pub fn volume(&self, file_name: &str) -> Result<Option<CatalogVolume>, Box<dyn Error>> {
+        let query = format!("SELECT {} FROM volumes WHERE file_name = ?1", COLUMNS);
+        Ok(self.connection.query_row(&query, [file_name], CatalogVolume::from_row).optional()?)
+    }
+
+    /// Volumes matching `condition`, in archive order (see `CatalogVolume::order_key`). Bucket times are all
+    /// RFC 3339 in UTC, so they sort as text.
+    fn select(&self, condition: &str, params: impl rusqlite::Params) -> Result<Vec<CatalogVolume>, Box<dyn Error>> {
+        let query = format!("SELECT {} FROM volumes WHERE {} ORDER BY bucket_start_time, volume_number, file_name", COLUMNS, condition);
+        let mut statement = self.connection.prepare(&query)?;
+        let volumes = statement.query_map(params, CatalogVolume::from_row)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(volumes)
+    }
+
+    pub fn all_volumes(&self) -> Result<Vec<CatalogVolume>, Box<dyn Error>> {
+        self.select("1", [])
+    }
+
+    /// Volumes that may hold data for slots `first_slot..=last_slot`, including those whose range is unknown.
+    pub fn volumes_for_slots(&self, first_slot: u64, last_slot: u64) -> Result<Vec<CatalogVolume>, Box<dyn Error>> {
+        self.select("first_slot IS NULL OR (first_slot <= ?2 AND last_slot >= ?1)", params![first_slot, last_slot])
+    }
+
+    /// Volumes whose time bucket overlaps `start..end`. Volumes with no bucket time are left out.
+    pub fn volumes_for_time_range(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<CatalogVolume>, Box<dyn Error>> {
+        self.select(
+            "julianday(bucket_start_time) < julianday(?2) AND julianday(bucket_start_time) + COALESCE(minutes_per_bucket, 0) / 1440.0 > julianday(?1)",
+            params![start.to_rfc3339(), end.to_rfc3339()],
+        )
+    }
+
+    pub fn epoch_volumes(&self, stream: &str, epoch: u64) -> Result<Vec<CatalogVolume>, Box<dyn Error>> {
+        self.select("stream = ?1 AND epoch = ?2", params![stream, epoch])
+    }
+
+    /// Volumes still on local disk whose upload has not been confirmed.
+    pub fn pending_uploads(&self) -> Result<Vec<CatalogVolume>, Box<dyn Error>> {
+        self.select("upload_state != 'confirmed' AND deletion_state = 'present'", [])
+    }
+
+    pub fn record_upload_progress(&self, file_name: &str, state: &str, progress: &UploadProgress) -> Result<(), Box<dyn Error>> {
+        let uploaded_at = (state == "confirmed").then(|| Utc::now().to_rfc3339());
+        let updated = self.connection.execute(
+            "UPDATE volumes SET upload_state = ?2, cascade_file_id = ?3, cascade_task_id = ?4, cascade_txid = ?5, upload_attempts = ?6, upload_error = ?7, uploaded_at = COALESCE(?8, uploaded_at) WHERE file_name = ?1",
+            params![file_name, state, progress.file_id, progress.task_id, progress.txid, progress.attempts, progress.error, uploaded_at],
+        )?;
+        if updated == 0 {
+            return Err(format!("{} is not in the catalog", file_name).into());
+        }
+        Ok(())
+    }
+
+    /// Volumes whose file is still in the archive directory, oldest first.
+    pub fn present_volumes(&self) -> Result<Vec<CatalogVolume>, Box<dyn Error>> {
+        self.select("deletion_state = 'present'", [])
+    }
+
+    /// Lowest first slot and highest last slot of the volumes that can still be read locally.
+    pub fn slot_bounds(&self) -> Result<Option<(u64, u64)>, Box<dyn Error>> {
+        let (first_slot, last_slot): (Option<u64>, Option<u64>) =
+            self.connection.query_row("SELECT MIN(first_slot), MAX(last_slot) FROM volumes WHERE deletion_state != 'deleted'", [], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(first_slot.zip(last_slot))
+    }
+
+    /// Volumes whose blocks are not in the transaction index yet, in archive order. Deleted volumes are left out.
+    pub fn unindexed_volumes(&self) -> Result<Vec<CatalogVolume>, Box<dyn Error>> {
+        self.select("deletion_state != 'deleted' AND file_name NOT IN (SELECT file_name FROM indexed_volumes)", [])
+    }
+
+    /// Adds the blocks of one volume to the transaction index and marks it indexed. Volumes are indexed in
+    /// archive order, so a slot captured more than once stays indexed at its first capture.
+    pub fn record_index(&self, file_name: &str, blocks: &[IndexedBlock]) -> Result<(), Box<dyn Error>> {
+        let transaction = self.connection.unchecked_transaction()?;
+        for block in blocks {
+            if transaction.execute("INSERT OR IGNORE INTO blocks (slot, file_name, block_time) VALUES (?1, ?2, ?3)", params![block.slot, file_name, block.block_time])? == 0 {
+                continue; // Already indexed from an earlier capture
+            }
+            for (position, indexed) in block.transactions.iter().enumerate() {
+                let err = (!indexed.err.is_null()).then(|| indexed.err.to_string());
+                transaction.execute("INSERT OR IGNORE INTO transactions (signature, slot, position, err) VALUES (?1, ?2, ?3, ?4)", params![indexed.signature, block.slot, position, err])?;
+                for account in &indexed.accounts {
+                    transaction.execute("INSERT OR IGNORE INTO transaction_accounts (account, slot, position) VALUES (?1, ?2, ?3)", params![account, block.slot, position])?;
+                }
+            }
+        }
+        transaction.execute("INSERT OR IGNORE INTO indexed_volumes (file_name) VALUES (?1)", [file_name])?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    pub fn indexed_block(&self, slot: u64) -> Result<Option<BlockLocation>, Box<dyn Error>> {
+        Ok(self.connection.query_row("SELECT file_name, block_time FROM blocks WHERE slot = ?1", [slot], |row| Ok((row.get(0)?, row.get(1)?))).optional()?)
+    }
+
+    /// Indexed slots between `first_slot` and `last_slot`, in order.
+    pub fn indexed_slots(&self, first_slot: u64, last_slot: u64) -> Result<Vec<u64>, Box<dyn Error>> {
+        let mut statement = self.connection.prepare("SELECT slot FROM blocks WHERE slot BETWEEN ?1 AND ?2 ORDER BY slot")?;
+        let slots = statement.query_map([first_slot, last_slot], |row| row.get(0))?.collect::<Result<Vec<_>, _>>()?;
+        Ok(slots)
+    }
+
+    pub fn highest_indexed_slot(&self) -> Result<Option<u64>, Box<dyn Error>> {
+        Ok(self.connection.query_row("SELECT MAX(slot) FROM blocks", [], |row| row.get(0))?)
+    }
+
+    pub fn indexed_transaction(&self, signature: &str) -> Result<Option<TransactionLocation>, Box<dyn Error>> {
+        let query = format!("SELECT {} FROM transactions JOIN blocks ON blocks.slot = transactions.slot WHERE transactions.signature = ?1", TRANSACTION_COLUMNS);
+        Ok(self.connection.query_row(&query, [signature], TransactionLocation::from_row).optional()?)
+    }
+
+    /// Up to `limit` indexed transactions referencing `account`, newest first, strictly between the
+    /// (slot, position) bounds `after` and `before` where given.
+    pub fn account_transactions(&self, account: &str, before: Option<(u64, u64)>, after: Option<(u64, u64)>, limit: usize) -> Result<Vec<TransactionLocation>, Box<dyn Error>> {
+        let query = format!(
+            "SELECT {} FROM transaction_accounts
+             JOIN transactions ON transactions.slot = transaction_accounts.slot AND transactions.position = transaction_accounts.position
+             JOIN blocks ON blocks.slot = transaction_accounts.slot
+             WHERE transaction_accounts.account = ?1 AND (transaction_accounts.slot, transaction_accounts.position) < (?2, ?3) AND (transaction_accounts.slot, transaction_accounts.position) > (?4, ?5)
+             ORDER BY transaction_accounts.slot DESC, transaction_accounts.position DESC LIMIT ?6",
+            TRANSACTION_COLUMNS
+        );
+        let (before_slot, before_position) = before.unwrap_or((i64::MAX as u64, 0));
+        let (after_slot, after_position) = after.map_or((-1, -1), |(slot, position)| (slot as i64, position as i64));
+        let mut statement = self.connection.prepare(&query)?;
+        let transactions = statement.query_map(params![account, before_slot, before_position, after_slot, after_position, limit], TransactionLocation::from_row)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(transactions)
+    }
+
+    /// Marks a volume's local file as "deleted", or as "moved" to `moved_to`.
+    pub fn record_deletion(&self, file_name: &str, moved_to: Option<&Path>) -> Result<(), Box<dyn Error>> {
+        let (deletion_state, path) = match moved_to {
+            Some(path) => ("moved", Some(path.to_string_lossy().to_string())),
+            None => ("deleted", None),
+        };
+        self.connection.execute(
+            "UPDATE volumes SET deletion_state = ?2, path = COALESCE(?3, path), deleted_at = ?4 WHERE file_name = ?1",
+            params![file_name, deletion_state, path, Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn finds_volumes_by_slot_and_keeps_upload_state_on_refresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = VolumeCatalog::open_in(dir.path()).unwrap();
+        catalog.record_volume(&dir.path().join("a.zstd"), &json!({"source": "live", "first_slot": 10, "last_slot": 20})).unwrap();
+        catalog.record_volume(&dir.path().join("b.zstd"), &json!({"source": "backfill", "first_slot": 21, "last_slot": 30})).unwrap();
+        catalog.record_volume(&dir.path().join("c.zstd"), &Value::Null).unwrap();
+
+        let names = |volumes: Vec<CatalogVolume>| volumes.into_iter().map(|volume| volume.file_name).collect::<Vec<_>>();
+        assert_eq!(names(catalog.volumes_for_slots(15, 15).unwrap()), ["a.zstd", "c.zstd"]);
+        assert_eq!(names(catalog.volumes_for_slots(20, 21).unwrap()), ["a.zstd", "b.zstd", "c.zstd"]);
+        assert_eq!(catalog.volume("c.zstd").unwrap().unwrap().stream, UNKNOWN_STREAM);
+
+        let progress = UploadProgress { txid: Some("txid".to_string()), ..UploadProgress::default() };
+        catalog.record_upload_progress("a.zstd", "confirmed", &progress).unwrap();
+        catalog.record_volume(&dir.path().join("a.zstd"), &json!({"source": "live", "first_slot": 10, "last_slot": 25})).unwrap();
+        let volume = catalog.volume("a.zstd").unwrap().unwrap();
+        assert_eq!((volume.upload_state.as_str(), volume.last_slot), ("confirmed", Some(25)));
+        assert_eq!(names(catalog.pending_uploads().unwrap()), ["b.zstd", "c.zstd"]);
+        assert!(catalog.record_upload_progress("missing.zstd", "confirmed", &progress).is_err());
+    }
+
+    #[test]
+    fn lists_volumes_by_bucket_then_volume_number() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = VolumeCatalog::open_in(dir.path()).unwrap();
+        for (file_name, bucket_start_time, volume_number) in [("12:01_Volume_1.zstd", "2023-09-01T12:01:00+00:00", 1), ("12:00_Volume_10.zstd", "2023-09-01T12:00:00+00:00", 10), ("12:00_Volume_2.zstd", "2023-09-01T12:00:00+00:00", 2)] {
+            catalog.record_volume(&dir.path().join(file_name), &json!({"bucket_start_time": bucket_start_time, "volume_number": volume_number})).unwrap();
+        }
+        catalog.record_volume(&dir.path().join("legacy.zstd"), &Value::Null).unwrap();
+
+        let names: Vec<String> = catalog.all_volumes().unwrap().into_iter().map(|volume| volume.file_name).collect();
+        assert_eq!(names, ["legacy.zstd", "12:00_Volume_2.zstd", "12:00_Volume_10.zstd", "12:01_Volume_1.zstd"]);
+    }
+
+    #[test]
+    fn indexes_each_slot_once_and_pages_account_transactions() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = VolumeCatalog::open_in(dir.path()).unwrap();
+        catalog.record_volume(&dir.path().join("a.zstd"), &Value::Null).unwrap();
+        catalog.record_volume(&dir.path().join("b.zstd"), &Value::Null).unwrap();
+        let transaction = |signature: &str, accounts: &[&str]| IndexedTransaction { signature: signature.to_string(), err: Value::Null, accounts: accounts.iter().map(|account| account.to_string()).collect() };
+        let block = |slot, transactions| IndexedBlock { slot, block_time: Some(100 + slot as i64), transactions };
+        catalog.record_index("a.zstd", &[block(10, vec![transaction("s1", &["x"]), transaction("s2", &["x", "y"])]), block(11, vec![transaction("s3", &["x"])])]).unwrap();
+        catalog.record_index("b.zstd", &[block(11, vec![transaction("s3-again", &["x"])]), block(12, vec![transaction("s4", &["y"])])]).unwrap();
+
+        assert!(catalog.unindexed_volumes().unwrap().is_empty());
+        assert_eq!(catalog.indexed_block(11).unwrap(), Some(("a.zstd".to_string(), Some(111))), "first capture wins");
+        assert_eq!(catalog.indexed_transaction("s3-again").unwrap(), None);
+        assert_eq!((catalog.indexed_slots(11, 20).unwrap(), catalog.highest_indexed_slot().unwrap()), (vec![11, 12], Some(12)));
+        let signatures = |before, after, limit| catalog.account_transactions("x", before, after, limit).unwrap().into_iter().map(|location| location.signature).collect::<Vec<_>>();
+        assert_eq!(signatures(None, None, 10), ["s3", "s2", "s1"]);
+        assert_eq!(signatures(Some((10, 1)), None, 10), ["s1"]);
+        assert_eq!(signatures(None, Some((10, 0)), 1), ["s3"]);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::data_archiver::message_slot;
+use crate::volume_catalog::{CatalogVolume, VolumeCatalog};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde_json::Value;
+use std::error::Error;
+use std::fs::{create_dir_all, read_dir, File};
+use std::io::{BufReader, BufWriter, Result as IOResult, Write};
+use std::path::{Path, PathBuf};
+use zstd::stream::read::Decoder;
+
+pub const DEFAULT_DEBUG_EXTRACT_DIRECTORY: &str = "extracted_compressed_message_blobs";
+
+/// Which archived messages to extract.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExtractSelection {
+    /// One volume, by path or by file name in the archive directory.
+    Volume(PathBuf),
+    /// Messages for these slots, from every volume that may hold them.
+    Slots { first_slot: u64, last_slot: u64 },
+    /// Every message in the volumes whose time bucket overlaps `start..end`.
+    Time { start: DateTime<Utc>, end: DateTime<Utc> },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExtractFormat {
+    /// One compact JSON message per line.
+    Ndjson,
+    /// A pretty-printed JSON array.
+    Pretty,
+}
+
+/// Volumes holding the selection, in archive order. Volumes evicted from local disk are skipped with a
+/// warning; moved volumes are read from where they were moved to.
+pub fn selected_volumes(archive_dir: &Path, selection: &ExtractSelection) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    if let ExtractSelection::Volume(volume) = selection {
+        let path = if volume.exists() { volume.clone() } else { archive_dir.join(volume) };
+        return if path.exists() { Ok(vec![path]) } else { Err(format!("Volume {} does not exist", volume.display()).into()) };
+    }
+    let catalog = VolumeCatalog::open_in(archive_dir)?;
+    catalog.sync_directory(archive_dir)?;
+    let volumes = match selection {
+        ExtractSelection::Slots { first_slot, last_slot } => catalog.volumes_for_slots(*first_slot, *last_slot)?,
+        ExtractSelection::Time { start, end } => catalog.volumes_for_time_range(*start, *end)?,
+        ExtractSelection::Volume(_) => unreachable!(),
+    };
+    Ok(volumes.iter().filter_map(|volume| local_path(archive_dir, volume)).collect())
+}
+
+/// Where a cataloged volume can be read from: the archive directory, or where retention moved it. Volumes
+/// evicted from local disk have none, and are logged.
+pub fn local_path(archive_dir: &Path, volume: &CatalogVolume) -> Option<PathBuf> {
+    match volume.deletion_state.as_str() {
+        "present" => Some(archive_dir.join(&volume.file_name)),
+        "moved" => Some(volume.path.clone()),
+        _ => {
+            warn!("Skipping {}: it was evicted from local disk{}", volume.file_name, volume.cascade_txid.as_ref().map(|txid| format!(" (Cascade ticket {})", txid)).unwrap_or_default());
+            None
+        }
+    }
+}
+
+/// Decompresses the selected messages into `output`. Returns how many messages were written.
+pub fn extract(archive_dir: &Path, selection: &ExtractSelection, format: ExtractFormat, output: &mut dyn Write) -> Result<usize, Box<dyn Error>> {
+    let mut output = BufWriter::new(output);
+    let mut written = 0;
+    if format == ExtractFormat::Pretty {
+        output.write_all(b"[")?;
+    }
+    for volume in selected_volumes(archive_dir, selection)? {
+        let decoder = BufReader::new(Decoder::new(File::open(&volume)?)?);
+        for message in serde_json::Deserializer::from_reader(decoder).into_iter::<Value>() {
+            let message = message.map_err(|err| format!("Malformed message in {}: {}", volume.display(), err))?;
+            let text = message.to_string();
+            if let ExtractSelection::Slots { first_slot, last_slot } = selection {
+                match message_slot(&text) {
+                    Some(slot) if slot >= *first_slot && slot <= *last_slot => {}
+                    _ => continue,
+                }
+            }
+            match format {
+                ExtractFormat::Ndjson => writeln!(output, "{}", text)?,
+                ExtractFormat::Pretty => {
+                    output.write_all(if written == 0 { b"\n" } else { b",\n" })?;
+                    serde_json::to_writer_pretty(&mut output, &message)?;
+                }
+            }
+            written += 1;
+        }
+    }
+    if format == ExtractFormat::Pretty {
+        output.write_all(if written == 0 { b"]\n" } else { b"\n]\n" })?;
+    }
+    output.flush()?;
+    Ok(written)
+}
+
+/// Decompresses every finalized volume in `archive_dir` that has not been decompressed into
+/// `output_dir` yet. Only for debugging: it doubles the disk space the archive takes.
+pub fn decompress_new_volumes(archive_dir: &Path, output_dir: &Path) -> IOResult<usize> {
+    create_dir_all(output_dir)?;
+    let mut decompressed = 0;
+    for entry in read_dir(archive_dir)? {
+        let path = entry?.path();
+        if !path.is_file() || path.extension() != Some(std::ffi::OsStr::new("zstd")) {
+            continue;
+        }
+        let Some(file_stem) = path.file_stem().map(|stem| stem.to_string_lossy().to_string()) else { continue };
+        let output_file_name = output_dir.join(&file_stem);
+        if file_stem.ends_with(".temp") || output_file_name.exists() {
+            continue; // Still being written, or already decompressed
+        }
+        let mut decompressor = Decoder::new(BufReader::new(File::open(&path)?))?;
+        std::io::copy(&mut decompressor, &mut File::create(&output_file_name)?)?;
+        info!("Successfully decompressed {} into {}", path.display(), output_file_name.display());
+        decompressed += 1;
+    }
+    Ok(decompressed)
+}
@@ -0,0 +1,36 @@
+use log::LevelFilter;
+use pastel_solana_archival_data_integration_api::admin_server::{self, LOG_LEVELS_PATH};
+use pastel_solana_archival_data_integration_api::logging_config::{LoggingController, LoggingSettings};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+
+// Installing a logger is process-wide, so this binary holds a single test.
+#[tokio::test]
+async fn changes_log_levels_at_runtime() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("log4rs.yaml");
+    std::fs::write(&config_path, "appenders:\n  stdout:\n    kind: console\n    encoder:\n      kind: json\nroot:\n  level: info\n  appenders: [stdout]\n").unwrap();
+    let settings = LoggingSettings { config_file: Some(config_path), ..LoggingSettings::default() };
+    let logging = Arc::new(Mutex::new(LoggingController::init(settings).unwrap()));
+    let (addr, server) = admin_server::bind("127.0.0.1:0".parse().unwrap(), logging).unwrap();
+    tokio::spawn(server);
+    let url = format!("http://{}{}", addr, LOG_LEVELS_PATH);
+    let client = reqwest::Client::new();
+
+    let levels: Value = client.get(&url).send().await.unwrap().json().await.unwrap();
+    assert_eq!(levels, json!({"root": "info", "modules": {}}));
+    assert_eq!(log::max_level(), LevelFilter::Info);
+
+    let response = client.put(&url).json(&json!({"module": "reqwest", "level": "trace"})).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.json::<Value>().await.unwrap(), json!({"root": "info", "modules": {"reqwest": "trace"}}));
+    assert_eq!(log::max_level(), LevelFilter::Trace);
+
+    client.put(&url).json(&json!({"level": "warn"})).send().await.unwrap();
+    let levels: Value = client.get(&url).send().await.unwrap().json().await.unwrap();
+    assert_eq!(levels["root"], "warn");
+
+    let rejected = client.put(&url).json(&json!({"level": "loud"})).send().await.unwrap();
+    assert_eq!(rejected.status(), 400);
+    assert_eq!(client.get(format!("http://{}/nope", addr)).send().await.unwrap().status(), 404);
+}
@@ -0,0 +1,104 @@
+mod support;
+
+use pastel_solana_archival_data_integration_api::archive_query_server::{self, Page, RecordFilter, VolumeRange, NEXT_CURSOR_HEADER};
+use pastel_solana_archival_data_integration_api::historical_backfiller::block_notification_message;
+use serde_json::{json, Value};
+use std::path::Path;
+use support::slot_notification_message;
+
+const PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+const ACCOUNT: &str = "4fYNw3dojWmQ4dXtSGE9epjRGy9pFSx62YypT7avPYvA";
+
+fn notification(method: &str, slot: u64, value: Value) -> String {
+    json!({"jsonrpc": "2.0", "method": method, "params": {"result": {"context": {"slot": slot}, "value": value}, "subscription": 1}}).to_string()
+}
+
+/// Two volumes: slots 1-3 from 12:00 and slots 4-6 from 12:01.
+fn write_archive(dir: &Path) {
+    support::write_archive(dir, [
+        (0, slot_notification_message(1)),
+        (0, block_notification_message(2, json!({"blockhash": "hash2", "parentSlot": 1, "transactions": []}))),
+        (0, notification("programNotification", 3, json!({"pubkey": ACCOUNT, "account": {"owner": PROGRAM, "lamports": 1}}))),
+        (1, block_notification_message(4, json!({"blockhash": "hash4", "parentSlot": 2, "transactions": []}))),
+        (1, notification("logsNotification", 5, json!({"signature": "sig5", "err": null, "logs": [format!("Program {} invoke [1]", PROGRAM)]}))),
+        (1, slot_notification_message(6)),
+    ]);
+}
+
+async fn start_server(archive_dir: &Path) -> String {
+    let (addr, server) = archive_query_server::bind("127.0.0.1:0".parse().unwrap(), archive_dir.to_path_buf()).unwrap();
+    tokio::spawn(server);
+    format!("http://{}", addr)
+}
+
+/// The NDJSON lines of a response, and its next-page cursor.
+async fn get_lines(url: &str, query: &[(&str, &str)]) -> (Vec<Value>, Option<String>) {
+    let response = reqwest::Client::new().get(url).query(query).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    let cursor = response.headers().get(NEXT_CURSOR_HEADER).map(|cursor| cursor.to_str().unwrap().to_string());
+    let lines = response.text().await.unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    (lines, cursor)
+}
+
+fn slots(records: &[Value]) -> Vec<u64> {
+    records.iter().map(|record| record["params"]["result"]["context"]["slot"].as_u64().or(record["params"]["result"]["slot"].as_u64()).unwrap()).collect()
+}
+
+#[tokio::test]
+async fn lists_volumes_and_fetches_blocks_by_slot() {
+    let archive_dir = tempfile::tempdir().unwrap();
+    write_archive(archive_dir.path());
+    let base_url = start_server(archive_dir.path()).await;
+    let volumes_url = format!("{}/volumes", base_url);
+
+    let (first_page, cursor) = get_lines(&volumes_url, &[("limit", "1")]).await;
+    assert_eq!((first_page.len(), first_page[0]["first_slot"].as_u64()), (1, Some(1)));
+    let (second_page, cursor) = get_lines(&volumes_url, &[("limit", "1"), ("cursor", &cursor.unwrap())]).await;
+    assert_eq!((second_page.len(), second_page[0]["first_slot"].as_u64(), cursor), (1, Some(4), None));
+    let (by_slot, _) = get_lines(&volumes_url, &[("start_slot", "5"), ("end_slot", "9")]).await;
+    assert_eq!(by_slot, second_page);
+    let (by_time, _) = get_lines(&volumes_url, &[("start_time", "2023-09-01T11:00:00Z"), ("end_time", "2023-09-01T12:00:30Z")]).await;
+    assert_eq!(by_time, first_page);
+
+    let block: Value = reqwest::get(format!("{}/blocks/4", base_url)).await.unwrap().json().await.unwrap();
+    assert_eq!(block["blockhash"], "hash4");
+    assert_eq!(reqwest::get(format!("{}/blocks/3", base_url)).await.unwrap().status(), 404);
+    assert_eq!(reqwest::get(format!("{}/blocks/latest", base_url)).await.unwrap().status(), 400);
+    assert_eq!(reqwest::get(format!("{}/volumes?limit=0", base_url)).await.unwrap().status(), 400);
+}
+
+#[tokio::test]
+async fn filters_and_pages_through_records() {
+    let archive_dir = tempfile::tempdir().unwrap();
+    write_archive(archive_dir.path());
+    let records_url = format!("{}/records", start_server(archive_dir.path()).await);
+
+    assert_eq!(slots(&get_lines(&records_url, &[("method", "blockNotification")]).await.0), [2, 4]);
+    assert_eq!(slots(&get_lines(&records_url, &[("program", PROGRAM)]).await.0), [3, 5]);
+    assert_eq!(slots(&get_lines(&records_url, &[("account", ACCOUNT)]).await.0), [3]);
+    assert_eq!(slots(&get_lines(&records_url, &[("start_slot", "2"), ("end_slot", "4")]).await.0), [2, 3, 4]);
+
+    let (first_page, cursor) = get_lines(&records_url, &[("limit", "4")]).await;
+    let (second_page, last_cursor) = get_lines(&records_url, &[("limit", "4"), ("cursor", &cursor.unwrap())]).await;
+    assert_eq!((slots(&first_page), slots(&second_page), last_cursor), (vec![1, 2, 3, 4], vec![5, 6], None));
+    assert_eq!(reqwest::get(format!("{}?cursor=nonsense", records_url)).await.unwrap().status(), 400);
+}
+
+#[test]
+fn records_pages_stop_at_the_scan_budget() {
+    let archive_dir = tempfile::tempdir().unwrap();
+    write_archive(archive_dir.path());
+    let filter = RecordFilter { method: Some("blockNotification".to_string()), ..RecordFilter::default() };
+    // Each page reads just one message past its cursor
+    let mut page = Page { limit: 100, cursor: None, max_scan_bytes: 1 };
+    let mut pages = Vec::new();
+    loop {
+        let (records, cursor) = archive_query_server::query_records(archive_dir.path(), &VolumeRange::All, &filter, &page).unwrap();
+        pages.push(slots(&records.iter().map(|record| serde_json::from_str(record).unwrap()).collect::<Vec<Value>>()));
+        match cursor {
+            Some(cursor) => page.cursor = Some(cursor),
+            None => break,
+        }
+    }
+    assert_eq!(pages, [vec![], vec![2], vec![], vec![4], vec![], vec![]]);
+}
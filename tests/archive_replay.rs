@@ -0,0 +1,110 @@
+mod support;
+
+use futures_util::{SinkExt, StreamExt};
+use pastel_solana_archival_data_integration_api::archive_replay_server::{self, ReplayOptions, ReplayPace, ReplayStart};
+use pastel_solana_archival_data_integration_api::data_archiver::Clock;
+use pastel_solana_archival_data_integration_api::historical_backfiller::block_notification_message;
+use pastel_solana_archival_data_integration_api::solana_connector::SolanaConnector;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use support::slot_notification_message;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Slots 1 to 6, with a block at slot 4 and logs at slot 5, as the connector archived them, acknowledgment included.
+fn write_archive(dir: &Path) {
+    let logs = json!({"jsonrpc": "2.0", "method": "logsNotification", "params": {"result": {"context": {"slot": 5}, "value": {"signature": "sig5", "err": null, "logs": []}}, "subscription": 2}});
+    let mut messages = vec![json!({"jsonrpc": "2.0", "result": 0, "id": 1}).to_string()];
+    for slot in 1..=6 {
+        messages.push(slot_notification_message(slot));
+        match slot {
+            4 => messages.push(block_notification_message(4, json!({"blockhash": "hash4", "parentSlot": 3, "transactions": []}))),
+            5 => messages.push(logs.to_string()),
+            _ => {}
+        }
+    }
+    support::write_archive(dir, messages.into_iter().map(|message| (0, message)));
+}
+
+async fn start_replay(archive_dir: &Path, options: ReplayOptions) -> String {
+    let (addr, server) = archive_replay_server::bind("127.0.0.1:0".parse().unwrap(), archive_dir.to_path_buf(), options).await.unwrap();
+    tokio::spawn(server);
+    format!("ws://{}", addr)
+}
+
+async fn next_json(socket: &mut (impl StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin)) -> Value {
+    serde_json::from_str(socket.next().await.unwrap().unwrap().to_text().unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn replays_the_archive_to_the_solana_connector() {
+    let archive_dir = tempfile::tempdir().unwrap();
+    write_archive(archive_dir.path());
+    let url = start_replay(archive_dir.path(), ReplayOptions { start: ReplayStart::Slot(3), pace: ReplayPace::AsFastAsPossible }).await;
+    let (tx, mut rx) = mpsc::channel(100);
+    let (faults, _fault_rx) = mpsc::unbounded_channel();
+    let connector = SolanaConnector::connect(&url, tx, faults, false).await.unwrap();
+
+    let mut subscription_ids = HashMap::new(); // Request id to subscription id, from the acknowledgments
+    let mut notifications = Vec::new();
+    while notifications.len() < 6 {
+        let message: Value = serde_json::from_str(&tokio::time::timeout(Duration::from_secs(10), rx.recv()).await.unwrap().unwrap()).unwrap();
+        match message["method"].as_str() {
+            Some(_) => notifications.push(message),
+            None => {
+                subscription_ids.insert(message["id"].as_u64().unwrap(), message["result"].clone());
+            }
+        }
+    }
+    connector.shutdown().await;
+
+    let replayed: Vec<(&str, u64)> = notifications.iter().map(|message| (message["method"].as_str().unwrap(), message["params"]["result"]["slot"].as_u64().or(message["params"]["result"]["context"]["slot"].as_u64()).unwrap())).collect();
+    assert_eq!(replayed, [("slotNotification", 3), ("slotNotification", 4), ("blockNotification", 4), ("slotNotification", 5), ("logsNotification", 5), ("slotNotification", 6)]);
+    assert_eq!(subscription_ids.len(), 6, "every subscription acknowledged");
+    assert_eq!(notifications[2]["params"]["subscription"], subscription_ids[&3], "blockSubscribe was request 3");
+    assert_eq!(notifications[4]["params"]["subscription"], subscription_ids[&4], "logsSubscribe was request 4");
+}
+
+#[tokio::test]
+async fn a_replay_from_a_time_starts_at_the_first_block_at_or_after_it() {
+    let archive_dir = tempfile::tempdir().unwrap();
+    let start = support::archive_clock().now();
+    support::write_archive(archive_dir.path(), (1..=4).flat_map(|slot| {
+        let block = json!({"blockhash": format!("hash{}", slot), "parentSlot": slot - 1, "blockTime": start.timestamp() + slot as i64 * 10, "transactions": []});
+        [(0, slot_notification_message(slot)), (0, block_notification_message(slot, block))]
+    }));
+    let url = start_replay(archive_dir.path(), ReplayOptions { start: ReplayStart::Time(start + chrono::Duration::seconds(25)), pace: ReplayPace::AsFastAsPossible }).await;
+    let (mut socket, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+    socket.send(Message::Text(json!({"jsonrpc": "2.0", "id": 1, "method": "slotSubscribe"}).to_string())).await.unwrap();
+
+    assert_eq!(next_json(&mut socket).await["result"], 0);
+    let slots = [next_json(&mut socket).await, next_json(&mut socket).await].map(|message| message["params"]["result"]["slot"].as_u64().unwrap());
+    assert_eq!(slots, [3, 4], "slots before the block at 12:00:30 are left out");
+}
+
+#[tokio::test]
+async fn paces_slots_and_honours_unsubscribe() {
+    let archive_dir = tempfile::tempdir().unwrap();
+    write_archive(archive_dir.path());
+    assert_eq!("10x".parse::<ReplayPace>(), Ok(ReplayPace::Accelerated(10.0)));
+    assert!("slow".parse::<ReplayPace>().is_err());
+    let url = start_replay(archive_dir.path(), ReplayOptions { start: ReplayStart::Beginning, pace: ReplayPace::Accelerated(4.0) }).await;
+    let (mut socket, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+    socket.send(Message::Text(json!({"jsonrpc": "2.0", "id": 1, "method": "slotSubscribe"}).to_string())).await.unwrap();
+
+    let ack = next_json(&mut socket).await;
+    assert_eq!(ack, json!({"jsonrpc": "2.0", "result": 0, "id": 1}));
+    let started = Instant::now();
+    let mut slots = Vec::new();
+    while slots.len() < 6 {
+        slots.push(next_json(&mut socket).await["params"]["result"]["slot"].as_u64().unwrap());
+    }
+    // Five slots after the first, at 400ms / 4 each
+    assert_eq!(slots, [1, 2, 3, 4, 5, 6]);
+    assert!(started.elapsed() >= Duration::from_millis(450), "replayed in {:?}", started.elapsed());
+
+    socket.send(Message::Text(json!({"jsonrpc": "2.0", "id": 2, "method": "slotUnsubscribe", "params": [0]}).to_string())).await.unwrap();
+    assert_eq!(next_json(&mut socket).await, json!({"jsonrpc": "2.0", "result": true, "id": 2}));
+}
@@ -0,0 +1,93 @@
+mod support;
+
+use base64::Engine;
+use pastel_solana_archival_data_integration_api::archive_rpc_server;
+use pastel_solana_archival_data_integration_api::historical_backfiller::block_notification_message;
+use serde_json::{json, Value};
+use std::path::Path;
+
+fn address(byte: u8) -> String {
+    bs58::encode([byte; 32]).into_string()
+}
+
+fn signature(byte: u8) -> String {
+    bs58::encode([byte; 64]).into_string()
+}
+
+/// A base64 v0 transaction with one signature and the given static account keys, and its metadata.
+fn transaction(signature_byte: u8, account_bytes: &[u8], loaded: &[u8]) -> Value {
+    let mut data = vec![1];
+    data.extend([signature_byte; 64]);
+    data.extend([0x80, 1, 0, 1, account_bytes.len() as u8]);
+    for byte in account_bytes {
+        data.extend([*byte; 32]);
+    }
+    data.extend([0u8; 34]);
+    let loaded: Vec<String> = loaded.iter().map(|byte| address(*byte)).collect();
+    json!({"transaction": [base64::engine::general_purpose::STANDARD.encode(data), "base64"], "meta": {"err": null, "fee": 5000, "loadedAddresses": {"writable": loaded, "readonly": []}}, "version": 0})
+}
+
+/// Blocks at slots 10, 11 and 13; slot 12 was skipped. Transaction 1 touches accounts 7 and 8,
+/// transaction 2 touches 8, and transaction 3 touches 7 and loads 9 from a lookup table.
+fn write_archive(dir: &Path) {
+    support::write_archive(dir, [(10, vec![transaction(1, &[7, 8], &[])]), (11, vec![transaction(2, &[8], &[])]), (13, vec![transaction(3, &[7], &[9])])].map(|(slot, transactions)| {
+        let block = json!({"blockhash": format!("hash{}", slot), "parentSlot": slot - 1, "blockTime": 1_693_569_600 + slot, "rewards": [], "transactions": transactions});
+        (0, block_notification_message(slot, block))
+    }));
+}
+
+async fn start_server() -> (tempfile::TempDir, String) {
+    let archive_dir = tempfile::tempdir().unwrap();
+    write_archive(archive_dir.path());
+    assert_eq!(archive_rpc_server::index_archive(archive_dir.path()).unwrap(), 1);
+    let (addr, server) = archive_rpc_server::bind("127.0.0.1:0".parse().unwrap(), archive_dir.path().to_path_buf()).unwrap();
+    tokio::spawn(server);
+    (archive_dir, format!("http://{}", addr))
+}
+
+async fn rpc(url: &str, method: &str, params: Value) -> Value {
+    let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+    reqwest::Client::new().post(url).json(&request).send().await.unwrap().json().await.unwrap()
+}
+
+#[tokio::test]
+async fn serves_blocks_and_slots() {
+    let (_archive_dir, url) = start_server().await;
+
+    assert_eq!(rpc(&url, "getSlot", json!([])).await["result"], 13);
+    assert_eq!(rpc(&url, "getBlocks", json!([10])).await["result"], json!([10, 11, 13]));
+    assert_eq!(rpc(&url, "getBlocks", json!([11, 12])).await["result"], json!([11]));
+    assert_eq!(rpc(&url, "getBlockTime", json!([13])).await["result"], 1_693_569_613);
+
+    let block = rpc(&url, "getBlock", json!([11, {"encoding": "base58", "rewards": false}])).await["result"].clone();
+    assert_eq!((block["blockhash"].as_str(), block["transactions"][0]["transaction"][1].as_str(), block.get("rewards")), (Some("hash11"), Some("base58"), None));
+    let block = rpc(&url, "getBlock", json!([11, {"transactionDetails": "signatures"}])).await["result"].clone();
+    assert_eq!((block["signatures"].clone(), block.get("transactions")), (json!([signature(2)]), None));
+    assert_eq!(rpc(&url, "getBlock", json!([11, {"encoding": "json"}])).await["error"]["code"], -32602);
+    assert_eq!(rpc(&url, "getBlock", json!([12])).await["error"]["code"], -32004);
+    assert_eq!(rpc(&url, "getBalance", json!([address(7)])).await["error"]["code"], -32601);
+
+    let batch = json!([{"jsonrpc": "2.0", "id": 1, "method": "getSlot"}, {"jsonrpc": "2.0", "id": 2, "method": "getBlockTime", "params": [10]}]);
+    let responses: Value = reqwest::Client::new().post(&url).json(&batch).send().await.unwrap().json().await.unwrap();
+    assert_eq!(responses, json!([{"jsonrpc": "2.0", "id": 1, "result": 13}, {"jsonrpc": "2.0", "id": 2, "result": 1_693_569_610}]));
+}
+
+#[tokio::test]
+async fn finds_transactions_and_signatures_for_addresses() {
+    let (_archive_dir, url) = start_server().await;
+    let signatures = |result: &Value| result.as_array().unwrap().iter().map(|entry| entry["signature"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+
+    let transaction = rpc(&url, "getTransaction", json!([signature(2), {"encoding": "base64"}])).await["result"].clone();
+    assert_eq!((transaction["slot"].as_u64(), transaction["blockTime"].as_u64(), transaction["meta"]["fee"].as_u64()), (Some(11), Some(1_693_569_611), Some(5000)));
+    assert_eq!(rpc(&url, "getTransaction", json!([signature(4)])).await["result"], Value::Null);
+
+    let newest_first = rpc(&url, "getSignaturesForAddress", json!([address(7)])).await["result"].clone();
+    assert_eq!(signatures(&newest_first), [signature(3), signature(1)]);
+    assert_eq!((newest_first[0]["slot"].as_u64(), newest_first[0]["confirmationStatus"].as_str()), (Some(13), Some("finalized")));
+    assert_eq!(signatures(&rpc(&url, "getSignaturesForAddress", json!([address(7), {"limit": 1}])).await["result"]), [signature(3)]);
+    assert_eq!(signatures(&rpc(&url, "getSignaturesForAddress", json!([address(7), {"before": signature(3)}])).await["result"]), [signature(1)]);
+    assert_eq!(signatures(&rpc(&url, "getSignaturesForAddress", json!([address(8), {"until": signature(1)}])).await["result"]), [signature(2)]);
+    assert_eq!(signatures(&rpc(&url, "getSignaturesForAddress", json!([address(9)])).await["result"]), [signature(3)]);
+    assert_eq!(rpc(&url, "getSignaturesForAddress", json!([address(7), {"before": signature(4)}])).await["result"], json!([]), "unknown before signature");
+    assert_eq!(rpc(&url, "getSignaturesForAddress", json!([address(7), {"until": signature(4)}])).await["result"], json!([]), "unknown until signature");
+}
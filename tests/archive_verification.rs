@@ -0,0 +1,85 @@
+mod support;
+
+use base64::Engine;
+use pastel_solana_archival_data_integration_api::archive_verifier;
+use pastel_solana_archival_data_integration_api::data_archiver::manifest_path;
+use pastel_solana_archival_data_integration_api::historical_backfiller::BlockArchiver;
+use pastel_solana_archival_data_integration_api::old_faithful_converter::OLD_FAITHFUL_SOURCE;
+use pastel_solana_archival_data_integration_api::volume_catalog::VolumeCatalog;
+use serde_json::{json, Value};
+use support::finalized_volumes;
+use support::car::{entry_hash, sample_epoch, sample_epoch_files, SampleTransaction, SAMPLE_FIRST_SLOT};
+
+/// A `blockSubscribe` block as the live connector archives it, with base64 transactions.
+fn live_block(slot: u64, blockhash: [u8; 32], transactions: &[&SampleTransaction]) -> Value {
+    json!({
+        "blockHeight": slot,
+        "blockTime": 1_600_000_000 + (slot - SAMPLE_FIRST_SLOT) as i64,
+        "blockhash": bs58::encode(blockhash).into_string(),
+        "parentSlot": slot - 1,
+        "transactions": transactions.iter().map(|transaction| json!({"transaction": [base64::engine::general_purpose::STANDARD.encode(transaction.serialized()), "base64"], "meta": null})).collect::<Vec<_>>(),
+    })
+}
+
+#[test]
+fn reports_missing_extra_and_mismatched_slots() {
+    let sample = sample_epoch();
+    let archive_dir = tempfile::tempdir().unwrap();
+    let mut live = BlockArchiver::new(archive_dir.path(), 60, "volume_state.json", json!({"source": "live"}));
+    let first_block: Vec<_> = sample[0].entries.iter().flatten().collect();
+    live.archive_block(SAMPLE_FIRST_SLOT, live_block(SAMPLE_FIRST_SLOT, entry_hash(SAMPLE_FIRST_SLOT, 1), &first_block)).unwrap();
+    live.archive_block(SAMPLE_FIRST_SLOT + 2, live_block(SAMPLE_FIRST_SLOT + 2, [7; 32], &[])).unwrap();
+    let forged = SampleTransaction { signature_bytes: vec![5], message: b"forged".to_vec(), metadata: Vec::new(), frames: 1 };
+    live.archive_block(SAMPLE_FIRST_SLOT + 3, live_block(SAMPLE_FIRST_SLOT + 3, [8; 32], &[&forged])).unwrap();
+    live.finish().unwrap();
+    // Converted volumes are Old Faithful's own data, so they must not hide the missing slot
+    let mut converted = BlockArchiver::new(archive_dir.path(), 60, "old_faithful_volume_state.json", json!({"source": OLD_FAITHFUL_SOURCE}));
+    let mut converted_block = live_block(SAMPLE_FIRST_SLOT + 1, entry_hash(SAMPLE_FIRST_SLOT + 1, 1), &[]);
+    converted_block["blockTime"] = json!(1_600_010_000);
+    converted.archive_block(SAMPLE_FIRST_SLOT + 1, converted_block).unwrap();
+    converted.finish().unwrap();
+
+    let report = archive_verifier::verify_epoch(archive_dir.path(), &sample_epoch_files(), None).unwrap();
+
+    assert_eq!((report["first_slot"].as_u64(), report["last_slot"].as_u64()), (Some(SAMPLE_FIRST_SLOT), Some(SAMPLE_FIRST_SLOT + 3)));
+    assert_eq!(report["archived_blocks"], 3);
+    assert_eq!(report["old_faithful_blocks"], 3);
+    assert_eq!(report["matched_blocks"], 1);
+    assert_eq!(report["missing_slots"], json!([SAMPLE_FIRST_SLOT + 1]));
+    assert_eq!(report["extra_slots"], json!([SAMPLE_FIRST_SLOT + 2]));
+    let mismatched = report["mismatched_slots"].as_array().unwrap();
+    assert_eq!(mismatched.len(), 1);
+    assert_eq!(mismatched[0]["slot"], SAMPLE_FIRST_SLOT + 3);
+    assert_eq!(mismatched[0]["blockhash"]["old_faithful"], bs58::encode(entry_hash(SAMPLE_FIRST_SLOT + 3, 0)).into_string());
+    assert!(mismatched[0].get("transaction_count").is_none(), "both sides have one transaction");
+    assert_eq!(mismatched[0]["signatures"]["missing"], json!(sample[2].entries[0][0].signatures()));
+    assert_eq!(mismatched[0]["signatures"]["extra"], json!(forged.signatures()));
+}
+
+#[test]
+fn reads_volumes_that_retention_moved_away() {
+    let sample = sample_epoch();
+    let archive_dir = tempfile::tempdir().unwrap();
+    let cold_dir = tempfile::tempdir().unwrap();
+    let mut live = BlockArchiver::new(archive_dir.path(), 60, "volume_state.json", json!({"source": "live"}));
+    let first_block: Vec<_> = sample[0].entries.iter().flatten().collect();
+    live.archive_block(SAMPLE_FIRST_SLOT, live_block(SAMPLE_FIRST_SLOT, entry_hash(SAMPLE_FIRST_SLOT, 1), &first_block)).unwrap();
+    live.finish().unwrap();
+    let volume = finalized_volumes(archive_dir.path()).remove(0);
+    let moved = cold_dir.path().join(volume.file_name().unwrap());
+    std::fs::rename(&volume, &moved).unwrap();
+    std::fs::rename(manifest_path(&volume), manifest_path(&moved)).unwrap();
+    VolumeCatalog::open_in(archive_dir.path()).unwrap().record_deletion(volume.file_name().unwrap().to_str().unwrap(), Some(&moved)).unwrap();
+
+    let report = archive_verifier::verify_epoch(archive_dir.path(), &sample_epoch_files(), None).unwrap();
+
+    assert_eq!((report["archived_blocks"].as_u64(), report["matched_blocks"].as_u64()), (Some(1), Some(1)));
+    assert_eq!(report["missing_slots"], json!([]));
+}
+
+#[test]
+fn refuses_an_epoch_with_nothing_archived() {
+    let archive_dir = tempfile::tempdir().unwrap();
+    let err = archive_verifier::verify_epoch(archive_dir.path(), &sample_epoch_files(), None).unwrap_err();
+    assert!(err.to_string().contains("No archived blocks in epoch 3"), "{}", err);
+}
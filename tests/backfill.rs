@@ -0,0 +1,106 @@
+mod support;
+
+use chrono::{TimeZone, Utc};
+use pastel_solana_archival_data_integration_api::historical_backfiller::{self, BackfillCheckpoint, BackfillRange};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use support::{finalized_volumes, read_volume_messages, test_block, MockJsonRpcServer};
+
+// Slot 12 is skipped; block times advance 30 seconds per slot, so 1-minute buckets hold two blocks each.
+fn mock_rpc(method: &str, params: &Value) -> Value {
+    match method {
+        "getBlocks" => {
+            let (start, end) = (params[0].as_u64().unwrap(), params[1].as_u64().unwrap());
+            json!((start..=end).filter(|slot| *slot != 12).collect::<Vec<u64>>())
+        }
+        "getBlock" => test_block(params[0].as_u64().unwrap()),
+        _ => json!({"error": {"code": -32601, "message": "Method not found"}}),
+    }
+}
+
+fn archived_slots(archive_dir: &Path) -> Vec<u64> {
+    finalized_volumes(archive_dir)
+        .iter()
+        .flat_map(|volume| read_volume_messages(volume))
+        .map(|message| {
+            assert_eq!(message["method"], "blockNotification");
+            message["params"]["result"]["value"]["slot"].as_u64().unwrap()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn backfill_archives_range_in_slot_order_and_resumes_from_checkpoint() {
+    let server = MockJsonRpcServer::start(mock_rpc).await;
+    let archive_dir = tempfile::tempdir().unwrap();
+    let checkpoint_path = archive_dir.path().join("checkpoint.json");
+
+    historical_backfiller::run_backfill(&server.url, BackfillRange { start_slot: 10, end_slot: 15 }, archive_dir.path(), 1, 4, &checkpoint_path).await.unwrap();
+
+    assert_eq!(archived_slots(archive_dir.path()), [10, 11, 13, 14, 15]);
+    assert!(finalized_volumes(archive_dir.path()).len() > 1, "blocks are bucketed by block time");
+    assert_eq!(BackfillCheckpoint::load(&checkpoint_path).unwrap().unwrap().last_completed_slot, Some(15));
+
+    let block_requests = server.request_count("getBlock");
+    historical_backfiller::run_backfill(&server.url, BackfillRange { start_slot: 10, end_slot: 15 }, archive_dir.path(), 1, 4, &checkpoint_path).await.unwrap();
+    assert_eq!(server.request_count("getBlock"), block_requests, "a completed checkpoint is not fetched again");
+}
+
+#[tokio::test]
+async fn interrupted_backfill_resumes_without_duplicate_or_missing_slots() {
+    // Two getBlocks chunks; the first request for the second one fails, stopping the run with slots 14 and
+    // 15 in a volume that was never finalized.
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let server_interrupted = interrupted.clone();
+    let server = MockJsonRpcServer::start(move |method, params| match method {
+        "getBlocks" => {
+            let (start, end) = (params[0].as_u64().unwrap(), params[1].as_u64().unwrap());
+            if start >= 1_000 && !server_interrupted.swap(true, Ordering::SeqCst) {
+                return json!({"error": {"code": -32000, "message": "Connection reset"}});
+            }
+            json!([10, 11, 13, 14, 15, 1_010, 1_011].iter().filter(|slot| (start..=end).contains(*slot)).collect::<Vec<_>>())
+        }
+        method => mock_rpc(method, params),
+    })
+    .await;
+    let archive_dir = tempfile::tempdir().unwrap();
+    let checkpoint_path = archive_dir.path().join("checkpoint.json");
+    let range = || BackfillRange { start_slot: 10, end_slot: 1_015 };
+
+    assert!(historical_backfiller::run_backfill(&server.url, range(), archive_dir.path(), 1, 4, &checkpoint_path).await.is_err());
+    assert!(interrupted.load(Ordering::SeqCst));
+    assert_eq!(BackfillCheckpoint::load(&checkpoint_path).unwrap().unwrap().last_completed_slot, Some(13), "only finalized volumes are checkpointed");
+    assert_eq!(archived_slots(archive_dir.path()), [10, 11, 13]);
+
+    historical_backfiller::run_backfill(&server.url, range(), archive_dir.path(), 1, 4, &checkpoint_path).await.unwrap();
+    assert_eq!(archived_slots(archive_dir.path()), [10, 11, 13, 14, 15, 1_010, 1_011]);
+    assert_eq!(BackfillCheckpoint::load(&checkpoint_path).unwrap().unwrap().last_completed_slot, Some(1_015));
+    let refetched: Vec<u64> = server.requests().iter().filter(|request| request["method"] == "getBlock").filter_map(|request| request["params"][0].as_u64()).filter(|slot| *slot < 14).collect();
+    assert_eq!(refetched.len(), 3, "blocks in finalized volumes are fetched once: {:?}", refetched);
+}
+
+#[tokio::test]
+async fn time_range_resolves_to_the_slots_it_covers() {
+    // Slots 10 to 2000 are available; 12 and 30 to 35 were skipped and have no block time.
+    let server = MockJsonRpcServer::start(|method, params| match method {
+        "getFirstAvailableBlock" => json!(10),
+        "getSlot" => json!(2_000),
+        "getBlockTime" => match params[0].as_u64().unwrap() {
+            12 | 30..=35 => Value::Null,
+            slot => test_block(slot)["blockTime"].clone(),
+        },
+        _ => json!({"error": {"code": -32601, "message": "Method not found"}}),
+    })
+    .await;
+    let slot_time = |slot: i64| Utc.timestamp_opt(1_700_000_000 + slot * 30, 0).unwrap();
+
+    assert_eq!(historical_backfiller::find_first_slot_at_or_after(&server.url, slot_time(500)).await.unwrap(), 500);
+    let after_skipped = historical_backfiller::find_first_slot_at_or_after(&server.url, slot_time(36) - chrono::Duration::seconds(10)).await.unwrap();
+    assert!((30..=36).contains(&after_skipped), "no block between {} and slot 36", after_skipped);
+    assert_eq!(historical_backfiller::find_first_slot_at_or_after(&server.url, slot_time(0)).await.unwrap(), 10, "clamped to the first available block");
+
+    let range = historical_backfiller::resolve_time_range(&server.url, slot_time(20) - chrono::Duration::seconds(10), slot_time(40)).await.unwrap();
+    assert_eq!((range.start_slot, range.end_slot), (20, 39));
+}
@@ -0,0 +1,104 @@
+mod support;
+
+use pastel_solana_archival_data_integration_api::cascade_uploader::{CascadeConfig, CascadeUploader};
+use pastel_solana_archival_data_integration_api::data_archiver::EncoderManager;
+use pastel_solana_archival_data_integration_api::volume_catalog::VolumeCatalog;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use support::{finalized_volumes, slot_notification_message, write_archive, MockCascadeServer};
+
+fn config(server: &MockCascadeServer) -> CascadeConfig {
+    CascadeConfig {
+        endpoint: server.url.clone(),
+        app_pastelid: "jXtestpastelid".to_string(),
+        passphrase: "passphrase".to_string(),
+        poll_interval: Duration::from_millis(10),
+        retry_backoff: Duration::ZERO,
+        ..CascadeConfig::default()
+    }
+}
+
+/// Writes one finalized volume per minute, each holding a single slot notification.
+fn write_volumes(dir: &Path, slots: &[u64]) {
+    write_archive(dir, slots.iter().enumerate().map(|(minute, slot)| (minute as i64, slot_notification_message(*slot))));
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+#[tokio::test]
+async fn uploads_finalized_volumes_and_records_their_tickets() {
+    let server = MockCascadeServer::start(2, 0).await;
+    let archive_dir = tempfile::tempdir().unwrap();
+    write_volumes(archive_dir.path(), &[10, 11]);
+    let mut uploader = CascadeUploader::new(config(&server), archive_dir.path(), VolumeCatalog::open_in(archive_dir.path()).unwrap());
+
+    assert_eq!(uploader.upload_pending().await.unwrap(), 2);
+
+    let volumes = finalized_volumes(archive_dir.path());
+    let uploads = server.uploads();
+    assert_eq!(uploads.len(), 2);
+    for (volume, (file_name, body)) in volumes.iter().zip(&uploads) {
+        assert_eq!(file_name, &volume.file_name().unwrap().to_string_lossy());
+        assert!(contains(body, &std::fs::read(volume).unwrap()), "the volume is sent unchanged");
+    }
+    assert_eq!(server.registrations()[0].1["app_pastelid"], "jXtestpastelid");
+    let catalog = VolumeCatalog::open_in(archive_dir.path()).unwrap();
+    let entry = catalog.volume(&volumes[1].file_name().unwrap().to_string_lossy()).unwrap().unwrap();
+    assert_eq!(entry.upload_state, "confirmed");
+    assert_eq!(entry.cascade_txid.as_deref(), Some("txid-task-2"));
+    assert_eq!(entry.cascade_file_id.as_deref(), Some("file-2"));
+    assert_eq!(entry.first_slot, Some(11));
+
+    let mut restarted = CascadeUploader::new(config(&server), archive_dir.path(), catalog);
+    assert_eq!(restarted.upload_pending().await.unwrap(), 0);
+    assert_eq!(server.uploads().len(), 2, "confirmed volumes are never uploaded again");
+}
+
+#[tokio::test]
+async fn retries_a_rejected_registration_without_storing_the_file_again() {
+    let server = MockCascadeServer::start(0, 1).await;
+    let archive_dir = tempfile::tempdir().unwrap();
+    write_volumes(archive_dir.path(), &[10]);
+    let volume = finalized_volumes(archive_dir.path()).remove(0);
+    let name = volume.file_name().unwrap().to_string_lossy().to_string();
+    let mut uploader = CascadeUploader::new(config(&server), archive_dir.path(), VolumeCatalog::open_in(archive_dir.path()).unwrap());
+    let catalog = VolumeCatalog::open_in(archive_dir.path()).unwrap();
+
+    assert_eq!(uploader.upload_pending().await.unwrap(), 0);
+    let failed = catalog.volume(&name).unwrap().unwrap();
+    assert_eq!((failed.upload_state.as_str(), failed.upload_attempts), ("failed", 1));
+    assert!(failed.upload_error.unwrap().contains("Task Rejected"));
+
+    assert_eq!(uploader.upload_pending().await.unwrap(), 1);
+    assert_eq!(catalog.volume(&name).unwrap().unwrap().cascade_txid.as_deref(), Some("txid-task-2"));
+    assert_eq!(server.uploads().len(), 1);
+    assert_eq!(server.registrations().len(), 2);
+}
+
+#[tokio::test]
+async fn uploads_volumes_as_the_encoder_finalizes_them() {
+    let server = MockCascadeServer::start(0, 0).await;
+    let archive_dir = tempfile::tempdir().unwrap();
+    let catalog = VolumeCatalog::open_in(archive_dir.path()).unwrap();
+    let uploader = CascadeUploader::new(CascadeConfig { rescan_interval: Duration::from_secs(3600), ..config(&server) }, archive_dir.path(), VolumeCatalog::open_in(archive_dir.path()).unwrap());
+    let (finalized_tx, finalized_rx) = tokio::sync::mpsc::unbounded_channel::<PathBuf>();
+    let uploader_task = tokio::spawn(uploader.run(finalized_rx));
+    tokio::time::sleep(Duration::from_millis(50)).await; // The first scan finds nothing
+
+    let mut manager = EncoderManager::new(1, archive_dir.path()).unwrap();
+    manager.set_finalized_volume_sender(finalized_tx);
+    manager.process_message(slot_notification_message(10)).unwrap();
+    manager.finish().unwrap();
+
+    let volume = finalized_volumes(archive_dir.path()).remove(0).file_name().unwrap().to_string_lossy().to_string();
+    for _ in 0..200 {
+        if catalog.volume(&volume).unwrap().unwrap().upload_state == "confirmed" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    uploader_task.abort();
+    assert_eq!(catalog.volume(&volume).unwrap().unwrap().upload_state, "confirmed");
+}
@@ -0,0 +1,57 @@
+mod support;
+
+use pastel_solana_archival_data_integration_api::data_archiver::manifest_path;
+use pastel_solana_archival_data_integration_api::disk_monitor::{DegradationLevel, DiskMonitor, DiskThresholds};
+use pastel_solana_archival_data_integration_api::fault_supervisor::{FaultSource, Severity};
+use serde_json::Value;
+use support::{archive_clock, archive_writer, finalized_volumes, read_volume_messages};
+use tokio::sync::{mpsc, watch};
+
+fn notification(method: &str, slot: u64) -> String {
+    format!(r#"{{"method":"{}","params":{{"result":{{"slot":{}}}}}}}"#, method, slot)
+}
+
+#[test]
+fn drops_low_priority_notifications_while_degraded() {
+    let archive_dir = tempfile::tempdir().unwrap();
+    let mut manager = archive_writer(archive_dir.path(), 10, &archive_clock());
+    let (level, degradation) = watch::channel(DegradationLevel::Normal);
+    manager.set_degradation(degradation);
+
+    manager.process_message(notification("voteNotification", 1)).unwrap();
+    level.send_replace(DegradationLevel::Conserving);
+    manager.process_message(notification("voteNotification", 2)).unwrap();
+    manager.process_message(notification("slotNotification", 2)).unwrap();
+    level.send_replace(DegradationLevel::Critical);
+    manager.process_message(notification("slotNotification", 3)).unwrap();
+    manager.process_message(notification("blockNotification", 3)).unwrap();
+    manager.finish().unwrap();
+
+    let volumes = finalized_volumes(archive_dir.path());
+    let methods: Vec<Value> = read_volume_messages(&volumes[0]).iter().map(|message| message["method"].clone()).collect();
+    assert_eq!(methods, ["voteNotification", "slotNotification", "blockNotification"]);
+    let manifest: Value = serde_json::from_slice(&std::fs::read(manifest_path(&volumes[0])).unwrap()).unwrap();
+    assert_eq!(manifest["dropped_messages"], 2);
+}
+
+#[test]
+fn disk_monitor_publishes_levels_and_asks_for_space() {
+    let archive_dir = tempfile::tempdir().unwrap();
+    let mut monitor = DiskMonitor::new(archive_dir.path(), DiskThresholds { conserving_below: u64::MAX, critical_below: u64::MAX, exhausted_below: u64::MAX });
+    let (free_space_tx, mut free_space_rx) = mpsc::unbounded_channel();
+    monitor.set_free_space_requests(free_space_tx);
+    let (fault_tx, mut fault_rx) = mpsc::unbounded_channel();
+    monitor.set_fault_sender(fault_tx);
+    let degradation = monitor.subscribe();
+
+    assert_eq!(monitor.check().unwrap(), DegradationLevel::Exhausted);
+    assert_eq!(*degradation.borrow(), DegradationLevel::Exhausted);
+    assert!(free_space_rx.try_recv().unwrap() > 0);
+    let fault = fault_rx.try_recv().unwrap();
+    assert_eq!((fault.source, fault.severity), (FaultSource::DiskMonitor, Severity::Fatal));
+    monitor.check().unwrap();
+    assert!(fault_rx.try_recv().is_err(), "only the transition is reported");
+
+    let monitor = DiskMonitor::new(archive_dir.path(), DiskThresholds { conserving_below: 0, critical_below: 0, exhausted_below: 0 });
+    assert_eq!(monitor.check().unwrap(), DegradationLevel::Normal);
+}
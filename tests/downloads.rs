@@ -0,0 +1,98 @@
+mod support;
+
+use pastel_solana_archival_data_integration_api::resumable_downloader::{self, DownloadOptions};
+use sha2::{Digest, Sha256};
+use support::{read_http_request, write_http_response, MockFileServer};
+use tokio::net::TcpListener;
+
+fn test_contents(length: usize) -> Vec<u8> {
+    (0..length).map(|index| (index * 31 % 251) as u8).collect()
+}
+
+fn single_stream() -> DownloadOptions {
+    DownloadOptions { parallel_chunks: 1, ..DownloadOptions::default() }
+}
+
+#[tokio::test]
+async fn interrupted_download_resumes_with_a_range_request() {
+    let server = MockFileServer::start(Some(30_000)).await;
+    let contents = test_contents(100_000);
+    server.add_file("/500/epoch-500.car", contents.clone());
+    let dir = tempfile::tempdir().unwrap();
+    let destination = dir.path().join("epoch-500.car");
+    let url = format!("{}/500/epoch-500.car", server.url);
+    let options = DownloadOptions { expected_sha256: Some(hex::encode(Sha256::digest(&contents))), ..single_stream() };
+
+    assert!(resumable_downloader::download_file(&url, &destination, &options).await.is_err());
+    assert!(!destination.exists(), "an incomplete download is never renamed into place");
+    assert_eq!(std::fs::metadata(dir.path().join("epoch-500.car.part")).unwrap().len(), 30_000);
+
+    assert_eq!(resumable_downloader::download_file(&url, &destination, &options).await.unwrap(), 100_000);
+    assert_eq!(std::fs::read(&destination).unwrap(), contents);
+    assert!(!dir.path().join("epoch-500.car.part").exists());
+    let ranges: Vec<Option<String>> = server.requests().into_iter().filter(|(method, _, _)| method == "GET").map(|(_, _, range)| range).collect();
+    assert_eq!(ranges, [None, Some("bytes=30000-".to_string())]);
+}
+
+#[tokio::test]
+async fn complete_part_file_is_verified_and_renamed_without_downloading() {
+    let server = MockFileServer::start(None).await;
+    let contents = test_contents(10_000);
+    server.add_file("/file", contents.clone());
+    let dir = tempfile::tempdir().unwrap();
+    let destination = dir.path().join("file");
+    std::fs::write(dir.path().join("file.part"), &contents).unwrap(); // Interrupted between the last byte and the rename
+    let options = DownloadOptions { expected_sha256: Some(hex::encode(Sha256::digest(&contents))), ..single_stream() };
+
+    assert_eq!(resumable_downloader::download_file(&format!("{}/file", server.url), &destination, &options).await.unwrap(), 10_000);
+    assert_eq!(std::fs::read(&destination).unwrap(), contents);
+    assert!(!dir.path().join("file.part").exists());
+    assert_eq!(server.requests().iter().filter(|(method, _, _)| method == "GET").count(), 0);
+}
+
+#[tokio::test]
+async fn large_files_are_fetched_in_parallel_chunks() {
+    let server = MockFileServer::start(None).await;
+    let contents = test_contents(1_000_000);
+    server.add_file("/index", contents.clone());
+    let dir = tempfile::tempdir().unwrap();
+    let destination = dir.path().join("index");
+    let options = DownloadOptions { parallel_chunks: 4, chunk_size: 64 * 1024, expected_sha256: None };
+
+    resumable_downloader::download_file(&format!("{}/index", server.url), &destination, &options).await.unwrap();
+
+    assert_eq!(std::fs::read(&destination).unwrap(), contents);
+    let range_requests = server.requests().iter().filter(|(method, _, range)| method == "GET" && range.is_some()).count();
+    assert_eq!(range_requests, 16);
+}
+
+#[tokio::test]
+async fn checksum_mismatch_is_rejected() {
+    let server = MockFileServer::start(None).await;
+    server.add_file("/file", test_contents(1_000));
+    let dir = tempfile::tempdir().unwrap();
+    let destination = dir.path().join("file");
+    let options = DownloadOptions { expected_sha256: Some("00".repeat(32)), ..single_stream() };
+
+    let error = resumable_downloader::download_file(&format!("{}/file", server.url), &destination, &options).await.unwrap_err();
+    assert!(error.to_string().contains("Checksum mismatch"));
+    assert!(!destination.exists());
+}
+
+#[tokio::test]
+async fn published_checksum_is_optional() {
+    let server = MockFileServer::start(None).await;
+    server.add_file("/file.sha256", b"ABCDEF  file\n".to_vec());
+    assert_eq!(resumable_downloader::fetch_published_sha256(&format!("{}/file", server.url)).await.unwrap(), Some("abcdef".to_string()));
+    assert_eq!(resumable_downloader::fetch_published_sha256(&format!("{}/other", server.url)).await.unwrap(), None);
+
+    // Object stores such as S3 answer 403 for a missing object when listing is not allowed
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/file", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        read_http_request(&mut stream).await;
+        write_http_response(&mut stream, "403 Forbidden", "application/xml", b"<Error><Code>AccessDenied</Code></Error>").await;
+    });
+    assert_eq!(resumable_downloader::fetch_published_sha256(&url).await.unwrap(), None);
+}
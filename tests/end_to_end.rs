@@ -0,0 +1,118 @@
+mod support;
+
+use pastel_solana_archival_data_integration_api::data_archiver::MessageDispatcher;
+use pastel_solana_archival_data_integration_api::fault_supervisor::{FaultSource, Severity};
+use pastel_solana_archival_data_integration_api::solana_connector::SolanaConnector;
+use serde_json::Value;
+use std::time::Duration;
+use support::{block_notification, finalized_volumes, logs_notification, read_volume_messages, slot_notification, MockPubSubServer, PubSubStep};
+
+const SUBSCRIPTION_COUNT: usize = 6;
+
+async fn receive(dispatcher: &mut MessageDispatcher) -> String {
+    tokio::time::timeout(Duration::from_secs(5), dispatcher.receive_message()).await.expect("timed out waiting for a message").expect("channel closed")
+}
+
+fn methods(messages: &[Value]) -> Vec<&str> {
+    messages.iter().map(|message| message["method"].as_str().unwrap_or("ack")).collect()
+}
+
+#[tokio::test]
+async fn connector_archives_scripted_notifications() {
+    let server = MockPubSubServer::start(vec![vec![
+        PubSubStep::AwaitSubscriptions(SUBSCRIPTION_COUNT),
+        slot_notification(101),
+        block_notification(101),
+        logs_notification(101, "5h6xBEauJ3PK6SWCZ1PGjBvj8vDdWG3KpwATGy1ARAXFSDwt8GFXM7W5Ncn16wmqokgpiKRLuS83KUxyZyv2sUYv"),
+    ]])
+    .await;
+    let archive_dir = tempfile::tempdir().unwrap();
+    let (mut dispatcher, _) = MessageDispatcher::new(100, 1, archive_dir.path());
+    SolanaConnector::connect(&server.url, dispatcher.clone_tx(), dispatcher.fault_sender(), true).await.unwrap();
+    for _ in 0..3 {
+        let message = receive(&mut dispatcher).await;
+        dispatcher.process_message(message).unwrap();
+    }
+    dispatcher.finish_and_create_new().unwrap();
+
+    let volumes = finalized_volumes(archive_dir.path());
+    assert_eq!(volumes.len(), 1);
+    let messages = read_volume_messages(&volumes[0]);
+    assert_eq!(methods(&messages), ["slotNotification", "blockNotification", "logsNotification"]);
+    assert_eq!(messages[1]["params"]["result"]["value"]["block"]["blockhash"], "blockhash101");
+    assert!(messages[0]["params"]["subscription"].as_u64().unwrap() > 100, "notifications carry the acknowledged subscription id");
+}
+
+#[tokio::test]
+async fn connector_sends_every_subscription() {
+    let server = MockPubSubServer::start(vec![]).await;
+    let archive_dir = tempfile::tempdir().unwrap();
+    let (dispatcher, _) = MessageDispatcher::new(100, 1, archive_dir.path());
+    SolanaConnector::connect(&server.url, dispatcher.clone_tx(), dispatcher.fault_sender(), true).await.unwrap();
+
+    let requested: Vec<String> = server.requests().iter().map(|request| request["method"].as_str().unwrap().to_string()).collect();
+    assert_eq!(requested, ["slotSubscribe", "slotsUpdatesSubscribe", "blockSubscribe", "logsSubscribe", "programSubscribe", "voteSubscribe"]);
+}
+
+#[tokio::test]
+async fn acknowledgments_are_archived_when_not_verbose() {
+    let server = MockPubSubServer::start(vec![vec![PubSubStep::AwaitSubscriptions(SUBSCRIPTION_COUNT), slot_notification(7)]]).await;
+    let archive_dir = tempfile::tempdir().unwrap();
+    let (mut dispatcher, _) = MessageDispatcher::new(100, 1, archive_dir.path());
+    SolanaConnector::connect(&server.url, dispatcher.clone_tx(), dispatcher.fault_sender(), false).await.unwrap();
+    for _ in 0..SUBSCRIPTION_COUNT + 1 {
+        let message = receive(&mut dispatcher).await;
+        dispatcher.process_message(message).unwrap();
+    }
+    dispatcher.finish_and_create_new().unwrap();
+
+    let messages = read_volume_messages(&finalized_volumes(archive_dir.path())[0]);
+    assert_eq!(methods(&messages), ["ack", "ack", "ack", "ack", "ack", "ack", "slotNotification"]);
+}
+
+#[tokio::test]
+async fn reconnecting_after_a_dropped_connection_resumes_archiving() {
+    let server = MockPubSubServer::start(vec![
+        vec![PubSubStep::AwaitSubscriptions(SUBSCRIPTION_COUNT), slot_notification(1), PubSubStep::DropConnection],
+        vec![PubSubStep::AwaitSubscriptions(SUBSCRIPTION_COUNT), slot_notification(2)],
+    ])
+    .await;
+    let archive_dir = tempfile::tempdir().unwrap();
+    let (mut dispatcher, mut faults) = MessageDispatcher::new(100, 1, archive_dir.path());
+    SolanaConnector::connect(&server.url, dispatcher.clone_tx(), dispatcher.fault_sender(), true).await.unwrap();
+    let first = receive(&mut dispatcher).await;
+    dispatcher.process_message(first).unwrap();
+    let fault = tokio::time::timeout(Duration::from_secs(5), faults.recv()).await.unwrap().unwrap();
+    assert_eq!((fault.source, fault.severity), (FaultSource::Connector, Severity::Recoverable));
+    SolanaConnector::connect(&server.url, dispatcher.clone_tx(), dispatcher.fault_sender(), true).await.unwrap();
+    let second = receive(&mut dispatcher).await;
+    dispatcher.process_message(second).unwrap();
+    dispatcher.finish_and_create_new().unwrap();
+
+    assert_eq!(server.connection_count(), 2);
+    let messages = read_volume_messages(&finalized_volumes(archive_dir.path())[0]);
+    let slots: Vec<u64> = messages.iter().map(|message| message["params"]["result"]["slot"].as_u64().unwrap()).collect();
+    assert_eq!(slots, [1, 2]);
+}
+
+#[tokio::test]
+async fn shutdown_unsubscribes_and_drains_queued_messages_into_the_volume() {
+    let server = MockPubSubServer::start(vec![vec![PubSubStep::AwaitSubscriptions(SUBSCRIPTION_COUNT), slot_notification(1), slot_notification(2), slot_notification(3)]]).await;
+    let archive_dir = tempfile::tempdir().unwrap();
+    let (mut dispatcher, _) = MessageDispatcher::new(100, 1, archive_dir.path());
+    let connector = SolanaConnector::connect(&server.url, dispatcher.clone_tx(), dispatcher.fault_sender(), true).await.unwrap();
+    let first = receive(&mut dispatcher).await;
+    dispatcher.process_message(first).unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await; // Let the rest queue up unprocessed
+
+    tokio::time::timeout(Duration::from_secs(10), connector.shutdown()).await.expect("connector did not stop");
+    let drained = dispatcher.drain().await.unwrap();
+
+    assert_eq!(drained, 2);
+    let unsubscribed: Vec<String> = server.requests().iter().filter_map(|request| request["method"].as_str()).filter(|method| method.ends_with("Unsubscribe")).map(String::from).collect();
+    assert_eq!(unsubscribed.len(), SUBSCRIPTION_COUNT);
+    assert!(unsubscribed.contains(&"blockUnsubscribe".to_string()));
+    let messages = read_volume_messages(&finalized_volumes(archive_dir.path())[0]);
+    assert_eq!(methods(&messages), ["slotNotification", "slotNotification", "slotNotification"]);
+    assert!(dispatcher.clone_tx().try_send("late".to_string()).is_err(), "intake is closed");
+}
@@ -0,0 +1,47 @@
+mod support;
+
+use pastel_solana_archival_data_integration_api::data_archiver::MessageDispatcher;
+use pastel_solana_archival_data_integration_api::event_log;
+use pastel_solana_archival_data_integration_api::fault_supervisor::{Fault, FaultSource, FaultSupervisor};
+use pastel_solana_archival_data_integration_api::historical_backfiller::block_notification_message;
+use pastel_solana_archival_data_integration_api::solana_connector::SolanaConnector;
+use serde_json::Value;
+use support::{test_block, MockPubSubServer, PubSubStep};
+
+// The event log is process-wide, so everything that writes to it is exercised in this one test.
+#[tokio::test]
+async fn records_the_ingestion_lifecycle_as_json_lines() {
+    let log_dir = tempfile::tempdir().unwrap();
+    let log_path = log_dir.path().join("events.jsonl");
+    event_log::init(&log_path).unwrap();
+    assert!(event_log::init(&log_path).is_err(), "only one event log per process");
+
+    let server = MockPubSubServer::start(vec![vec![PubSubStep::AwaitSubscriptions(6)]]).await;
+    let archive_dir = tempfile::tempdir().unwrap();
+    let (mut dispatcher, _) = MessageDispatcher::new(100, 1, archive_dir.path());
+    let connector = SolanaConnector::connect(&server.url, dispatcher.clone_tx(), dispatcher.fault_sender(), true).await.unwrap();
+    for slot in [10, 11] {
+        dispatcher.process_message(block_notification_message(slot, test_block(slot))).unwrap();
+    }
+    connector.shutdown().await;
+    let mut block = test_block(15);
+    block["parentSlot"] = 14.into();
+    dispatcher.clone_tx().send(block_notification_message(15, block)).await.unwrap(); // Queued, so the gap is only seen while draining
+    assert_eq!(dispatcher.drain().await.unwrap(), 1);
+    FaultSupervisor::new(1).decide(&Fault::recoverable(FaultSource::Encoder, "write failed"));
+
+    let events: Vec<Value> = std::fs::read_to_string(&log_path).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    let names: Vec<&str> = events.iter().map(|event| event["event"].as_str().unwrap()).collect();
+    assert_eq!(names, [
+        "connected",
+        "subscription_acknowledged", "subscription_acknowledged", "subscription_acknowledged",
+        "subscription_acknowledged", "subscription_acknowledged", "subscription_acknowledged",
+        "volume_opened", "disconnected", "gap_detected", "volume_finalized", "fault",
+    ]);
+    assert!(events.iter().all(|event| event["timestamp"].is_string()));
+    assert_eq!(events[3]["method"], "blockSubscribe");
+    assert_eq!(events[8]["reason"], "shutdown");
+    assert_eq!((&events[9]["after_slot"], &events[9]["through_slot"]), (&Value::from(11), &Value::from(14)));
+    assert_eq!((&events[10]["first_slot"], &events[10]["last_slot"], &events[10]["message_count"]), (&Value::from(10), &Value::from(15), &Value::from(3)));
+    assert_eq!(events[11]["action"], "RotateVolume");
+}
@@ -0,0 +1,47 @@
+mod support;
+
+use chrono::{Duration, TimeZone, Utc};
+use pastel_solana_archival_data_integration_api::volume_extractor::{self, ExtractFormat, ExtractSelection};
+use serde_json::Value;
+use std::path::Path;
+use support::{finalized_volumes, slot_notification_message};
+
+/// Two one-minute buckets: slots 1-3 from 12:00 and slots 4-6 from 12:01.
+fn write_archive(dir: &Path) {
+    support::write_archive(dir, (1..=6).map(|slot| (if slot < 4 { 0 } else { 1 }, slot_notification_message(slot))));
+}
+
+fn extracted_slots(output: &[u8]) -> Vec<u64> {
+    String::from_utf8_lossy(output).lines().map(|line| serde_json::from_str::<Value>(line).unwrap()["params"]["result"]["slot"].as_u64().unwrap()).collect()
+}
+
+#[test]
+fn extracts_a_slot_range_across_volumes() {
+    let archive_dir = tempfile::tempdir().unwrap();
+    write_archive(archive_dir.path());
+    let mut output = Vec::new();
+
+    let extracted = volume_extractor::extract(archive_dir.path(), &ExtractSelection::Slots { first_slot: 3, last_slot: 4 }, ExtractFormat::Ndjson, &mut output).unwrap();
+
+    assert_eq!(extracted, 2);
+    assert_eq!(extracted_slots(&output), [3, 4]);
+}
+
+#[test]
+fn extracts_volumes_by_time_bucket_or_name() {
+    let archive_dir = tempfile::tempdir().unwrap();
+    write_archive(archive_dir.path());
+    let start = Utc.with_ymd_and_hms(2023, 9, 1, 12, 1, 30).unwrap();
+    let mut output = Vec::new();
+    volume_extractor::extract(archive_dir.path(), &ExtractSelection::Time { start, end: start + Duration::hours(1) }, ExtractFormat::Ndjson, &mut output).unwrap();
+    assert_eq!(extracted_slots(&output), [4, 5, 6]);
+
+    let first_volume = finalized_volumes(archive_dir.path())[0].file_name().unwrap().into();
+    let mut output = Vec::new();
+    volume_extractor::extract(archive_dir.path(), &ExtractSelection::Volume(first_volume), ExtractFormat::Pretty, &mut output).unwrap();
+    let messages: Vec<Value> = serde_json::from_slice(&output).unwrap();
+    assert_eq!(messages.len(), 3);
+    assert!(String::from_utf8(output).unwrap().contains("\n  \"params\""), "pretty-printed");
+
+    assert!(volume_extractor::extract(archive_dir.path(), &ExtractSelection::Volume("missing.zstd".into()), ExtractFormat::Ndjson, &mut Vec::new()).is_err());
+}
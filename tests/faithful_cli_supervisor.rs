@@ -0,0 +1,98 @@
+mod support;
+
+use pastel_solana_archival_data_integration_api::faithful_cli_supervisor::{FaithfulCliConfig, FaithfulCliSupervisor};
+use pastel_solana_archival_data_integration_api::old_faithful_files::{EpochFiles, LocalEpochFiles};
+use serde_json::json;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use support::MockJsonRpcServer;
+use tokio::sync::watch;
+
+fn epoch_files(data_dir: &Path) -> LocalEpochFiles {
+    let local = LocalEpochFiles::new(EpochFiles { epoch: 7, root_cid: "bafyreidtestrootcid".to_string() }, data_dir, None);
+    std::fs::create_dir_all(local.car_path.parent().unwrap()).unwrap();
+    for path in std::iter::once(&local.car_path).chain(local.index_paths.iter().map(|(_, path)| path)) {
+        std::fs::write(path, b"contents").unwrap();
+    }
+    local
+}
+
+/// A stand-in for faithful-cli that records each start in `starts` before running `body`.
+fn fake_faithful_cli(dir: &Path, body: &str) -> PathBuf {
+    let path = dir.join("faithful-cli");
+    std::fs::write(&path, format!("#!/bin/sh\necho started >> {}\n{}\n", dir.join("starts").display(), body)).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+fn start_count(dir: &Path) -> usize {
+    std::fs::read_to_string(dir.join("starts")).map(|starts| starts.lines().count()).unwrap_or(0)
+}
+
+fn test_config(binary_path: PathBuf, listen_address: &str) -> FaithfulCliConfig {
+    FaithfulCliConfig {
+        binary_path,
+        listen_address: listen_address.to_string(),
+        startup_timeout: Duration::from_secs(5),
+        max_restarts: 2,
+        restart_backoff: Duration::from_millis(10),
+        shutdown_grace_period: Duration::from_secs(5),
+        ..FaithfulCliConfig::default()
+    }
+}
+
+#[tokio::test]
+async fn missing_binary_is_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let supervisor = FaithfulCliSupervisor::new(test_config(dir.path().join("no-such-binary"), ":0"), epoch_files(dir.path()));
+    let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    let err = supervisor.supervise(shutdown_rx).await.unwrap_err();
+
+    assert!(err.to_string().contains("no-such-binary"), "{}", err);
+}
+
+#[tokio::test]
+async fn crashing_server_is_restarted_until_the_limit() {
+    let dir = tempfile::tempdir().unwrap();
+    let binary = fake_faithful_cli(dir.path(), "echo 'failed to open index' >&2\nexit 1");
+    let supervisor = FaithfulCliSupervisor::new(test_config(binary, ":1"), epoch_files(dir.path()));
+    let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    let result = tokio::time::timeout(Duration::from_secs(10), supervisor.supervise(shutdown_rx)).await.expect("supervisor did not give up");
+
+    assert!(result.is_err());
+    assert_eq!(start_count(dir.path()), 3, "the first start plus two restarts");
+}
+
+#[tokio::test]
+async fn healthy_server_is_stopped_with_sigterm_on_shutdown() {
+    let rpc = MockJsonRpcServer::start(|_, _| json!({"solana-core": "1.16.0"})).await;
+    let listen_address = rpc.url.trim_start_matches("http://").to_string();
+    let dir = tempfile::tempdir().unwrap();
+    let terminated = dir.path().join("terminated");
+    let binary = fake_faithful_cli(dir.path(), &format!("trap 'echo yes > {}; exit 0' TERM\nwhile true; do sleep 0.1; done", terminated.display()));
+    let supervisor = FaithfulCliSupervisor::new(test_config(binary, &listen_address), epoch_files(dir.path()));
+    let mut health = supervisor.health();
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    let stop_once_healthy = async {
+        tokio::time::timeout(Duration::from_secs(5), health.wait_for(|healthy| *healthy)).await.expect("server never became healthy").unwrap();
+        shutdown_tx.send(true).unwrap();
+    };
+    let (result, _) = tokio::time::timeout(Duration::from_secs(10), async { tokio::join!(supervisor.supervise(shutdown_rx), stop_once_healthy) }).await.expect("supervisor did not stop");
+
+    result.unwrap();
+    assert!(rpc.request_count("getVersion") >= 1);
+    assert_eq!(std::fs::read_to_string(terminated).unwrap().trim(), "yes");
+    assert_eq!(start_count(dir.path()), 1);
+}
+
+#[test]
+fn rpc_url_points_at_the_listen_address() {
+    let config = |listen_address: &str| FaithfulCliConfig { listen_address: listen_address.to_string(), ..FaithfulCliConfig::default() };
+    assert_eq!(config(":7999").rpc_url(), "http://127.0.0.1:7999");
+    assert_eq!(config("0.0.0.0:8899").rpc_url(), "http://127.0.0.1:8899");
+    assert_eq!(config("10.0.0.5:8899").rpc_url(), "http://10.0.0.5:8899");
+}
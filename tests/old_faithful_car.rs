@@ -0,0 +1,80 @@
+mod support;
+
+use pastel_solana_archival_data_integration_api::old_faithful_car::{self, CarReader, Cid, EpochBlocks, Kind, Node};
+use support::car::{build_car, checked_in_fixture, entry_hash, fixture_path, sample_epoch, SAMPLE_EPOCH, SAMPLE_FIRST_SLOT};
+
+const FIXTURE: &str = "epoch-3-sample.car";
+
+fn fixture() -> Vec<u8> {
+    checked_in_fixture(FIXTURE, &build_car(&sample_epoch()).bytes)
+}
+
+#[test]
+fn checked_in_fixture_matches_the_generator() {
+    assert_eq!(fixture(), build_car(&sample_epoch()).bytes, "regenerate with REGENERATE_FIXTURES=1");
+}
+
+#[test]
+fn reads_blocks_and_transactions_in_slot_order() {
+    fixture();
+    let blocks: Vec<_> = old_faithful_car::open_epoch_car(&fixture_path(FIXTURE)).unwrap().collect::<Result<_, _>>().unwrap();
+    let expected = sample_epoch();
+
+    assert_eq!(blocks.iter().map(|block| block.slot).collect::<Vec<_>>(), [SAMPLE_FIRST_SLOT, SAMPLE_FIRST_SLOT + 1, SAMPLE_FIRST_SLOT + 3]);
+    assert_eq!(blocks[2].parent_slot, SAMPLE_FIRST_SLOT + 1);
+    assert_eq!(blocks[0].block_time, 1_600_000_000);
+    assert_eq!(blocks[0].blockhash, Some(bs58::encode(entry_hash(SAMPLE_FIRST_SLOT, 1)).into_string()), "the blockhash is the last entry's hash");
+    assert_eq!(blocks[0].rewards.as_deref(), Some(&b"rewards"[..]));
+    assert!(blocks[1].transactions.is_empty());
+    assert_eq!(blocks[1].rewards, None);
+
+    let sample_transactions: Vec<_> = expected[0].entries.iter().flatten().collect();
+    assert_eq!(blocks[0].transactions.len(), 2);
+    for (transaction, sample) in blocks[0].transactions.iter().zip(&sample_transactions) {
+        assert_eq!(transaction.data, sample.serialized(), "data frames are reassembled");
+        assert_eq!(transaction.signatures().unwrap(), sample.signatures());
+        assert_eq!(transaction.decompressed_metadata().unwrap(), zstd::stream::decode_all(&sample.metadata[..]).unwrap());
+    }
+    assert_eq!(blocks[0].transactions[1].index, Some(1));
+    assert_eq!(blocks[2].transactions[0].signature().unwrap(), sample_epoch()[2].entries[0][0].signatures()[0]);
+}
+
+#[test]
+fn sections_carry_their_cids_and_offsets() {
+    let car = build_car(&sample_epoch());
+    let bytes = fixture();
+    let mut reader = CarReader::new(&bytes[..]).unwrap();
+    reader.set_verify_hashes(true);
+    assert_eq!(reader.roots(), [Cid(car.root.clone())]);
+
+    let mut last = None;
+    for section in &car.sections {
+        assert_eq!(reader.offset(), section.offset);
+        let (cid, node) = reader.next_node().unwrap().unwrap();
+        assert_eq!(cid.0, section.cid);
+        assert_eq!(node.kind() as u64, section.kind);
+        last = Some(node);
+    }
+    assert!(reader.next_node().unwrap().is_none());
+    match last {
+        Some(Node::Epoch(epoch)) => assert_eq!(epoch.epoch, SAMPLE_EPOCH),
+        other => panic!("expected the epoch node last, got {:?}", other.map(|node| node.kind())),
+    }
+    assert_eq!(car.sections.iter().filter(|section| section.kind == Kind::Block as u64).count(), 3);
+}
+
+#[test]
+fn truncated_or_corrupt_files_are_errors() {
+    let bytes = fixture();
+    let truncated: Result<Vec<_>, _> = EpochBlocks::new(CarReader::new(&bytes[..bytes.len() - 10]).unwrap()).collect();
+    assert!(truncated.is_err());
+
+    let car = build_car(&sample_epoch());
+    let mut corrupt = bytes.clone();
+    let last_byte = (car.sections[0].offset + car.sections[0].size - 1) as usize;
+    corrupt[last_byte] ^= 0xff;
+    let mut reader = CarReader::new(&corrupt[..]).unwrap();
+    reader.set_verify_hashes(true);
+    let err = reader.next_section().unwrap_err();
+    assert!(err.to_string().contains("does not match its CID"), "{}", err);
+}
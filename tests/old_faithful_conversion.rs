@@ -0,0 +1,65 @@
+mod support;
+
+use pastel_solana_archival_data_integration_api::data_archiver::manifest_path;
+use pastel_solana_archival_data_integration_api::old_faithful_converter::{self, OLD_FAITHFUL_SOURCE};
+use serde_json::{json, Value};
+use support::car::{sample_epoch_files, SAMPLE_EPOCH, SAMPLE_FIRST_SLOT};
+use support::{finalized_volumes, read_volume_messages, test_block, MockJsonRpcServer};
+
+/// Stands in for faithful-cli serving the sample epoch.
+async fn mock_faithful_cli() -> MockJsonRpcServer {
+    MockJsonRpcServer::start(|method, params| match method {
+        "getBlock" => {
+            let slot = params[0].as_u64().unwrap();
+            if [SAMPLE_FIRST_SLOT, SAMPLE_FIRST_SLOT + 1, SAMPLE_FIRST_SLOT + 3].contains(&slot) {
+                test_block(slot)
+            } else {
+                json!({"error": {"code": -32009, "message": "Slot was skipped"}})
+            }
+        }
+        _ => Value::Null,
+    })
+    .await
+}
+
+fn archived_slots(dir: &std::path::Path) -> Vec<u64> {
+    finalized_volumes(dir).iter().flat_map(|volume| read_volume_messages(volume)).map(|message| message["params"]["result"]["context"]["slot"].as_u64().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn converts_an_epoch_into_volumes_with_manifests() {
+    let server = mock_faithful_cli().await;
+    let archive_dir = tempfile::tempdir().unwrap();
+
+    let converted = old_faithful_converter::convert_epoch(&sample_epoch_files(), &server.url, archive_dir.path(), 60, 4).await.unwrap();
+
+    assert_eq!(converted, 3);
+    assert_eq!(server.request_count("getBlock"), 3, "skipped slots are never requested");
+    assert_eq!(archived_slots(archive_dir.path()), [SAMPLE_FIRST_SLOT, SAMPLE_FIRST_SLOT + 1, SAMPLE_FIRST_SLOT + 3]);
+    let volumes = finalized_volumes(archive_dir.path());
+    let names: Vec<String> = volumes.iter().map(|volume| volume.file_name().unwrap().to_string_lossy().to_string()).collect();
+    assert_eq!(names, [format!("solana_data_archive__epoch_{}_slots_{}_{}.zstd", SAMPLE_EPOCH, SAMPLE_FIRST_SLOT, SAMPLE_FIRST_SLOT + 3)], "named by epoch and slot range");
+    let messages = read_volume_messages(&volumes[0]);
+    assert_eq!(messages[0]["method"], "blockNotification");
+    assert_eq!(messages[0]["params"]["result"]["value"]["block"]["blockhash"], format!("blockhash{}", SAMPLE_FIRST_SLOT));
+
+    let manifest: Value = serde_json::from_str(&std::fs::read_to_string(manifest_path(&volumes[0])).unwrap()).unwrap();
+    assert_eq!(manifest["source"], OLD_FAITHFUL_SOURCE);
+    assert_eq!(manifest["epoch"], SAMPLE_EPOCH);
+    assert_eq!((manifest["first_slot"].as_u64(), manifest["last_slot"].as_u64()), (Some(SAMPLE_FIRST_SLOT), Some(SAMPLE_FIRST_SLOT + 3)));
+}
+
+#[tokio::test]
+async fn reconverting_resumes_after_the_last_finalized_volume() {
+    let server = mock_faithful_cli().await;
+    let archive_dir = tempfile::tempdir().unwrap();
+    let local = sample_epoch_files();
+    old_faithful_converter::convert_epoch(&local, &server.url, archive_dir.path(), 60, 4).await.unwrap();
+
+    let converted = old_faithful_converter::convert_epoch(&local, &server.url, archive_dir.path(), 60, 4).await.unwrap();
+
+    assert_eq!(converted, 0);
+    assert_eq!(server.request_count("getBlock"), 3);
+    assert_eq!(archived_slots(archive_dir.path()).len(), 3, "no block is archived twice");
+    assert_eq!(old_faithful_converter::converted_volumes(archive_dir.path(), SAMPLE_EPOCH).unwrap().len(), 1);
+}
@@ -0,0 +1,53 @@
+mod support;
+
+use pastel_solana_archival_data_integration_api::old_faithful_files::{self, IndexKind};
+use pastel_solana_archival_data_integration_api::resumable_downloader::DownloadOptions;
+use support::MockFileServer;
+
+const ROOT_CID: &str = "bafyreidtestrootcid";
+
+fn publish_epoch(server: &MockFileServer, epoch: u64, kinds: &[IndexKind]) {
+    server.add_file(&format!("/{}/epoch-{}.cid", epoch, epoch), format!("{}\n", ROOT_CID).into_bytes());
+    server.add_file(&format!("/{}/epoch-{}.car", epoch, epoch), b"car contents".to_vec());
+    for kind in kinds {
+        server.add_file(&format!("/{}/epoch-{}-{}-mainnet-{}.index", epoch, epoch, ROOT_CID, kind.name()), kind.name().as_bytes().to_vec());
+    }
+}
+
+#[tokio::test]
+async fn fetches_car_and_indexes_under_their_published_names() {
+    let server = MockFileServer::start(None).await;
+    publish_epoch(&server, 7, &IndexKind::ALL);
+    let data_dir = tempfile::tempdir().unwrap();
+
+    let local = old_faithful_files::fetch_epoch_files(&server.url, 7, data_dir.path(), None, &DownloadOptions::default()).await.unwrap();
+
+    assert_eq!(local.files.root_cid, ROOT_CID);
+    assert_eq!(local.car_path, data_dir.path().join("7/epoch-7.car"));
+    assert_eq!(std::fs::read(local.index_path(IndexKind::SlotToCid)).unwrap(), b"slot-to-cid");
+    assert!(local.index_path(IndexKind::CidToOffsetAndSize).ends_with(format!("epoch-7-{}-mainnet-cid-to-offset-and-size.index", ROOT_CID)));
+    local.validate().unwrap();
+}
+
+#[tokio::test]
+async fn existing_car_is_used_in_place() {
+    let server = MockFileServer::start(None).await;
+    publish_epoch(&server, 8, &IndexKind::ALL);
+    let data_dir = tempfile::tempdir().unwrap();
+    let car_source_dir = tempfile::tempdir().unwrap();
+    std::fs::write(car_source_dir.path().join("epoch-8.car"), b"local car").unwrap();
+
+    let local = old_faithful_files::fetch_epoch_files(&server.url, 8, data_dir.path(), Some(car_source_dir.path()), &DownloadOptions::default()).await.unwrap();
+
+    assert_eq!(local.car_path, car_source_dir.path().join("epoch-8.car"));
+    assert!(!server.requests().iter().any(|(_, path, _)| path == "/8/epoch-8.car"));
+}
+
+#[tokio::test]
+async fn missing_index_fails_before_anything_is_served() {
+    let server = MockFileServer::start(None).await;
+    publish_epoch(&server, 9, &[IndexKind::CidToOffsetAndSize, IndexKind::SlotToCid]);
+    let data_dir = tempfile::tempdir().unwrap();
+
+    assert!(old_faithful_files::fetch_epoch_files(&server.url, 9, data_dir.path(), None, &DownloadOptions::default()).await.is_err());
+}
@@ -0,0 +1,54 @@
+mod support;
+
+use pastel_solana_archival_data_integration_api::old_faithful_car;
+use pastel_solana_archival_data_integration_api::old_faithful_files::IndexKind;
+use pastel_solana_archival_data_integration_api::old_faithful_index::{EpochIndexes, IndexedEpoch};
+use support::car::{build_car, build_epoch_indexes, sample_epoch, sample_epoch_files, SAMPLE_FIRST_SLOT};
+
+#[test]
+fn checked_in_indexes_match_the_generator() {
+    let local = sample_epoch_files();
+    for (kind, contents) in build_epoch_indexes(&build_car(&sample_epoch())) {
+        let kind = IndexKind::ALL.into_iter().find(|candidate| candidate.name() == kind).unwrap();
+        assert_eq!(std::fs::read(local.index_path(kind)).unwrap(), contents, "regenerate with REGENERATE_FIXTURES=1");
+    }
+}
+
+#[test]
+fn extracts_blocks_by_slot_without_scanning() {
+    let local = sample_epoch_files();
+    let epoch = IndexedEpoch::open(&local).unwrap();
+    let streamed: Vec<_> = old_faithful_car::open_epoch_car(&local.car_path).unwrap().collect::<Result<_, _>>().unwrap();
+
+    for block in &streamed {
+        assert_eq!(epoch.block(block.slot).unwrap().as_ref(), Some(block));
+    }
+    assert_eq!(epoch.block(SAMPLE_FIRST_SLOT + 2).unwrap(), None, "skipped slot");
+    assert_eq!(epoch.block(SAMPLE_FIRST_SLOT + 100).unwrap(), None);
+}
+
+#[test]
+fn extracts_transactions_by_signature() {
+    let local = sample_epoch_files();
+    let epoch = IndexedEpoch::open(&local).unwrap();
+    let sample = sample_epoch();
+
+    let split = &sample[0].entries[0][1];
+    let transaction = epoch.transaction(&split.signatures()[0]).unwrap().expect("indexed transaction");
+    assert_eq!(transaction.slot, SAMPLE_FIRST_SLOT);
+    assert_eq!(transaction.data, split.serialized(), "data frames are fetched through the offset index");
+    assert_eq!(epoch.transaction(&sample[2].entries[0][0].signatures()[0]).unwrap().unwrap().slot, SAMPLE_FIRST_SLOT + 3);
+    assert_eq!(epoch.transaction(&bs58::encode([9u8; 64]).into_string()).unwrap(), None);
+    assert!(epoch.transaction("not base58!").is_err());
+}
+
+#[test]
+fn rejects_an_index_of_the_wrong_kind() {
+    let mut local = sample_epoch_files();
+    let slot_index = local.index_path(IndexKind::SlotToCid).to_path_buf();
+    local.index_paths.retain(|(kind, _)| *kind != IndexKind::SigToCid);
+    local.index_paths.push((IndexKind::SigToCid, slot_index));
+
+    let err = EpochIndexes::open(&local).err().expect("mismatched index accepted");
+    assert!(err.to_string().contains("slot-to-cid index, not sig-to-cid"), "{}", err);
+}
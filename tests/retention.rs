@@ -0,0 +1,95 @@
+mod support;
+
+use chrono::{Duration, Utc};
+use pastel_solana_archival_data_integration_api::data_archiver::{manifest_path, Clock};
+use pastel_solana_archival_data_integration_api::retention_manager::{EvictionAction, RetentionManager, RetentionPolicy};
+use pastel_solana_archival_data_integration_api::volume_catalog::{UploadProgress, VolumeCatalog};
+use serde_json::json;
+use std::path::Path;
+use support::{archive_clock, archive_writer, finalized_volumes, slot_notification_message};
+
+/// Writes one volume per (stream, slot), a minute apart, and returns their file names in order.
+fn write_volumes(dir: &Path, volumes: &[(&str, u64)]) -> Vec<String> {
+    let clock = archive_clock();
+    for (stream, slot) in volumes {
+        let mut manager = archive_writer(dir, 1, &clock);
+        manager.set_state_file_name(&format!("{}_volume_state.json", stream));
+        manager.set_manifest_details(json!({"source": stream}));
+        manager.process_message(slot_notification_message(*slot)).unwrap();
+        manager.finish().unwrap();
+        clock.advance(Duration::minutes(1));
+    }
+    finalized_volumes(dir).iter().map(|path| path.file_name().unwrap().to_string_lossy().to_string()).collect()
+}
+
+fn confirm_upload(catalog: &VolumeCatalog, file_name: &str) {
+    let progress = UploadProgress { file_id: Some("file".to_string()), task_id: Some("task".to_string()), txid: Some(format!("txid-{}", file_name)), ..UploadProgress::default() };
+    catalog.record_upload_progress(file_name, "confirmed", &progress).unwrap();
+}
+
+#[test]
+fn evicts_only_confirmed_and_intact_volumes_past_the_retention_window() {
+    let archive_dir = tempfile::tempdir().unwrap();
+    let names = write_volumes(archive_dir.path(), &[("live", 1), ("live", 2), ("live", 3), ("backfill", 4)]);
+    let catalog = VolumeCatalog::open_in(archive_dir.path()).unwrap();
+    for name in [&names[0], &names[1], &names[3]] {
+        confirm_upload(&catalog, name);
+    }
+    std::fs::write(archive_dir.path().join(&names[1]), b"tampered").unwrap();
+    let policy = RetentionPolicy { keep_recent: Some(Duration::hours(1)), keep_forever_streams: vec!["backfill".to_string()], ..RetentionPolicy::default() };
+    let manager = RetentionManager::new(policy, archive_dir.path(), VolumeCatalog::open_in(archive_dir.path()).unwrap());
+
+    let finalized = archive_clock().now(); // Volumes are stamped with the simulated clock's time
+    assert!(manager.enforce(finalized + Duration::minutes(30)).unwrap().evicted.is_empty(), "everything is within the window");
+    let report = manager.enforce(finalized + Duration::hours(2)).unwrap();
+
+    assert_eq!(report.evicted, [names[0].clone()]);
+    assert_eq!(report.checksum_mismatches, [names[1].clone()]);
+    assert!(!archive_dir.path().join(&names[0]).exists());
+    assert!(!manifest_path(&archive_dir.path().join(&names[0])).exists());
+    assert!(archive_dir.path().join(&names[2]).exists(), "not uploaded yet");
+    assert!(archive_dir.path().join(&names[3]).exists(), "backfill is kept forever");
+    assert_eq!(catalog.volume(&names[0]).unwrap().unwrap().deletion_state, "deleted");
+    assert_eq!(catalog.volume(&names[2]).unwrap().unwrap().deletion_state, "present");
+}
+
+#[test]
+fn moves_the_oldest_volumes_away_when_over_the_size_limit() {
+    let archive_dir = tempfile::tempdir().unwrap();
+    let cold_dir = tempfile::tempdir().unwrap();
+    let names = write_volumes(archive_dir.path(), &[("live", 1), ("live", 2), ("live", 3)]);
+    let catalog = VolumeCatalog::open_in(archive_dir.path()).unwrap();
+    for name in &names {
+        confirm_upload(&catalog, name);
+    }
+    let volume_size = std::fs::metadata(archive_dir.path().join(&names[0])).unwrap().len();
+    let policy = RetentionPolicy { max_local_bytes: Some(volume_size * 2), action: EvictionAction::MoveTo(cold_dir.path().to_path_buf()), ..RetentionPolicy::default() };
+    let manager = RetentionManager::new(policy, archive_dir.path(), VolumeCatalog::open_in(archive_dir.path()).unwrap());
+
+    let report = manager.enforce(Utc::now()).unwrap();
+
+    assert_eq!(report.evicted, [names[0].clone()]);
+    assert!(report.remaining_bytes <= volume_size * 2);
+    assert_eq!(finalized_volumes(cold_dir.path()), [cold_dir.path().join(&names[0])]);
+    assert!(manifest_path(&cold_dir.path().join(&names[0])).exists());
+    let moved = catalog.volume(&names[0]).unwrap().unwrap();
+    assert_eq!((moved.deletion_state.as_str(), moved.path), ("moved", cold_dir.path().join(&names[0])));
+}
+
+#[test]
+fn frees_space_on_request_even_without_a_policy() {
+    let archive_dir = tempfile::tempdir().unwrap();
+    let names = write_volumes(archive_dir.path(), &[("live", 1), ("live", 2), ("live", 3)]);
+    let catalog = VolumeCatalog::open_in(archive_dir.path()).unwrap();
+    for name in &names[..2] {
+        confirm_upload(&catalog, name);
+    }
+    let manager = RetentionManager::new(RetentionPolicy::default(), archive_dir.path(), VolumeCatalog::open_in(archive_dir.path()).unwrap());
+
+    assert!(manager.enforce(Utc::now()).unwrap().evicted.is_empty());
+    let report = manager.free_space(1, Utc::now()).unwrap();
+
+    assert_eq!(report.evicted, [names[0].clone()], "one volume frees enough");
+    let report = manager.free_space(u64::MAX, Utc::now()).unwrap();
+    assert_eq!(report.evicted, [names[1].clone()], "unconfirmed volumes are never evicted");
+}
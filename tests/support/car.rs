@@ -0,0 +1,277 @@
+// Builds small Old Faithful-style epoch CARs and their indexes. The checked-in fixtures under
+// tests/fixtures are generated from `sample_epoch`; set REGENERATE_FIXTURES=1 when running the CAR and
+// index tests to rewrite them.
+
+use ciborium::value::Value;
+use pastel_solana_archival_data_integration_api::old_faithful_car::Cid;
+use pastel_solana_archival_data_integration_api::old_faithful_files::{EpochFiles, IndexKind, LocalEpochFiles};
+use pastel_solana_archival_data_integration_api::old_faithful_index::{self, bucket_for, truncation_mask, COMPACT_INDEX_MAGIC};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+pub const SAMPLE_EPOCH: u64 = 3;
+pub const SAMPLE_FIRST_SLOT: u64 = SAMPLE_EPOCH * 432_000;
+
+pub struct SampleTransaction {
+    pub signature_bytes: Vec<u8>, // Each signature is 64 copies of one of these bytes
+    pub message: Vec<u8>,
+    pub metadata: Vec<u8>,
+    /// Splits the serialized transaction across this many data frames.
+    pub frames: usize,
+}
+
+impl SampleTransaction {
+    pub fn serialized(&self) -> Vec<u8> {
+        let mut data = vec![self.signature_bytes.len() as u8];
+        for byte in &self.signature_bytes {
+            data.extend([*byte; 64]);
+        }
+        data.extend(&self.message);
+        data
+    }
+
+    pub fn signatures(&self) -> Vec<String> {
+        self.signature_bytes.iter().map(|byte| bs58::encode([*byte; 64]).into_string()).collect()
+    }
+}
+
+pub struct SampleBlock {
+    pub slot: u64,
+    pub parent_slot: u64,
+    pub block_time: i64,
+    /// Transactions grouped by entry; an empty entry is a tick.
+    pub entries: Vec<Vec<SampleTransaction>>,
+    pub rewards: Option<Vec<u8>>,
+}
+
+/// Three blocks around a skipped slot: one with a multi-signature transaction split across data
+/// frames, one with only ticks, and one with a single transaction.
+pub fn sample_epoch() -> Vec<SampleBlock> {
+    let transaction = |signature_bytes: Vec<u8>, frames: usize| SampleTransaction {
+        message: format!("message signed by {:?}", signature_bytes).into_bytes(),
+        metadata: zstd::stream::encode_all(&format!("metadata for {:?}", signature_bytes).into_bytes()[..], 3).unwrap(),
+        signature_bytes,
+        frames,
+    };
+    vec![
+        SampleBlock { slot: SAMPLE_FIRST_SLOT, parent_slot: SAMPLE_FIRST_SLOT - 1, block_time: 1_600_000_000, entries: vec![vec![transaction(vec![1], 1), transaction(vec![2, 3], 3)], vec![]], rewards: Some(b"rewards".to_vec()) },
+        SampleBlock { slot: SAMPLE_FIRST_SLOT + 1, parent_slot: SAMPLE_FIRST_SLOT, block_time: 1_600_000_001, entries: vec![vec![], vec![]], rewards: None },
+        SampleBlock { slot: SAMPLE_FIRST_SLOT + 3, parent_slot: SAMPLE_FIRST_SLOT + 1, block_time: 1_600_000_002, entries: vec![vec![transaction(vec![4], 1)]], rewards: None },
+    ]
+}
+
+pub fn entry_hash(slot: u64, entry: usize) -> [u8; 32] {
+    Sha256::digest(format!("entry {} of slot {}", entry, slot)).into()
+}
+
+/// A section written to the CAR: its CID, the byte offset of its length prefix, and its total size.
+pub struct WrittenSection {
+    pub cid: Vec<u8>,
+    pub offset: u64,
+    pub size: u64,
+    pub kind: u64,
+    pub slot: Option<u64>,
+    /// The first signature, for transaction nodes.
+    pub signature: Option<Vec<u8>>,
+}
+
+pub struct SampleCar {
+    pub bytes: Vec<u8>,
+    pub root: Vec<u8>,
+    pub sections: Vec<WrittenSection>,
+}
+
+fn varint(mut value: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn int(value: impl Into<i128>) -> Value {
+    Value::Integer(value.into().try_into().unwrap())
+}
+
+fn link(cid: &[u8]) -> Value {
+    let mut bytes = vec![0];
+    bytes.extend(cid);
+    Value::Tag(42, Box::new(Value::Bytes(bytes)))
+}
+
+fn links(cids: &[Vec<u8>]) -> Value {
+    Value::Array(cids.iter().map(|cid| link(cid)).collect())
+}
+
+fn data_frame(index: usize, total: usize, data: &[u8], next: &[Vec<u8>]) -> Value {
+    let next = if next.is_empty() { Value::Null } else { links(next) };
+    Value::Array(vec![int(6), Value::Null, int(index as u64), int(total as u64), Value::Bytes(data.to_vec()), next])
+}
+
+struct Writer {
+    body: Vec<u8>,
+    sections: Vec<WrittenSection>,
+}
+
+impl Writer {
+    fn write(&mut self, node: Value, slot: Option<u64>) -> Vec<u8> {
+        let mut data = Vec::new();
+        ciborium::ser::into_writer(&node, &mut data).unwrap();
+        let mut cid = vec![0x01, 0x71, 0x12, 0x20];
+        cid.extend(Sha256::digest(&data));
+        let kind = node.as_array().unwrap()[0].as_integer().map(|kind| u64::try_from(kind).unwrap()).unwrap();
+        let mut section = varint((cid.len() + data.len()) as u64);
+        section.extend(&cid);
+        section.extend(&data);
+        self.sections.push(WrittenSection { cid: cid.clone(), offset: self.body.len() as u64, size: section.len() as u64, kind, slot, signature: None });
+        self.body.extend(section);
+        cid
+    }
+
+    /// Writes any continuation frames and returns the embedded first frame.
+    fn frames(&mut self, data: &[u8], count: usize, slot: u64) -> Value {
+        let chunk_size = data.len().div_ceil(count);
+        let chunks: Vec<&[u8]> = data.chunks(chunk_size).collect();
+        let next: Vec<Vec<u8>> = chunks.iter().enumerate().skip(1).map(|(index, chunk)| self.write(data_frame(index, chunks.len(), chunk, &[]), Some(slot))).collect();
+        data_frame(0, chunks.len(), chunks[0], &next)
+    }
+}
+
+pub fn build_car(blocks: &[SampleBlock]) -> SampleCar {
+    let mut writer = Writer { body: Vec::new(), sections: Vec::new() };
+    let mut block_cids = Vec::new();
+    for block in blocks {
+        let mut entry_cids = Vec::new();
+        let mut index = 0u64;
+        for (entry_index, entry) in block.entries.iter().enumerate() {
+            let mut transaction_cids = Vec::new();
+            for transaction in entry {
+                let data = writer.frames(&transaction.serialized(), transaction.frames, block.slot);
+                let metadata = data_frame(0, 1, &transaction.metadata, &[]);
+                transaction_cids.push(writer.write(Value::Array(vec![int(0), data, metadata, int(block.slot), int(index)]), Some(block.slot)));
+                writer.sections.last_mut().unwrap().signature = Some(vec![transaction.signature_bytes[0]; 64]);
+                index += 1;
+            }
+            let entry = Value::Array(vec![int(1), int(12_500u64), Value::Bytes(entry_hash(block.slot, entry_index).to_vec()), links(&transaction_cids)]);
+            entry_cids.push(writer.write(entry, Some(block.slot)));
+        }
+        let rewards = Value::Array(vec![int(5), int(block.slot), data_frame(0, 1, block.rewards.as_deref().unwrap_or_default(), &[])]);
+        let rewards_cid = writer.write(rewards, Some(block.slot));
+        let shredding = Value::Array(entry_cids.iter().map(|_| Value::Array(vec![int(-1i64), int(-1i64)])).collect());
+        let meta = Value::Array(vec![int(block.parent_slot), int(block.block_time), int(block.slot - 100)]);
+        block_cids.push(writer.write(Value::Array(vec![int(2), int(block.slot), shredding, links(&entry_cids), meta, link(&rewards_cid)]), Some(block.slot)));
+    }
+    let first = blocks.first().map(|block| block.slot).unwrap_or_default();
+    let last = blocks.last().map(|block| block.slot).unwrap_or_default();
+    let subset = writer.write(Value::Array(vec![int(3), int(first), int(last), links(&block_cids)]), None);
+    let root = writer.write(Value::Array(vec![int(4), int(SAMPLE_EPOCH), links(&[subset])]), None);
+
+    let header = Value::Map(vec![(Value::Text("roots".to_string()), links(std::slice::from_ref(&root))), (Value::Text("version".to_string()), int(1))]);
+    let mut header_bytes = Vec::new();
+    ciborium::ser::into_writer(&header, &mut header_bytes).unwrap();
+    let mut bytes = varint(header_bytes.len() as u64);
+    bytes.extend(header_bytes);
+    let header_length = bytes.len() as u64;
+    bytes.extend(writer.body);
+    let sections = writer.sections.into_iter().map(|section| WrittenSection { offset: section.offset + header_length, ..section }).collect();
+    SampleCar { bytes, root, sections }
+}
+
+const INDEX_HASH_LEN: u8 = 3;
+
+/// Builds a `compactindexsized` file holding `entries`, picking for each bucket the first hash domain
+/// under which its truncated hashes are unique.
+pub fn build_compact_index(kind: &str, value_size: u64, entries: &[(Vec<u8>, Vec<u8>)], num_buckets: u32) -> Vec<u8> {
+    let mut buckets: Vec<Vec<&(Vec<u8>, Vec<u8>)>> = vec![Vec::new(); num_buckets as usize];
+    for entry in entries {
+        assert_eq!(entry.1.len() as u64, value_size);
+        buckets[bucket_for(&entry.0, num_buckets) as usize].push(entry);
+    }
+    let metadata: Vec<(&str, Vec<u8>)> = vec![("kind", kind.as_bytes().to_vec()), ("epoch", SAMPLE_EPOCH.to_le_bytes().to_vec())];
+    let mut header = value_size.to_le_bytes().to_vec();
+    header.extend(num_buckets.to_le_bytes());
+    header.push(1); // Version
+    header.push(metadata.len() as u8);
+    for (key, value) in &metadata {
+        header.push(key.len() as u8);
+        header.extend(key.as_bytes());
+        header.push(value.len() as u8);
+        header.extend(value);
+    }
+    let mut bytes = COMPACT_INDEX_MAGIC.to_vec();
+    bytes.extend((header.len() as u32).to_le_bytes());
+    bytes.extend(header);
+    let mut data_offset = bytes.len() as u64 + num_buckets as u64 * 16;
+    let mut bucket_data: Vec<u8> = Vec::new();
+    for bucket in buckets {
+        let hashed = (0u32..)
+            .map(|domain| {
+                let mut hashed: Vec<(u64, &Vec<u8>)> = bucket.iter().map(|(key, value)| (old_faithful_index::entry_hash(domain, key) & truncation_mask(INDEX_HASH_LEN), value)).collect();
+                hashed.sort();
+                (domain, hashed)
+            })
+            .find(|(_, hashed)| hashed.windows(2).all(|pair| pair[0].0 != pair[1].0))
+            .unwrap();
+        let (domain, hashed) = hashed;
+        bytes.extend(domain.to_le_bytes());
+        bytes.extend((hashed.len() as u32).to_le_bytes());
+        bytes.extend([INDEX_HASH_LEN, 0]);
+        bytes.extend(&data_offset.to_le_bytes()[..6]);
+        for (hash, value) in hashed {
+            bucket_data.extend(&hash.to_le_bytes()[..INDEX_HASH_LEN as usize]);
+            bucket_data.extend(value);
+            data_offset += INDEX_HASH_LEN as u64 + value_size;
+        }
+    }
+    bytes.extend(bucket_data);
+    bytes
+}
+
+/// The three indexes Old Faithful publishes alongside `car`, as (kind, contents).
+pub fn build_epoch_indexes(car: &SampleCar) -> Vec<(&'static str, Vec<u8>)> {
+    let offsets: Vec<_> = car.sections.iter().map(|section| {
+        let mut value = section.offset.to_le_bytes()[..6].to_vec();
+        value.extend(&section.size.to_le_bytes()[..3]);
+        (section.cid.clone(), value)
+    }).collect();
+    let slots: Vec<_> = car.sections.iter().filter(|section| section.kind == 2).map(|section| (section.slot.unwrap().to_le_bytes().to_vec(), section.cid.clone())).collect();
+    let signatures: Vec<_> = car.sections.iter().filter_map(|section| Some((section.signature.clone()?, section.cid.clone()))).collect();
+    vec![
+        ("cid-to-offset-and-size", build_compact_index("cid-to-offset-and-size", 9, &offsets, 3)),
+        ("slot-to-cid", build_compact_index("slot-to-cid", 36, &slots, 2)),
+        ("sig-to-cid", build_compact_index("sig-to-cid", 36, &signatures, 2)),
+    ]
+}
+
+pub fn fixture_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+/// Reads a checked-in fixture, first rewriting it with `contents` if REGENERATE_FIXTURES is set.
+pub fn checked_in_fixture(name: &str, contents: &[u8]) -> Vec<u8> {
+    let path = fixture_path(name);
+    if std::env::var("REGENERATE_FIXTURES").is_ok() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+    }
+    std::fs::read(&path).unwrap_or_else(|err| panic!("Missing fixture {} ({}); run with REGENERATE_FIXTURES=1", path.display(), err))
+}
+
+/// The sample epoch's CAR and indexes as checked in, laid out as `fetch_epoch_files` would return them.
+pub fn sample_epoch_files() -> LocalEpochFiles {
+    let car = build_car(&sample_epoch());
+    checked_in_fixture("epoch-3-sample.car", &car.bytes);
+    for (kind, contents) in build_epoch_indexes(&car) {
+        checked_in_fixture(&format!("epoch-3-sample-{}.index", kind), &contents);
+    }
+    LocalEpochFiles {
+        files: EpochFiles { epoch: SAMPLE_EPOCH, root_cid: Cid(car.root).to_string() },
+        car_path: fixture_path("epoch-3-sample.car"),
+        index_paths: IndexKind::ALL.iter().map(|kind| (*kind, fixture_path(&format!("epoch-3-sample-{}.index", kind.name())))).collect(),
+    }
+}
@@ -0,0 +1,451 @@
+// In-process stand-ins for the Solana PubSub WebSocket API and the JSON-RPC HTTP API, so integration
+// tests can drive the connector, dispatcher and encoder end to end without touching the network.
+#![allow(dead_code)] // Each integration test binary only uses part of the support module
+
+pub mod car;
+
+use chrono::{TimeZone, Utc};
+use futures_util::{SinkExt, StreamExt};
+use pastel_solana_archival_data_integration_api::data_archiver::{Clock, EncoderManager, SimulatedClock};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio_tungstenite::tungstenite::Message;
+
+/// One step of the script a mock PubSub connection plays back.
+pub enum PubSubStep {
+    /// Waits until the client has sent at least this many subscription requests on the connection.
+    AwaitSubscriptions(usize),
+    /// Emits a notification for the subscription created by `subscribe_method` (e.g. "slotSubscribe").
+    Notify { subscribe_method: &'static str, result: Value },
+    Sleep(Duration),
+    /// Drops the TCP connection without a close handshake, as a flaky upstream would.
+    DropConnection,
+}
+
+/// A local WebSocket server speaking the Solana PubSub protocol. The n-th accepted connection plays
+/// back `scripts[n]`; connections beyond the supplied scripts are acknowledged but otherwise idle.
+pub struct MockPubSubServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Value>>>,
+    connections: Arc<Mutex<usize>>,
+}
+
+impl MockPubSubServer {
+    pub async fn start(scripts: Vec<Vec<PubSubStep>>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let connections = Arc::new(Mutex::new(0));
+        let server_requests = requests.clone();
+        let server_connections = connections.clone();
+        tokio::spawn(async move {
+            let mut scripts = scripts.into_iter();
+            while let Ok((stream, _)) = listener.accept().await {
+                *server_connections.lock().unwrap() += 1;
+                let script = scripts.next().unwrap_or_default();
+                tokio::spawn(serve_pubsub_connection(stream, script, server_requests.clone()));
+            }
+        });
+        Self { url, requests, connections }
+    }
+
+    /// Every JSON-RPC request received so far, across all connections, in arrival order.
+    pub fn requests(&self) -> Vec<Value> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub fn connection_count(&self) -> usize {
+        *self.connections.lock().unwrap()
+    }
+}
+
+enum Outgoing {
+    Message(Message),
+    Drop,
+}
+
+pub fn notification_method(subscribe_method: &str) -> String {
+    format!("{}Notification", subscribe_method.trim_end_matches("Subscribe"))
+}
+
+async fn serve_pubsub_connection(stream: TcpStream, script: Vec<PubSubStep>, requests: Arc<Mutex<Vec<Value>>>) {
+    let ws_stream = match tokio_tungstenite::accept_async(stream).await {
+        Ok(ws_stream) => ws_stream,
+        Err(_) => return,
+    };
+    let (mut write, mut read) = ws_stream.split();
+    let (outgoing_tx, mut outgoing_rx) = mpsc::unbounded_channel::<Outgoing>();
+    let subscriptions: Arc<Mutex<HashMap<String, u64>>> = Arc::new(Mutex::new(HashMap::new()));
+    let (subscription_count_tx, mut subscription_count_rx) = watch::channel(0usize);
+    let reader = tokio::spawn({
+        let outgoing_tx = outgoing_tx.clone();
+        let subscriptions = subscriptions.clone();
+        async move {
+            let mut next_subscription_id = 100;
+            while let Some(Ok(Message::Text(text))) = read.next().await {
+                let Ok(request) = serde_json::from_str::<Value>(&text) else { continue };
+                requests.lock().unwrap().push(request.clone());
+                let method = request["method"].as_str().unwrap_or_default().to_string();
+                if method.ends_with("Unsubscribe") {
+                    let ack = json!({"jsonrpc": "2.0", "result": true, "id": request["id"]});
+                    let _ = outgoing_tx.send(Outgoing::Message(Message::Text(ack.to_string())));
+                    continue;
+                }
+                next_subscription_id += 1;
+                let ack = json!({"jsonrpc": "2.0", "result": next_subscription_id, "id": request["id"]});
+                let _ = outgoing_tx.send(Outgoing::Message(Message::Text(ack.to_string()))); // Queue the ack before any scripted notification can follow it
+                let mut subscriptions = subscriptions.lock().unwrap();
+                subscriptions.insert(method, next_subscription_id);
+                subscription_count_tx.send_replace(subscriptions.len());
+            }
+        }
+    });
+    tokio::spawn(async move {
+        for step in script {
+            match step {
+                PubSubStep::AwaitSubscriptions(count) => {
+                    let _ = subscription_count_rx.wait_for(|subscribed| *subscribed >= count).await;
+                }
+                PubSubStep::Notify { subscribe_method, result } => {
+                    let subscription = subscriptions.lock().unwrap().get(subscribe_method).copied().unwrap_or(0);
+                    let notification = json!({
+                        "jsonrpc": "2.0",
+                        "method": notification_method(subscribe_method),
+                        "params": {"result": result, "subscription": subscription}
+                    });
+                    let _ = outgoing_tx.send(Outgoing::Message(Message::Text(notification.to_string())));
+                }
+                PubSubStep::Sleep(duration) => tokio::time::sleep(duration).await,
+                PubSubStep::DropConnection => {
+                    let _ = outgoing_tx.send(Outgoing::Drop);
+                    return;
+                }
+            }
+        }
+    });
+    while let Some(outgoing) = outgoing_rx.recv().await {
+        match outgoing {
+            Outgoing::Message(message) => {
+                if write.send(message).await.is_err() {
+                    break;
+                }
+            }
+            Outgoing::Drop => break,
+        }
+    }
+    reader.abort(); // Dropping both halves closes the socket without a close frame
+}
+
+pub fn slot_notification(slot: u64) -> PubSubStep {
+    PubSubStep::Notify { subscribe_method: "slotSubscribe", result: json!({"parent": slot - 1, "root": slot.saturating_sub(32), "slot": slot}) }
+}
+
+pub fn block_notification(slot: u64) -> PubSubStep {
+    PubSubStep::Notify { subscribe_method: "blockSubscribe", result: json!({"context": {"slot": slot}, "value": {"slot": slot, "block": test_block(slot), "err": null}}) }
+}
+
+pub fn logs_notification(slot: u64, signature: &str) -> PubSubStep {
+    PubSubStep::Notify { subscribe_method: "logsSubscribe", result: json!({"context": {"slot": slot}, "value": {"signature": signature, "err": null, "logs": ["Program 11111111111111111111111111111111 invoke [1]", "Program 11111111111111111111111111111111 success"]}}) }
+}
+
+/// A minimal `getBlock`-shaped block whose block time advances 30 seconds per slot.
+pub fn test_block(slot: u64) -> Value {
+    json!({
+        "blockHeight": slot,
+        "blockTime": 1_700_000_000 + slot as i64 * 30,
+        "blockhash": format!("blockhash{}", slot),
+        "parentSlot": slot - 1,
+        "previousBlockhash": format!("blockhash{}", slot - 1),
+        "rewards": [],
+        "transactions": []
+    })
+}
+
+type RpcHandler = dyn Fn(&str, &Value) -> Value + Send + Sync;
+
+/// A local HTTP server answering Solana JSON-RPC requests. The handler receives the method and params
+/// and returns the `result`; returning a value with an "error" key sends it as a JSON-RPC error instead.
+pub struct MockJsonRpcServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Value>>>,
+}
+
+impl MockJsonRpcServer {
+    pub async fn start(handler: impl Fn(&str, &Value) -> Value + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler: Arc<RpcHandler> = Arc::new(handler);
+        let server_requests = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_json_rpc_connection(stream, handler.clone(), server_requests.clone()));
+            }
+        });
+        Self { url, requests }
+    }
+
+    pub fn requests(&self) -> Vec<Value> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub fn request_count(&self, method: &str) -> usize {
+        self.requests().iter().filter(|request| request["method"] == method).count()
+    }
+}
+
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(header, _)| header.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+}
+
+/// Reads one HTTP/1.1 request.
+pub async fn read_http_request(stream: &mut TcpStream) -> Option<HttpRequest> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 8192];
+    let header_end = loop {
+        if let Some(position) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+    let headers: Vec<(String, String)> = lines.filter_map(|line| line.split_once(':')).map(|(name, value)| (name.trim().to_string(), value.trim().to_string())).collect();
+    let content_length = headers.iter().find(|(name, _)| name.eq_ignore_ascii_case("content-length")).and_then(|(_, value)| value.parse::<usize>().ok()).unwrap_or(0);
+    while buffer.len() < header_end + content_length {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    Some(HttpRequest { method, path, headers, body: buffer[header_end..header_end + content_length].to_vec() })
+}
+
+pub async fn write_http_response(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) {
+    let head = format!("HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, content_type, body.len());
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(body).await;
+    let _ = stream.shutdown().await;
+}
+
+async fn serve_json_rpc_connection(mut stream: TcpStream, handler: Arc<RpcHandler>, requests: Arc<Mutex<Vec<Value>>>) {
+    let Some(http_request) = read_http_request(&mut stream).await else { return };
+    let request: Value = serde_json::from_slice(&http_request.body).unwrap_or(Value::Null);
+    requests.lock().unwrap().push(request.clone());
+    let result = handler(request["method"].as_str().unwrap_or_default(), &request["params"]);
+    let response = match result.get("error") {
+        Some(error) => json!({"jsonrpc": "2.0", "error": error, "id": request["id"]}),
+        None => json!({"jsonrpc": "2.0", "result": result, "id": request["id"]}),
+    };
+    write_http_response(&mut stream, "200 OK", "application/json", response.to_string().as_bytes()).await;
+}
+
+/// A static file server supporting HEAD and byte-range GET requests, standing in for the Old Faithful
+/// file host. `cut_off_first_download_after` makes the first GET drop the connection after that many
+/// bytes, to exercise resuming.
+pub struct MockFileServer {
+    pub url: String,
+    files: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+    requests: Arc<Mutex<Vec<FileRequest>>>,
+}
+
+/// (method, path, Range header) of a request to the mock file server.
+pub type FileRequest = (String, String, Option<String>);
+
+impl MockFileServer {
+    pub async fn start(cut_off_first_download_after: Option<usize>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let files: Arc<Mutex<HashMap<String, Vec<u8>>>> = Arc::new(Mutex::new(HashMap::new()));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let cut_off = Arc::new(Mutex::new(cut_off_first_download_after));
+        let (server_files, server_requests) = (files.clone(), requests.clone());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let (files, requests, cut_off) = (server_files.clone(), server_requests.clone(), cut_off.clone());
+                tokio::spawn(async move {
+                    let Some(request) = read_http_request(&mut stream).await else { return };
+                    let range = request.header("range").map(str::to_string);
+                    requests.lock().unwrap().push((request.method.clone(), request.path.clone(), range.clone()));
+                    let Some(contents) = files.lock().unwrap().get(&request.path).cloned() else {
+                        write_http_response(&mut stream, "404 Not Found", "text/plain", b"not found").await;
+                        return;
+                    };
+                    let (status, start, end) = match range.as_deref().and_then(|range| range.strip_prefix("bytes=")).and_then(|range| range.split_once('-')) {
+                        Some((start, end)) => {
+                            let start: usize = start.parse().unwrap();
+                            let end: usize = if end.is_empty() { contents.len() - 1 } else { end.parse::<usize>().unwrap().min(contents.len() - 1) };
+                            ("206 Partial Content", start, end)
+                        }
+                        None => ("200 OK", 0, contents.len() - 1),
+                    };
+                    let body = &contents[start..=end];
+                    let head = format!("HTTP/1.1 {}\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\nConnection: close\r\n\r\n", status, body.len());
+                    let _ = stream.write_all(head.as_bytes()).await;
+                    if request.method == "HEAD" {
+                        return;
+                    }
+                    let cut_off_after = if request.method == "GET" { cut_off.lock().unwrap().take() } else { None };
+                    match cut_off_after {
+                        Some(limit) => {
+                            let _ = stream.write_all(&body[..limit.min(body.len())]).await;
+                            let _ = stream.flush().await; // Then drop the connection mid-body
+                        }
+                        None => {
+                            let _ = stream.write_all(body).await;
+                            let _ = stream.shutdown().await;
+                        }
+                    }
+                });
+            }
+        });
+        Self { url, files, requests }
+    }
+
+    pub fn add_file(&self, path: &str, contents: Vec<u8>) {
+        self.files.lock().unwrap().insert(path.to_string(), contents);
+    }
+
+    pub fn requests(&self) -> Vec<FileRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Paths of the finalized (non-temporary) volumes in `dir`, sorted by name.
+pub fn finalized_volumes(dir: &Path) -> Vec<PathBuf> {
+    let mut volumes: Vec<PathBuf> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.to_string_lossy().ends_with(".zstd") && !path.to_string_lossy().ends_with(".temp.zstd"))
+        .collect();
+    volumes.sort();
+    volumes
+}
+
+/// Decompresses a volume and splits it back into the JSON messages it was built from.
+pub fn read_volume_messages(path: &Path) -> Vec<Value> {
+    let mut contents = Vec::new();
+    zstd::stream::read::Decoder::new(std::fs::File::open(path).unwrap()).unwrap().read_to_end(&mut contents).unwrap();
+    serde_json::Deserializer::from_slice(&contents).into_iter::<Value>().map(|message| message.unwrap()).collect()
+}
+
+/// The simulated clock test archives are written on, starting at 2023-09-01 12:00 UTC. Clones share its time.
+pub fn archive_clock() -> SimulatedClock {
+    SimulatedClock::new(Utc.with_ymd_and_hms(2023, 9, 1, 12, 0, 0).unwrap())
+}
+
+/// An encoder writing into `dir` in buckets of `minutes_per_bucket`, on a clock the test drives.
+pub fn archive_writer(dir: &Path, minutes_per_bucket: i64, clock: &SimulatedClock) -> EncoderManager {
+    EncoderManager::with_clock(minutes_per_bucket, dir, Box::new(clock.clone())).unwrap()
+}
+
+/// Archives `messages` into one-minute volumes in `dir`. Each message comes with the minute after
+/// `archive_clock()`'s start it was received in; the minutes must not go backwards.
+pub fn write_archive(dir: &Path, messages: impl IntoIterator<Item = (i64, String)>) {
+    let clock = archive_clock();
+    let start = clock.now();
+    let mut manager = archive_writer(dir, 1, &clock);
+    for (minute, message) in messages {
+        clock.set(start + chrono::Duration::minutes(minute));
+        manager.process_message(message).unwrap();
+    }
+    manager.finish().unwrap();
+}
+
+/// A `slotNotification` message as the connector archives it.
+pub fn slot_notification_message(slot: u64) -> String {
+    json!({"jsonrpc": "2.0", "method": "slotNotification", "params": {"result": {"parent": slot.saturating_sub(1), "root": slot.saturating_sub(1), "slot": slot}, "subscription": 0}}).to_string()
+}
+
+/// (file name, multipart body) of a file stored on the mock Cascade server.
+pub type CascadeUpload = (String, Vec<u8>);
+
+/// A local stand-in for a Pastel walletnode's Cascade OpenAPI. Stored files get ids `file-<n>` and
+/// registrations get task ids `task-<n>`. A task reports "Task Completed" with txid `txid-<task id>` once
+/// its history has been polled `polls_before_confirmation` times. The first `rejected_registrations`
+/// tasks report "Task Rejected" instead.
+pub struct MockCascadeServer {
+    pub url: String,
+    uploads: Arc<Mutex<Vec<CascadeUpload>>>,
+    registrations: Arc<Mutex<Vec<(String, HttpRequest)>>>,
+}
+
+impl MockCascadeServer {
+    pub async fn start(polls_before_confirmation: usize, rejected_registrations: usize) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let uploads: Arc<Mutex<Vec<CascadeUpload>>> = Arc::new(Mutex::new(Vec::new()));
+        let registrations: Arc<Mutex<Vec<(String, HttpRequest)>>> = Arc::new(Mutex::new(Vec::new()));
+        let polls: Arc<Mutex<HashMap<String, usize>>> = Arc::new(Mutex::new(HashMap::new()));
+        let (server_uploads, server_registrations) = (uploads.clone(), registrations.clone());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let (uploads, registrations, polls) = (server_uploads.clone(), server_registrations.clone(), polls.clone());
+                tokio::spawn(async move {
+                    let Some(request) = read_http_request(&mut stream).await else { return };
+                    let response = if request.path == "/openapi/cascade/upload" {
+                        let body = String::from_utf8_lossy(&request.body).to_string();
+                        let file_name = body.split("filename=\"").nth(1).and_then(|rest| rest.split('"').next()).unwrap_or_default().to_string();
+                        let mut uploads = uploads.lock().unwrap();
+                        uploads.push((file_name, request.body.clone()));
+                        json!({"file_id": format!("file-{}", uploads.len())})
+                    } else if let Some(file_id) = request.path.strip_prefix("/openapi/cascade/start/") {
+                        let mut registrations = registrations.lock().unwrap();
+                        registrations.push((file_id.to_string(), request));
+                        json!({"task_id": format!("task-{}", registrations.len())})
+                    } else if let Some(task_id) = request.path.strip_prefix("/openapi/cascade/").and_then(|rest| rest.strip_suffix("/history")) {
+                        let task_number: usize = task_id.trim_start_matches("task-").parse().unwrap_or(0);
+                        let mut polls = polls.lock().unwrap();
+                        let count = polls.entry(task_id.to_string()).or_insert(0);
+                        *count += 1;
+                        if task_number <= rejected_registrations {
+                            json!([{"status": "Task Started"}, {"status": "Task Rejected"}])
+                        } else if *count > polls_before_confirmation {
+                            json!([{"status": "Task Started"}, {"status": "Task Completed", "txid": format!("txid-{}", task_id)}])
+                        } else {
+                            json!([{"status": "Task Started"}])
+                        }
+                    } else {
+                        write_http_response(&mut stream, "404 Not Found", "text/plain", b"not found").await;
+                        return;
+                    };
+                    write_http_response(&mut stream, "200 OK", "application/json", response.to_string().as_bytes()).await;
+                });
+            }
+        });
+        Self { url, uploads, registrations }
+    }
+
+    pub fn uploads(&self) -> Vec<CascadeUpload> {
+        self.uploads.lock().unwrap().clone()
+    }
+
+    /// (file id, request body) of every registration started.
+    pub fn registrations(&self) -> Vec<(String, Value)> {
+        self.registrations.lock().unwrap().iter().map(|(file_id, request)| (file_id.clone(), serde_json::from_slice(&request.body).unwrap_or(Value::Null))).collect()
+    }
+}